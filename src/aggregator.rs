@@ -1,4 +1,4 @@
-use crate::keycode::{EventType, KeyCode, KeyEvent};
+use crate::keycode::{DeviceId, EventType, KeyCode, KeyEvent};
 use chrono::{DateTime, Utc};
 use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
@@ -6,9 +6,66 @@ use std::time::{Duration, Instant};
 const WPM_MIN_KEYSTROKES: usize = 10;
 const WPM_MIN_WINDOW: Duration = Duration::from_secs(3);
 
+/// Counts keyed by the device they came from, `None` for unknown devices.
+pub type DeviceCounts<K> = HashMap<(Option<DeviceId>, K), u64>;
+
+/// Lower bounds in ms of the dwell and flight histogram buckets.
+pub const TIMING_BUCKETS_MS: [u32; 15] = [
+    0, 25, 50, 75, 100, 125, 150, 200, 250, 300, 400, 500, 750, 1000, 1500,
+];
+
+/// Holds and gaps longer than this are pauses or deliberate holds, not typing.
+pub const TIMING_MAX: Duration = Duration::from_millis(2000);
+
+/// Dwell histogram counts keyed by key and bucket.
+pub type DwellCounts = DeviceCounts<(KeyCode, u32)>;
+
+/// Flight histogram counts keyed by (from, to) transition and bucket.
+pub type FlightCounts = DeviceCounts<(KeyCode, KeyCode, u32)>;
+
+/// Consecutive typing key pairs and triples.
+pub type BigramCounts = DeviceCounts<(KeyCode, KeyCode)>;
+pub type TrigramCounts = DeviceCounts<(KeyCode, KeyCode, KeyCode)>;
+
+/// The bucket a duration falls in, as its lower bound.
+pub fn timing_bucket(duration: Duration) -> u32 {
+    let ms = duration.as_millis().min(u32::MAX as u128) as u32;
+    TIMING_BUCKETS_MS
+        .iter()
+        .rev()
+        .copied()
+        .find(|&lower| ms >= lower)
+        .unwrap_or(0)
+}
+
+/// Everything accumulated since the last flush.
+#[derive(Default)]
+pub struct FlushBatch {
+    pub key_counts: DeviceCounts<KeyCode>,
+    pub shortcut_counts: DeviceCounts<String>,
+    pub wpm_samples: Vec<WpmSample>,
+    pub dwell_counts: DwellCounts,
+    pub flight_counts: FlightCounts,
+    pub bigram_counts: BigramCounts,
+    pub trigram_counts: TrigramCounts,
+}
+
+impl FlushBatch {
+    pub fn is_empty(&self) -> bool {
+        self.key_counts.is_empty()
+            && self.shortcut_counts.is_empty()
+            && self.wpm_samples.is_empty()
+            && self.dwell_counts.is_empty()
+            && self.flight_counts.is_empty()
+            && self.bigram_counts.is_empty()
+            && self.trigram_counts.is_empty()
+    }
+}
+
 pub struct ActiveSession {
     pub db_id: i64,
     pub keystroke_count: u64,
+    pub device_counts: HashMap<Option<DeviceId>, u64>,
     pub last_event_at: Instant,
 }
 
@@ -18,6 +75,9 @@ pub struct WpmSample {
     pub session_id: i64,
     pub wpm: f64,
     pub keystrokes_in_window: u32,
+    /// device of the most recent keystroke in the window. A window typed on
+    /// two keyboards counts toward that one alone
+    pub device: Option<DeviceId>,
 }
 
 pub struct WpmTracker {
@@ -29,6 +89,7 @@ pub struct WpmTracker {
     samples: Vec<WpmSample>,
     last_sample_at: Option<Instant>,
     last_event_utc: Option<DateTime<Utc>>,
+    last_device: Option<DeviceId>,
     had_keystrokes_since_sample: bool,
     wpm_window: Duration,
     wpm_sample_interval: Duration,
@@ -45,6 +106,7 @@ impl WpmTracker {
             samples: Vec::new(),
             last_sample_at: None,
             last_event_utc: None,
+            last_device: None,
             had_keystrokes_since_sample: false,
             wpm_window,
             wpm_sample_interval,
@@ -59,6 +121,7 @@ impl WpmTracker {
         self.peak_wpm = 0.0;
         self.last_sample_at = None;
         self.last_event_utc = None;
+        self.last_device = None;
         self.had_keystrokes_since_sample = false;
     }
 
@@ -67,9 +130,16 @@ impl WpmTracker {
         self.session_start = Some(Instant::now());
     }
 
-    pub fn record_keystroke(&mut self, timestamp: Instant, utc_time: DateTime<Utc>, session_id: i64) {
+    pub fn record_keystroke(
+        &mut self,
+        timestamp: Instant,
+        utc_time: DateTime<Utc>,
+        session_id: i64,
+        device: Option<DeviceId>,
+    ) {
         self.recent_timestamps.push_back(timestamp);
         self.last_event_utc = Some(utc_time);
+        self.last_device = device;
         self.session_keystrokes += 1;
         self.had_keystrokes_since_sample = true;
 
@@ -136,6 +206,7 @@ impl WpmTracker {
             session_id,
             wpm: self.current_wpm,
             keystrokes_in_window: self.recent_timestamps.len() as u32,
+            device: self.last_device.clone(),
         });
 
         self.last_sample_at = Some(now);
@@ -171,46 +242,279 @@ impl Default for WpmTracker {
     }
 }
 
+struct HeldKey {
+    pressed_at: Instant,
+    repeated: bool,
+}
+
+struct LastTyped {
+    device: Option<DeviceId>,
+    key: KeyCode,
+    pressed_at: Instant,
+    released_at: Option<Instant>,
+}
+
+/// Tracks dwell (press to release) and flight (release to next press) times.
+///
+/// Flight only covers consecutive typing keys on the same keyboard; shortcuts
+/// and navigation keys break the chain. Pressing the next key before the
+/// previous one is released (rollover) is recorded as zero flight.
+#[derive(Default)]
+pub struct TimingTracker {
+    held: HashMap<(Option<DeviceId>, KeyCode), HeldKey>,
+    last_typed: Option<LastTyped>,
+    dwell_counts: DwellCounts,
+    flight_counts: FlightCounts,
+}
+
+impl TimingTracker {
+    pub fn record_press(&mut self, event: &KeyEvent) {
+        let key = event.key_code;
+        self.held.insert(
+            (event.device.clone(), key),
+            HeldKey {
+                pressed_at: event.timestamp,
+                repeated: false,
+            },
+        );
+
+        // shift is needed for capitals and doesn't break a typing run
+        if key.is_modifier() {
+            return;
+        }
+
+        if !key.is_typing_key() || event.modifiers.has_command_modifier() {
+            self.last_typed = None;
+            return;
+        }
+
+        if let Some(prev) = self.last_typed.take() {
+            if prev.device == event.device {
+                let flight = match prev.released_at {
+                    Some(released_at) => Some(event.timestamp.saturating_duration_since(released_at)),
+                    // still held: rollover, unless the release was missed
+                    None if event.timestamp.saturating_duration_since(prev.pressed_at)
+                        <= TIMING_MAX =>
+                    {
+                        Some(Duration::ZERO)
+                    }
+                    None => None,
+                };
+
+                if let Some(flight) = flight.filter(|&f| f <= TIMING_MAX) {
+                    let bucket = timing_bucket(flight);
+                    *self
+                        .flight_counts
+                        .entry((event.device.clone(), (prev.key, key, bucket)))
+                        .or_insert(0) += 1;
+                }
+            }
+        }
+
+        self.last_typed = Some(LastTyped {
+            device: event.device.clone(),
+            key,
+            pressed_at: event.timestamp,
+            released_at: None,
+        });
+    }
+
+    pub fn record_repeat(&mut self, event: &KeyEvent) {
+        if let Some(held) = self.held.get_mut(&(event.device.clone(), event.key_code)) {
+            held.repeated = true;
+        }
+    }
+
+    pub fn record_release(&mut self, event: &KeyEvent) {
+        let key = event.key_code;
+        let Some(held) = self.held.remove(&(event.device.clone(), key)) else {
+            return;
+        };
+
+        if let Some(last) = &mut self.last_typed {
+            if last.key == key && last.device == event.device && last.released_at.is_none() {
+                last.released_at = Some(event.timestamp);
+            }
+        }
+
+        // modifiers are held for the whole chord and auto-repeat means the
+        // key was held on purpose, neither says anything about keystroke force
+        if key.is_modifier() || held.repeated {
+            return;
+        }
+
+        let dwell = event.timestamp.saturating_duration_since(held.pressed_at);
+        if dwell <= TIMING_MAX {
+            *self
+                .dwell_counts
+                .entry((event.device.clone(), (key, timing_bucket(dwell))))
+                .or_insert(0) += 1;
+        }
+    }
+
+    /// Forgets held keys for which `keep` is false, since their release
+    /// will never arrive, and ends the typing run if it was on one of them.
+    pub fn forget_held(&mut self, keep: impl Fn(&Option<DeviceId>, Instant) -> bool) {
+        self.held
+            .retain(|(device, _), held| keep(device, held.pressed_at));
+        if let Some(last) = &self.last_typed {
+            if !keep(&last.device, last.pressed_at) {
+                self.last_typed = None;
+            }
+        }
+    }
+
+    pub fn take_dwell_counts(&mut self) -> DwellCounts {
+        std::mem::take(&mut self.dwell_counts)
+    }
+
+    pub fn take_flight_counts(&mut self) -> FlightCounts {
+        std::mem::take(&mut self.flight_counts)
+    }
+}
+
+/// Counts bigrams and trigrams of consecutive typing keys.
+///
+/// Runs are broken by idle gaps, shortcuts, navigation keys and corrections,
+/// and only daily counts are kept, so the typed text can't be pieced back
+/// together from them.
+#[derive(Default)]
+pub struct NgramTracker {
+    run: Vec<KeyCode>,
+    run_device: Option<DeviceId>,
+    last_press: Option<Instant>,
+    bigram_counts: BigramCounts,
+    trigram_counts: TrigramCounts,
+}
+
+impl NgramTracker {
+    pub fn record_press(&mut self, event: &KeyEvent) {
+        let key = event.key_code;
+        if key.is_modifier() {
+            return;
+        }
+
+        let breaks_run = !key.is_typing_key()
+            || event.modifiers.has_command_modifier()
+            || matches!(key, KeyCode::Backspace | KeyCode::Delete);
+        if breaks_run {
+            self.run.clear();
+            return;
+        }
+
+        let idle = self
+            .last_press
+            .is_some_and(|last| event.timestamp.saturating_duration_since(last) > TIMING_MAX);
+        if idle || self.run_device != event.device {
+            self.run.clear();
+        }
+        self.last_press = Some(event.timestamp);
+        self.run_device = event.device.clone();
+
+        self.run.push(key);
+        if let [.., a, b] = self.run[..] {
+            *self
+                .bigram_counts
+                .entry((event.device.clone(), (a, b)))
+                .or_insert(0) += 1;
+        }
+        if let [a, b, c] = self.run[..] {
+            *self
+                .trigram_counts
+                .entry((event.device.clone(), (a, b, c)))
+                .or_insert(0) += 1;
+            self.run.remove(0);
+        }
+    }
+
+    pub fn take_bigram_counts(&mut self) -> BigramCounts {
+        std::mem::take(&mut self.bigram_counts)
+    }
+
+    pub fn take_trigram_counts(&mut self) -> TrigramCounts {
+        std::mem::take(&mut self.trigram_counts)
+    }
+
+    /// Ends the current run, so the next press starts a new one.
+    pub fn break_run(&mut self) {
+        self.run.clear();
+    }
+}
+
 pub struct Aggregator {
-    key_counts: HashMap<KeyCode, u64>,
-    shortcut_counts: HashMap<String, u64>,
+    key_counts: DeviceCounts<KeyCode>,
+    shortcut_counts: DeviceCounts<String>,
+    timing: TimingTracker,
+    ngrams: NgramTracker,
     session: Option<ActiveSession>,
     pending_session_start: bool,
     wpm_tracker: WpmTracker,
     idle_threshold: Duration,
+    /// latest shortcuts, newest last, for `keyheat live`
+    recent_shortcuts: VecDeque<(DateTime<Utc>, String)>,
 }
 
+/// How many shortcuts [`Aggregator::recent_shortcuts`] remembers.
+const RECENT_SHORTCUTS: usize = 10;
+
 impl Aggregator {
     pub fn new(idle_threshold: Duration, wpm_window: Duration, wpm_sample_interval: Duration) -> Self {
         Self {
             key_counts: HashMap::new(),
             shortcut_counts: HashMap::new(),
+            timing: TimingTracker::default(),
+            ngrams: NgramTracker::default(),
             session: None,
             pending_session_start: false,
             wpm_tracker: WpmTracker::new(wpm_window, wpm_sample_interval),
             idle_threshold,
+            recent_shortcuts: VecDeque::new(),
         }
     }
 
     pub fn process_event(&mut self, event: KeyEvent) {
-        // only count key down events, not releases or repeats
-        if event.event_type != EventType::KeyDown {
-            return;
+        // releases and repeats only feed timing, not counts
+        match event.event_type {
+            EventType::KeyDown => {
+                // a release missed this long ago isn't coming
+                let idle = self.idle_threshold;
+                self.timing.forget_held(|_, pressed_at| {
+                    event.timestamp.saturating_duration_since(pressed_at) <= idle
+                });
+                self.timing.record_press(&event);
+                self.ngrams.record_press(&event);
+            }
+            EventType::KeyUp => {
+                self.timing.record_release(&event);
+                return;
+            }
+            EventType::Repeat => {
+                self.timing.record_repeat(&event);
+                return;
+            }
         }
 
         // count the key press
-        *self.key_counts.entry(event.key_code).or_insert(0) += 1;
+        *self
+            .key_counts
+            .entry((event.device.clone(), event.key_code))
+            .or_insert(0) += 1;
 
         // update session state
         match &mut self.session {
             Some(session) => {
                 session.keystroke_count += 1;
+                *session.device_counts.entry(event.device.clone()).or_insert(0) += 1;
                 session.last_event_at = Instant::now();
 
                 // WPM tracking for typing-eligible keys without modifiers held
                 if event.key_code.is_typing_key() && event.modifiers.is_empty() {
-                    self.wpm_tracker
-                        .record_keystroke(event.timestamp, Utc::now(), session.db_id);
+                    self.wpm_tracker.record_keystroke(
+                        event.timestamp,
+                        Utc::now(),
+                        session.db_id,
+                        event.device.clone(),
+                    );
                 }
             }
             None => {
@@ -221,7 +525,14 @@ impl Aggregator {
         // detect shortcuts: non-modifier key pressed with modifiers held
         if !event.key_code.is_modifier() && !event.modifiers.is_empty() {
             let combo = format!("{}{}", event.modifiers.combo_prefix(), event.key_code);
-            *self.shortcut_counts.entry(combo).or_insert(0) += 1;
+            if self.recent_shortcuts.len() == RECENT_SHORTCUTS {
+                self.recent_shortcuts.pop_front();
+            }
+            self.recent_shortcuts.push_back((Utc::now(), combo.clone()));
+            *self
+                .shortcut_counts
+                .entry((event.device, combo))
+                .or_insert(0) += 1;
         }
     }
 
@@ -233,6 +544,7 @@ impl Aggregator {
         self.session = Some(ActiveSession {
             db_id,
             keystroke_count: 0,
+            device_counts: HashMap::new(),
             last_event_at: Instant::now(),
         });
         self.pending_session_start = false;
@@ -255,20 +567,57 @@ impl Aggregator {
         stats
     }
 
+    /// Forgets every held key and the n-gram run in progress. Events are
+    /// dropped while capture is paused, so nothing held or typed across a
+    /// pause can be timed or paired.
+    pub fn interrupt(&mut self) {
+        self.timing.forget_held(|_, _| false);
+        self.ngrams.break_run();
+    }
+
+    /// Forgets keys held on devices that are no longer attached.
+    pub fn forget_detached(&mut self, attached: &[DeviceId]) {
+        self.timing
+            .forget_held(|device, _| device.as_ref().is_none_or(|d| attached.contains(d)));
+    }
+
     pub fn current_session(&self) -> Option<(i64, u64)> {
         self.session.as_ref().map(|s| (s.db_id, s.keystroke_count))
     }
 
-    pub fn take_counts(&mut self) -> HashMap<KeyCode, u64> {
-        std::mem::take(&mut self.key_counts)
+    /// Keystrokes per device in the active session so far.
+    pub fn session_device_counts(&self) -> HashMap<Option<DeviceId>, u64> {
+        self.session
+            .as_ref()
+            .map(|s| s.device_counts.clone())
+            .unwrap_or_default()
+    }
+
+    /// Key presses counted since the last flush, summed across devices.
+    pub fn pending_key_counts(&self) -> HashMap<KeyCode, u64> {
+        let mut counts = HashMap::new();
+        for ((_, key), &count) in &self.key_counts {
+            *counts.entry(*key).or_insert(0) += count;
+        }
+        counts
     }
 
-    pub fn take_shortcuts(&mut self) -> HashMap<String, u64> {
-        std::mem::take(&mut self.shortcut_counts)
+    /// The last few shortcuts pressed, oldest first. Unlike the counts,
+    /// these survive a flush.
+    pub fn recent_shortcuts(&self) -> impl Iterator<Item = &(DateTime<Utc>, String)> {
+        self.recent_shortcuts.iter()
     }
 
-    pub fn take_wpm_samples(&mut self) -> Vec<WpmSample> {
-        self.wpm_tracker.take_samples()
+    pub fn take_batch(&mut self) -> FlushBatch {
+        FlushBatch {
+            key_counts: std::mem::take(&mut self.key_counts),
+            shortcut_counts: std::mem::take(&mut self.shortcut_counts),
+            wpm_samples: self.wpm_tracker.take_samples(),
+            dwell_counts: self.timing.take_dwell_counts(),
+            flight_counts: self.timing.take_flight_counts(),
+            bigram_counts: self.ngrams.take_bigram_counts(),
+            trigram_counts: self.ngrams.take_trigram_counts(),
+        }
     }
 
     pub fn current_wpm(&self) -> f64 {
@@ -293,6 +642,7 @@ mod tests {
             event_type: EventType::KeyDown,
             timestamp,
             modifiers: ModifierState::empty(),
+            device: None,
         }
     }
 
@@ -304,7 +654,7 @@ mod tests {
         let start = Instant::now();
         let utc_start = Utc::now();
         for i in 0..5 {
-            tracker.record_keystroke(start + Duration::from_millis(i * 100), utc_start, 1);
+            tracker.record_keystroke(start + Duration::from_millis(i * 100), utc_start, 1, None);
         }
 
         assert_eq!(tracker.current_wpm(), 0.0);
@@ -320,7 +670,7 @@ mod tests {
         // 15 keystrokes over 5 seconds = 15/5 * 60 / 5 = 36 WPM
         for i in 0..15 {
             let ts = start + Duration::from_millis(i * 333);
-            tracker.record_keystroke(ts, utc_start, 1);
+            tracker.record_keystroke(ts, utc_start, 1, None);
         }
 
         assert!(tracker.current_wpm() > 30.0);
@@ -343,6 +693,7 @@ mod tests {
                 event_type: EventType::KeyDown,
                 timestamp: start + Duration::from_millis(i * 100),
                 modifiers: ctrl,
+                device: None,
             };
             agg.process_event(event);
         }
@@ -363,4 +714,147 @@ mod tests {
 
         assert_eq!(agg.current_wpm(), 0.0);
     }
+
+    fn timed(key: KeyCode, event_type: EventType, start: Instant, ms: u64) -> KeyEvent {
+        KeyEvent {
+            event_type,
+            ..make_event(key, start + Duration::from_millis(ms))
+        }
+    }
+
+    #[test]
+    fn dwell_and_flight_are_bucketed() {
+        let mut agg = Aggregator::default();
+        let start = Instant::now();
+
+        // t held 80ms, then 120ms until h
+        agg.process_event(timed(KeyCode::T, EventType::KeyDown, start, 0));
+        agg.process_event(timed(KeyCode::T, EventType::KeyUp, start, 80));
+        agg.process_event(timed(KeyCode::H, EventType::KeyDown, start, 200));
+        // e pressed while h is still down: rollover, zero flight
+        agg.process_event(timed(KeyCode::E, EventType::KeyDown, start, 230));
+        agg.process_event(timed(KeyCode::H, EventType::KeyUp, start, 260));
+        agg.process_event(timed(KeyCode::E, EventType::KeyUp, start, 290));
+
+        let batch = agg.take_batch();
+        assert_eq!(batch.dwell_counts.get(&(None, (KeyCode::T, 75))), Some(&1));
+        assert_eq!(batch.dwell_counts.get(&(None, (KeyCode::H, 50))), Some(&1));
+        assert_eq!(batch.dwell_counts.get(&(None, (KeyCode::E, 50))), Some(&1));
+        assert_eq!(
+            batch.flight_counts.get(&(None, (KeyCode::T, KeyCode::H, 100))),
+            Some(&1)
+        );
+        assert_eq!(
+            batch.flight_counts.get(&(None, (KeyCode::H, KeyCode::E, 0))),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    fn held_keys_and_breaks_are_not_timed() {
+        let mut agg = Aggregator::default();
+        let start = Instant::now();
+
+        // auto-repeated backspace is a deliberate hold
+        agg.process_event(timed(KeyCode::Backspace, EventType::KeyDown, start, 0));
+        agg.process_event(timed(KeyCode::Backspace, EventType::Repeat, start, 500));
+        agg.process_event(timed(KeyCode::Backspace, EventType::KeyUp, start, 900));
+        // navigation breaks the chain, and a long pause isn't a transition
+        agg.process_event(timed(KeyCode::Left, EventType::KeyDown, start, 1000));
+        agg.process_event(timed(KeyCode::Left, EventType::KeyUp, start, 1050));
+        agg.process_event(timed(KeyCode::A, EventType::KeyDown, start, 1100));
+        agg.process_event(timed(KeyCode::A, EventType::KeyUp, start, 1150));
+        agg.process_event(timed(KeyCode::B, EventType::KeyDown, start, 5000));
+
+        let batch = agg.take_batch();
+        assert!(!batch.dwell_counts.keys().any(|(_, (k, _))| *k == KeyCode::Backspace));
+        assert!(batch.flight_counts.is_empty());
+        // releases are not keystrokes
+        assert_eq!(batch.key_counts.values().sum::<u64>(), 4);
+    }
+
+    #[test]
+    fn keys_held_across_a_pause_or_unplug_are_forgotten() {
+        let mut agg = Aggregator::default();
+        let start = Instant::now();
+        let keyboard: DeviceId = "04d9:a0f8:keyboard".into();
+        let on_keyboard = |key, event_type, ms| KeyEvent {
+            device: Some(keyboard.clone()),
+            ..timed(key, event_type, start, ms)
+        };
+
+        // unplugged with t down, then plugged back in
+        agg.process_event(on_keyboard(KeyCode::T, EventType::KeyDown, 0));
+        agg.forget_detached(&[]);
+        agg.process_event(on_keyboard(KeyCode::T, EventType::KeyUp, 80));
+        // paused with h down
+        agg.process_event(timed(KeyCode::H, EventType::KeyDown, start, 100));
+        agg.interrupt();
+        agg.process_event(timed(KeyCode::H, EventType::KeyUp, start, 180));
+        let batch = agg.take_batch();
+        assert!(batch.dwell_counts.is_empty());
+        assert!(batch.flight_counts.is_empty());
+
+        // e's release went missing, so a press long after drops it
+        agg.process_event(timed(KeyCode::E, EventType::KeyDown, start, 200));
+        agg.process_event(timed(KeyCode::A, EventType::KeyDown, start, 60_000));
+        assert!(!agg.timing.held.contains_key(&(None, KeyCode::E)));
+    }
+
+    #[test]
+    fn ngram_runs_break_on_shortcuts_idle_and_pauses() {
+        let mut agg = Aggregator::default();
+        let start = Instant::now();
+        let mut ctrl = ModifierState::empty();
+        ctrl.set_ctrl(true);
+
+        for (key, ms) in [(KeyCode::T, 0), (KeyCode::H, 100), (KeyCode::E, 200)] {
+            agg.process_event(make_event(key, start + Duration::from_millis(ms)));
+        }
+        agg.process_event(KeyEvent {
+            modifiers: ctrl,
+            ..make_event(KeyCode::S, start + Duration::from_millis(300))
+        });
+        agg.process_event(make_event(KeyCode::A, start + Duration::from_millis(400)));
+        // long pause before n, so a→n is not a bigram
+        agg.process_event(make_event(KeyCode::N, start + Duration::from_millis(9000)));
+        // nor is n→d across a capture pause
+        agg.interrupt();
+        agg.process_event(make_event(KeyCode::D, start + Duration::from_millis(9100)));
+
+        let batch = agg.take_batch();
+        let bigrams: Vec<_> = batch.bigram_counts.keys().map(|(_, b)| *b).collect();
+        assert_eq!(bigrams.len(), 2);
+        assert!(bigrams.contains(&(KeyCode::T, KeyCode::H)));
+        assert!(bigrams.contains(&(KeyCode::H, KeyCode::E)));
+        assert_eq!(
+            batch.trigram_counts.get(&(None, (KeyCode::T, KeyCode::H, KeyCode::E))),
+            Some(&1)
+        );
+        assert_eq!(batch.trigram_counts.len(), 1);
+    }
+
+    #[test]
+    fn counts_are_attributed_per_device() {
+        let mut agg = Aggregator::default();
+        agg.start_session(1);
+
+        let laptop: DeviceId = "0001:0001:laptop".into();
+        let split: DeviceId = "feed:6060:split".into();
+        let start = Instant::now();
+
+        for (i, device) in [&laptop, &split, &split].into_iter().enumerate() {
+            let event = make_event(KeyCode::A, start + Duration::from_millis(i as u64 * 100))
+                .with_device(device.clone());
+            agg.process_event(event);
+        }
+
+        let session = agg.session_device_counts();
+        assert_eq!(session.get(&Some(laptop.clone())), Some(&1));
+        assert_eq!(session.get(&Some(split.clone())), Some(&2));
+
+        let counts = agg.take_batch().key_counts;
+        assert_eq!(counts.get(&(Some(laptop), KeyCode::A)), Some(&1));
+        assert_eq!(counts.get(&(Some(split), KeyCode::A)), Some(&2));
+    }
 }
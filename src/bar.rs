@@ -0,0 +1,271 @@
+//! `keyheat bar`: one line per update for status bars.
+//!
+//! Keeps a control socket connection open and asks the daemon for its
+//! status every few seconds. The daemon answers from memory, so updates
+//! never touch the database.
+
+use crate::format_number;
+use crate::ipc;
+use anyhow::Result;
+use chrono::Utc;
+use clap::ValueEnum;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Which bar the lines are for.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Protocol {
+    /// JSON for a waybar custom module with "return-type": "json"
+    Waybar,
+    /// i3bar protocol, for i3bar and swaybar's status_command
+    I3bar,
+    /// plain text, for polybar (tail = true) and i3blocks (interval = persist)
+    Plain,
+}
+
+/// What to show, e.g. `{wpm} WPM, {session}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Format {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    /// current WPM
+    Wpm,
+    /// keystrokes today
+    Today,
+    /// time in the current session
+    Session,
+    /// keystrokes in the current session
+    Keys,
+    /// typing, idle or paused
+    State,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let Some(len) = rest[open..].find('}') else {
+                anyhow::bail!("unclosed '{{' in '{s}'");
+            };
+            let field = match &rest[open + 1..open + len] {
+                "wpm" => Field::Wpm,
+                "today" => Field::Today,
+                "session" => Field::Session,
+                "keys" => Field::Keys,
+                "state" => Field::State,
+                other => anyhow::bail!(
+                    "unknown placeholder {{{other}}}, expected {{wpm}}, {{today}}, {{session}}, {{keys}} or {{state}}"
+                ),
+            };
+            parts.push(Part::Field(field));
+            rest = &rest[open + len + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl Format {
+    fn render(&self, status: &ipc::Status) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(Field::Wpm) => format!("{:.0}", status.current_wpm),
+                Part::Field(Field::Today) => format_number(status.today_keystrokes),
+                Part::Field(Field::Session) => session_timer(status),
+                Part::Field(Field::Keys) => {
+                    format_number(status.session.as_ref().map_or(0, |s| s.keystrokes))
+                }
+                Part::Field(Field::State) => state(status).to_string(),
+            })
+            .collect()
+    }
+}
+
+fn state(status: &ipc::Status) -> &'static str {
+    if status.paused.is_some() {
+        "paused"
+    } else if status.session.is_some() {
+        "typing"
+    } else {
+        "idle"
+    }
+}
+
+/// `m:ss` or `h:mm:ss` since the session started, else the state.
+fn session_timer(status: &ipc::Status) -> String {
+    let started_at = status.session.as_ref().and_then(|s| s.started_at);
+    let Some(started_at) = started_at.filter(|_| status.paused.is_none()) else {
+        return state(status).to_string();
+    };
+    let secs = (Utc::now() - started_at).num_seconds().max(0);
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// One update for the bar.
+#[derive(Debug, PartialEq)]
+struct Update {
+    text: String,
+    tooltip: String,
+    /// the state, or "stopped" when no daemon answers
+    class: &'static str,
+}
+
+impl Update {
+    fn new(status: &ipc::Status, format: &Format) -> Self {
+        let session = match &status.session {
+            Some(session) => format!(
+                "Session: {} keystrokes, {}",
+                format_number(session.keystrokes),
+                session_timer(status)
+            ),
+            None => format!("Session: {}", state(status)),
+        };
+        Self {
+            text: format.render(status),
+            tooltip: format!(
+                "KeyHeat: {:.0} WPM\n{session}\nToday: {} keystrokes",
+                status.current_wpm,
+                format_number(status.today_keystrokes)
+            ),
+            class: state(status),
+        }
+    }
+
+    fn stopped() -> Self {
+        Self {
+            text: "keyheat off".to_string(),
+            tooltip: "KeyHeat is not running".to_string(),
+            class: "stopped",
+        }
+    }
+
+    fn line(&self, protocol: Protocol) -> String {
+        match protocol {
+            Protocol::Waybar => serde_json::json!({
+                "text": self.text,
+                "tooltip": self.tooltip,
+                "class": self.class,
+                "alt": self.class,
+            })
+            .to_string(),
+            Protocol::I3bar => {
+                let mut block = serde_json::json!({
+                    "name": "keyheat",
+                    "full_text": self.text,
+                });
+                // the HTML report's amber and muted grey
+                match self.class {
+                    "paused" => block["color"] = "#f59e0b".into(),
+                    "stopped" => block["color"] = "#4b5563".into(),
+                    _ => {}
+                }
+                format!("[{block}]")
+            }
+            Protocol::Plain => self.text.clone(),
+        }
+    }
+}
+
+/// Prints an update whenever it changes, checking every `interval`. Runs
+/// until the bar stops reading.
+pub fn run(protocol: Protocol, format: Format, interval: Duration) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    if let Protocol::I3bar = protocol {
+        // the body is an endless JSON array of block lists
+        writeln!(stdout, r#"{{"version":1}}"#)?;
+        writeln!(stdout, "[")?;
+    }
+
+    let mut client = None;
+    let mut last = None;
+    loop {
+        let update = match fetch(&mut client) {
+            Some(status) => Update::new(&status, &format),
+            None => Update::stopped(),
+        };
+        if last.as_ref() != Some(&update) {
+            let separator = match (protocol, &last) {
+                (Protocol::I3bar, Some(_)) => ",",
+                _ => "",
+            };
+            let written = writeln!(stdout, "{separator}{}", update.line(protocol));
+            if written.and_then(|_| stdout.flush()).is_err() {
+                // the bar closed the pipe
+                return Ok(());
+            }
+            last = Some(update);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// The daemon's status, reconnecting if the last connection dropped.
+fn fetch(client: &mut Option<ipc::Client>) -> Option<ipc::Status> {
+    if let Some(status) = client
+        .as_mut()
+        .and_then(|c| c.send(&ipc::Request::Status).ok())
+    {
+        return status.status;
+    }
+    // the daemon restarted, or closed a connection that sat idle
+    *client = ipc::Client::connect().ok();
+    client.as_mut()?.send(&ipc::Request::Status).ok()?.status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_fills_in_placeholders() {
+        let format: Format = "{wpm} WPM | {today} | {state}".parse().unwrap();
+        let status = ipc::Status {
+            pid: 1,
+            version: "0.1.0".to_string(),
+            started_at: Utc::now(),
+            uptime_secs: 0,
+            current_wpm: 71.6,
+            session: None,
+            today_keystrokes: 12345,
+            devices: Vec::new(),
+            last_flush: None,
+            paused: None,
+            errors: ipc::ErrorCounts::default(),
+        };
+        assert_eq!(format.render(&status), "72 WPM | 12,345 | idle");
+        assert_eq!(session_timer(&status), "idle");
+
+        assert!("{wpm".parse::<Format>().is_err());
+        assert!("{speed}".parse::<Format>().is_err());
+
+        let update = Update::new(&status, &format);
+        assert_eq!(
+            update.line(Protocol::I3bar),
+            r#"[{"full_text":"72 WPM | 12,345 | idle","name":"keyheat"}]"#
+        );
+    }
+}
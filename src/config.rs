@@ -1,3 +1,4 @@
+use crate::error::Error;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
@@ -12,6 +13,277 @@ pub struct Config {
 
     #[serde(default = "default_wpm_sample_interval_secs")]
     pub wpm_sample_interval_secs: u64,
+
+    #[serde(default)]
+    pub devices: DeviceRules,
+
+    /// keyboard layout for reports, `physical[:logical]` e.g. "iso:colemak-dh"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+
+    /// IANA timezone days and hours are counted in, e.g. "Europe/Berlin",
+    /// defaults to the system timezone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+
+    /// toggles pause from the keyboard, empty to disable
+    #[serde(default = "default_privacy_chord")]
+    pub privacy_chord: String,
+
+    #[serde(default)]
+    pub retention: Retention,
+
+    #[serde(default)]
+    pub bar: Bar,
+
+    #[serde(default)]
+    pub dashboard: Dashboard,
+
+    #[serde(default)]
+    pub metrics: Metrics,
+}
+
+/// The web dashboard the daemon can serve on localhost. Off unless
+/// enabled.
+///
+/// ```toml
+/// [dashboard]
+/// enabled = true
+/// port = 7878
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dashboard {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// on 127.0.0.1
+    #[serde(default = "default_dashboard_port")]
+    pub port: u16,
+
+    /// required on every request; one is generated and saved next to the
+    /// database if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+
+    /// serve on `dashboard.sock` in the data directory instead of a port.
+    /// Only your user can connect, so no token is needed
+    #[serde(default)]
+    pub unix_socket: bool,
+}
+
+/// Prometheus metrics the daemon can serve on localhost, apart from the
+/// dashboard. Off unless enabled.
+///
+/// ```toml
+/// [metrics]
+/// enabled = true
+/// port = 7879
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metrics {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// on 127.0.0.1, answers `/metrics` without a token
+    #[serde(default = "default_metrics_port")]
+    pub port: u16,
+}
+
+/// What `keyheat bar` prints.
+///
+/// ```toml
+/// [bar]
+/// format = "{wpm} WPM, {session}"
+/// interval_secs = 1
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bar {
+    /// placeholders: {wpm}, {today}, {session}, {keys} and {state}
+    #[serde(default = "default_bar_format")]
+    pub format: String,
+
+    /// seconds between updates
+    #[serde(default = "default_bar_interval_secs")]
+    pub interval_secs: u64,
+}
+
+/// How long detailed data is kept. Zero keeps it forever.
+///
+/// ```toml
+/// [retention]
+/// wpm_samples_days = 90
+/// detail_days = 365
+/// daily_days = 730
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Retention {
+    /// raw WPM samples older than this are rolled up into 15-minute
+    /// summaries, which reports read the same way
+    #[serde(default = "default_wpm_samples_days")]
+    pub wpm_samples_days: u32,
+
+    /// hourly key counts older than this are rolled up into hour totals
+    /// and per-key counts for each part of the day, which reports read the
+    /// same way
+    #[serde(default)]
+    pub detail_days: u32,
+
+    /// per-day key, shortcut, timing and n-gram counts older than this are
+    /// merged into one row per month. Month and year reports stay the same,
+    /// shorter ranges see a month's counts on its first day.
+    #[serde(default)]
+    pub daily_days: u32,
+
+    /// compact from the daemon once a day
+    #[serde(default = "default_auto_compact")]
+    pub auto_compact: bool,
+}
+
+/// Which input devices get captured.
+///
+/// ```toml
+/// [devices]
+/// exclude = [
+///   { name = "YubiKey" },
+///   { vendor = "1050" },
+///   { name = "keyd virtual keyboard" },
+/// ]
+/// ```
+///
+/// With an empty `include` list every keyboard is a candidate, otherwise only
+/// devices matching an include rule are. Exclude rules always win.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceRules {
+    #[serde(default)]
+    pub include: Vec<DeviceRule>,
+
+    #[serde(default)]
+    pub exclude: Vec<DeviceRule>,
+}
+
+/// Matches a device when every field that is set matches. A rule with no
+/// fields set matches nothing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceRule {
+    /// case-insensitive substring of the device name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// USB vendor ID in hex, e.g. "1050" or "0x1050"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+
+    /// USB product ID in hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+
+    /// substring of the physical path, e.g. "usb-0000:00:14.0-2"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phys: Option<String>,
+}
+
+/// The properties of an input device that rules can match on.
+pub struct DeviceInfo<'a> {
+    pub name: &'a str,
+    pub vendor: u16,
+    pub product: u16,
+    pub phys: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceVerdict {
+    Capture,
+    /// include rules exist and none of them matched
+    NotIncluded,
+    /// matched the exclude rule at this index
+    Excluded(usize),
+}
+
+impl DeviceRules {
+    /// Fails on a vendor or product that isn't a hex ID, since that rule
+    /// could never match.
+    pub fn validate(&self) -> Result<(), Error> {
+        let rules = self.include.iter().chain(&self.exclude);
+        for id in rules.flat_map(|r| r.vendor.iter().chain(&r.product)) {
+            if parse_hex_id(id).is_none() {
+                return Err(Error::DeviceId(id.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn evaluate(&self, device: &DeviceInfo) -> DeviceVerdict {
+        if let Some(idx) = self.exclude.iter().position(|r| r.matches(device)) {
+            return DeviceVerdict::Excluded(idx);
+        }
+
+        if !self.include.is_empty() && !self.include.iter().any(|r| r.matches(device)) {
+            return DeviceVerdict::NotIncluded;
+        }
+
+        DeviceVerdict::Capture
+    }
+}
+
+impl DeviceRule {
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        if self.name.is_none() && self.vendor.is_none() && self.product.is_none() && self.phys.is_none()
+        {
+            return false;
+        }
+
+        let name_ok = self
+            .name
+            .as_ref()
+            .is_none_or(|n| contains_ignore_case(device.name, n));
+        let vendor_ok = self
+            .vendor
+            .as_ref()
+            .is_none_or(|v| parse_hex_id(v) == Some(device.vendor));
+        let product_ok = self
+            .product
+            .as_ref()
+            .is_none_or(|p| parse_hex_id(p) == Some(device.product));
+        let phys_ok = self.phys.as_ref().is_none_or(|p| {
+            device
+                .phys
+                .is_some_and(|phys| contains_ignore_case(phys, p))
+        });
+
+        name_ok && vendor_ok && product_ok && phys_ok
+    }
+}
+
+impl std::fmt::Display for DeviceRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(name) = &self.name {
+            parts.push(format!("name = \"{name}\""));
+        }
+        if let Some(vendor) = &self.vendor {
+            parts.push(format!("vendor = \"{vendor}\""));
+        }
+        if let Some(product) = &self.product {
+            parts.push(format!("product = \"{product}\""));
+        }
+        if let Some(phys) = &self.phys {
+            parts.push(format!("phys = \"{phys}\""));
+        }
+        write!(f, "{{ {} }}", parts.join(", "))
+    }
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+fn parse_hex_id(s: &str) -> Option<u16> {
+    let s = s.trim();
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).ok()
 }
 
 fn default_idle_threshold_secs() -> u64 {
@@ -26,12 +298,88 @@ fn default_wpm_sample_interval_secs() -> u64 {
     10
 }
 
+fn default_privacy_chord() -> String {
+    "ctrl+alt+shift+p".to_string()
+}
+
+fn default_wpm_samples_days() -> u32 {
+    90
+}
+
+fn default_auto_compact() -> bool {
+    true
+}
+
+fn default_bar_format() -> String {
+    "{wpm} WPM, {today} today".to_string()
+}
+
+fn default_bar_interval_secs() -> u64 {
+    2
+}
+
+fn default_dashboard_port() -> u16 {
+    7878
+}
+
+fn default_metrics_port() -> u16 {
+    7879
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_dashboard_port(),
+            token: None,
+            unix_socket: false,
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_metrics_port(),
+        }
+    }
+}
+
+impl Default for Bar {
+    fn default() -> Self {
+        Self {
+            format: default_bar_format(),
+            interval_secs: default_bar_interval_secs(),
+        }
+    }
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            wpm_samples_days: default_wpm_samples_days(),
+            detail_days: 0,
+            daily_days: 0,
+            auto_compact: default_auto_compact(),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             idle_threshold_secs: default_idle_threshold_secs(),
             wpm_window_secs: default_wpm_window_secs(),
             wpm_sample_interval_secs: default_wpm_sample_interval_secs(),
+            devices: DeviceRules::default(),
+            layout: None,
+            timezone: None,
+            privacy_chord: default_privacy_chord(),
+            retention: Retention::default(),
+            bar: Bar::default(),
+            dashboard: Dashboard::default(),
+            metrics: Metrics::default(),
         }
     }
 }
@@ -49,7 +397,7 @@ impl Config {
         Self::default()
     }
 
-    fn config_path() -> PathBuf {
+    pub fn config_path() -> PathBuf {
         dirs::config_dir()
             .unwrap_or_else(|| PathBuf::from("."))
             .join("keyheat")
@@ -84,3 +432,74 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yubikey() -> DeviceInfo<'static> {
+        DeviceInfo {
+            name: "Yubico YubiKey OTP+FIDO+CCID",
+            vendor: 0x1050,
+            product: 0x0407,
+            phys: Some("usb-0000:00:14.0-2/input0"),
+        }
+    }
+
+    #[test]
+    fn device_rules_default_captures_everything() {
+        let rules = DeviceRules::default();
+        assert_eq!(rules.evaluate(&yubikey()), DeviceVerdict::Capture);
+    }
+
+    #[test]
+    fn device_rules_exclude_by_name_or_id() {
+        let rules: DeviceRules = toml::from_str(
+            r#"
+            exclude = [{ name = "keyd" }, { vendor = "0x1050", product = "0407" }]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(rules.evaluate(&yubikey()), DeviceVerdict::Excluded(1));
+    }
+
+    #[test]
+    fn device_rules_include_restricts_and_exclude_wins() {
+        let rules: DeviceRules = toml::from_str(
+            r#"
+            include = [{ phys = "USB-0000:00:14.0" }]
+            exclude = [{ name = "yubikey" }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rules.evaluate(&yubikey()), DeviceVerdict::Excluded(0));
+
+        let laptop = DeviceInfo {
+            name: "AT Translated Set 2 keyboard",
+            vendor: 0x0001,
+            product: 0x0001,
+            phys: Some("isa0060/serio0/input0"),
+        };
+        assert_eq!(rules.evaluate(&laptop), DeviceVerdict::NotIncluded);
+    }
+
+    #[test]
+    fn device_rules_reject_ids_that_are_not_hex() {
+        let rules: DeviceRules = toml::from_str(
+            r#"
+            exclude = [{ name = "keyd" }, { vendor = "0x1050", product = "04zz" }]
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(rules.validate(), Err(Error::DeviceId(id)) if id == "04zz"));
+
+        let rules: DeviceRules = toml::from_str(r#"include = [{ vendor = "0X1050" }]"#).unwrap();
+        assert!(rules.validate().is_ok());
+    }
+
+    #[test]
+    fn empty_device_rule_matches_nothing() {
+        assert!(!DeviceRule::default().matches(&yubikey()));
+    }
+}
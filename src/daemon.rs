@@ -1,6 +1,11 @@
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Set by the signal handler, polled by the capture loop.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
 
 pub fn pid_file_path() -> PathBuf {
     dirs::data_local_dir()
@@ -46,14 +51,71 @@ pub fn remove_pid_file() -> Result<()> {
     Ok(())
 }
 
+/// Removes the PID file if it names this process, so a foreground `run`
+/// leaves a daemon's file alone.
+pub fn remove_own_pid_file() -> Result<()> {
+    if read_pid() == Some(std::process::id()) {
+        remove_pid_file()?;
+    }
+    Ok(())
+}
+
+/// Makes SIGTERM, SIGINT and SIGHUP request a shutdown instead of killing
+/// the process. See [`shutdown_requested`].
+#[cfg(target_os = "linux")]
+pub fn handle_shutdown_signals() -> Result<()> {
+    extern "C" fn request_shutdown(_signal: libc::c_int) {
+        SHUTDOWN.store(true, Ordering::SeqCst);
+    }
+
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        // SA_RESTART keeps the listener threads' blocking reads going
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            let handler: extern "C" fn(libc::c_int) = request_shutdown;
+            action.sa_sigaction = handler as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .context("failed to install signal handler");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn handle_shutdown_signals() -> Result<()> {
+    Ok(())
+}
+
+/// Shuts down as if a signal had arrived, for the `stop` socket command.
+pub fn request_shutdown() {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// Waits up to `timeout` for `pid` to exit, returning whether it did.
+pub fn wait_for_exit(pid: u32, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while is_process_alive(pid) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    true
+}
+
 #[cfg(target_os = "linux")]
 pub fn is_process_alive(pid: u32) -> bool {
-    use std::process::Command;
-    Command::new("kill")
-        .args(["-0", &pid.to_string()])
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
+    // signal 0 only checks the process exists; EPERM means it does
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
 }
 
 #[cfg(target_os = "windows")]
@@ -89,7 +151,7 @@ pub fn check_running() -> Option<u32> {
 
 #[cfg(target_os = "linux")]
 pub fn get_process_start_time(pid: u32) -> Option<std::time::SystemTime> {
-    use std::time::{Duration, UNIX_EPOCH};
+    use std::time::UNIX_EPOCH;
 
     let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
     let parts: Vec<&str> = stat.split_whitespace().collect();
@@ -115,7 +177,7 @@ fn get_boot_time() -> Option<u64> {
 
 #[cfg(target_os = "windows")]
 pub fn get_process_start_time(pid: u32) -> Option<std::time::SystemTime> {
-    use std::time::{Duration, UNIX_EPOCH};
+    use std::time::UNIX_EPOCH;
     use windows_sys::Win32::Foundation::CloseHandle;
     use windows_sys::Win32::System::Threading::{
         GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
@@ -179,16 +241,12 @@ pub fn format_uptime(start: std::time::SystemTime) -> String {
     }
 }
 
+/// Asks the daemon to shut down with SIGTERM. It flushes, closes the
+/// session and exits on its own, see [`wait_for_exit`].
 #[cfg(target_os = "linux")]
 pub fn kill_process(pid: u32) -> Result<()> {
-    use std::process::Command;
-    let status = Command::new("kill")
-        .arg(pid.to_string())
-        .status()
-        .context("failed to send kill signal")?;
-
-    if !status.success() {
-        anyhow::bail!("kill command failed");
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("failed to send SIGTERM");
     }
     Ok(())
 }
@@ -0,0 +1,559 @@
+//! The web dashboard, served by the daemon on localhost when enabled.
+//!
+//! Reports are built on request for whatever range the URL asks for, on a
+//! database connection of their own. Live numbers come from the capture
+//! loop through the same requests the control socket sends. Prometheus
+//! metrics get a port of their own, without the token. See
+//! `docs/dashboard.md` for the endpoints.
+
+use crate::config;
+use crate::ipc;
+use crate::report;
+use crate::storage;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a client gets to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request head accepted, in bytes.
+const MAX_HEAD: u64 = 16 * 1024;
+
+/// Time between events on `/api/live`.
+const LIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+const TOKEN_COOKIE: &str = "keyheat_token";
+
+/// Where the dashboard or metrics listen. A Unix socket goes away when
+/// this is dropped.
+pub struct Dashboard {
+    socket: Option<PathBuf>,
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        if let Some(path) = &self.socket {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn data_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("keyheat")
+}
+
+pub fn socket_path() -> PathBuf {
+    data_dir().join("dashboard.sock")
+}
+
+fn token_path() -> PathBuf {
+    data_dir().join("dashboard-token")
+}
+
+/// The token set in the config, or the one saved by the daemon.
+pub fn token(settings: &config::Dashboard) -> Option<String> {
+    settings.token.clone().or_else(|| {
+        std::fs::read_to_string(token_path())
+            .ok()
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty())
+    })
+}
+
+/// Like [`token`], but saves a new token if there's none yet.
+fn token_or_create(settings: &config::Dashboard) -> Result<String> {
+    if let Some(token) = token(settings) {
+        return Ok(token);
+    }
+    let token = random_token()?;
+    let path = token_path();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .with_context(|| format!("failed to save dashboard token to {}", path.display()))?;
+    Ok(token)
+}
+
+/// 128 bits from the OS random number generator, as hex.
+fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| anyhow::anyhow!("failed to generate dashboard token: {e}"))?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// What each connection thread needs.
+#[derive(Clone)]
+struct Server {
+    /// required on every request, unless on a Unix socket
+    token: Option<String>,
+    /// answer nothing but `/metrics`
+    metrics_only: bool,
+    requester: ipc::Requester,
+}
+
+/// Starts serving. Each connection gets a thread.
+pub fn serve(settings: &config::Dashboard, requester: ipc::Requester) -> Result<Dashboard> {
+    let mut server = Server {
+        token: None,
+        metrics_only: false,
+        requester,
+    };
+    if settings.unix_socket {
+        return serve_unix(server);
+    }
+
+    server.token = Some(token_or_create(settings)?);
+    let listener = std::net::TcpListener::bind(("127.0.0.1", settings.port))
+        .with_context(|| format!("failed to listen on 127.0.0.1:{}", settings.port))?;
+    eprintln!(
+        "dashboard at http://127.0.0.1:{}/, open it with `keyheat dashboard`",
+        settings.port
+    );
+
+    accept(listener, server);
+    Ok(Dashboard { socket: None })
+}
+
+/// Starts serving `/metrics` for Prometheus, which scrapers can reach
+/// without the dashboard or its token.
+pub fn serve_metrics(settings: &config::Metrics, requester: ipc::Requester) -> Result<Dashboard> {
+    let server = Server {
+        token: None,
+        metrics_only: true,
+        requester,
+    };
+    let listener = std::net::TcpListener::bind(("127.0.0.1", settings.port))
+        .with_context(|| format!("failed to listen on 127.0.0.1:{}", settings.port))?;
+    eprintln!("metrics at http://127.0.0.1:{}/metrics", settings.port);
+
+    accept(listener, server);
+    Ok(Dashboard { socket: None })
+}
+
+fn accept(listener: std::net::TcpListener, server: Server) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let server = server.clone();
+            std::thread::spawn(move || {
+                let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+                if let Ok(reader) = stream.try_clone() {
+                    handle(reader, stream, &server);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(unix)]
+fn serve_unix(server: Server) -> Result<Dashboard> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    let path = socket_path();
+    // the daemon refuses to start while another one answers on the control
+    // socket, so this is left over from a crash
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("failed to listen on {}", path.display()))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+        .context("failed to restrict dashboard socket")?;
+    eprintln!("dashboard on {}", path.display());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let server = server.clone();
+            std::thread::spawn(move || {
+                let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+                if let Ok(reader) = stream.try_clone() {
+                    handle(reader, stream, &server);
+                }
+            });
+        }
+    });
+    Ok(Dashboard { socket: Some(path) })
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_server: Server) -> Result<Dashboard> {
+    anyhow::bail!("a Unix socket dashboard is only supported on Unix")
+}
+
+struct Request {
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+}
+
+/// Why a request failed, as an HTTP status and message.
+struct Failure(u16, String);
+
+impl Failure {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self(400, message.into())
+    }
+}
+
+/// Answers one request.
+fn handle(reader: impl Read, mut stream: impl Write, server: &Server) {
+    let request = match read_request(reader) {
+        Ok(request) => request,
+        Err(failure) => return send_failure(&mut stream, failure),
+    };
+
+    let mut extra_headers = Vec::new();
+    if let Some(token) = &server.token {
+        let from_query = request.query.get("token").map(String::as_str);
+        let from_header = request
+            .headers
+            .get("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let from_cookie = request.headers.get("cookie").and_then(|cookies| {
+            cookies
+                .split(';')
+                .find_map(|cookie| cookie.trim().strip_prefix(TOKEN_COOKIE)?.strip_prefix('='))
+        });
+        if ![from_query, from_header, from_cookie]
+            .into_iter()
+            .flatten()
+            .any(|given| same_token(given, token))
+        {
+            return send_failure(
+                &mut stream,
+                Failure(401, "missing or wrong token".to_string()),
+            );
+        }
+        // so the browser can drop the token from later URLs
+        if from_query.is_some() {
+            extra_headers.push(format!(
+                "Set-Cookie: {TOKEN_COOKIE}={token}; HttpOnly; SameSite=Strict; Path=/"
+            ));
+        }
+    }
+
+    let result = match request.path.as_str() {
+        "/metrics" if server.metrics_only => metrics(&server.requester, &request.headers),
+        _ if server.metrics_only => Err(Failure(404, "not found".to_string())),
+        "/" | "/report" => report_data(&request.query)
+            .map(|data| ("text/html; charset=utf-8", report::render_html(&data))),
+        "/api/report" => report_data(&request.query).and_then(|data| {
+            report::render_json(&data)
+                .map(|json| ("application/json", json))
+                .map_err(|e| Failure(500, e.to_string()))
+        }),
+        "/api/status" => status(&server.requester).map(|status| {
+            let json = serde_json::to_string_pretty(&status).unwrap_or_default();
+            ("application/json", json)
+        }),
+        "/api/live" => return stream_live(&mut stream, &server.requester),
+        _ => Err(Failure(404, "not found".to_string())),
+    };
+
+    match result {
+        Ok((content_type, body)) => send(&mut stream, 200, content_type, &extra_headers, &body),
+        Err(failure) => send_failure(&mut stream, failure),
+    }
+}
+
+fn read_request(reader: impl Read) -> Result<Request, Failure> {
+    let mut lines = BufReader::new(reader.take(MAX_HEAD)).lines();
+    let request_line = lines
+        .next()
+        .and_then(Result::ok)
+        .ok_or_else(|| Failure::bad_request("empty request"))?;
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Failure::bad_request("malformed request line"));
+    };
+    if method != "GET" {
+        return Err(Failure(405, "only GET is supported".to_string()));
+    }
+
+    let mut headers = HashMap::new();
+    for line in lines {
+        let line = line.map_err(|_| Failure::bad_request("malformed headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+
+    Ok(Request {
+        path: path.to_string(),
+        query,
+        headers,
+    })
+}
+
+/// Decodes `%XX` escapes and `+` for space, as browsers send query strings.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'+', _) => out.push(b' '),
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 2;
+            }
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Compares without stopping at the first difference.
+fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Builds the report the query asks for. It takes the same options as
+/// `keyheat report`, e.g. `?last=30d&device=kinesis`.
+fn report_data(query: &HashMap<String, String>) -> Result<report::ReportData, Failure> {
+    let param = |name: &str| {
+        query
+            .get(name)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    };
+    let ranges = ["week", "day", "month", "year", "from", "last"];
+    if ranges.iter().filter(|name| param(name).is_some()).count() > 1 {
+        return Err(Failure::bad_request(
+            "give only one of week, day, month, year, from or last",
+        ));
+    }
+    let date = |name: &str, parse: fn(&str) -> Result<chrono::NaiveDate, String>| {
+        param(name)
+            .map(parse)
+            .transpose()
+            .map_err(Failure::bad_request)
+    };
+    let year = param("year")
+        .map(|year| match year.parse::<i32>() {
+            Ok(year) if (1970..=9999).contains(&year) => Ok(year),
+            _ => Err(Failure::bad_request(format!("invalid year '{year}'"))),
+        })
+        .transpose()?;
+    let range = report::RangeChoice {
+        week: date("week", report::parse_week)?,
+        day: date("day", report::parse_day)?,
+        month: date("month", report::parse_month)?,
+        year,
+        from: date("from", report::parse_day)?,
+        to: date("to", report::parse_day)?,
+        last: param("last")
+            .map(str::parse)
+            .transpose()
+            .map_err(Failure::bad_request)?,
+    };
+    let range = range.into_spec().map_err(Failure::bad_request)?;
+    let layout = param("layout")
+        .map(str::parse::<report::LayoutSpec>)
+        .transpose()
+        .map_err(|e| Failure::bad_request(e.to_string()))?;
+
+    let storage = storage::Storage::open()
+        .map_err(|e| Failure(500, format!("failed to open database: {e}")))?;
+    let config = config::Config::load();
+    let options = report::ReportOptions::build(
+        &storage,
+        &config,
+        range,
+        param("device").map(str::to_string),
+        param("machine").map(str::to_string),
+        layout,
+    )
+    .map_err(|e| Failure::bad_request(format!("{e:#}")))?;
+
+    report::build_report(storage.connection(), &options)
+        .map_err(|e| Failure(500, format!("failed to build report: {e}")))
+}
+
+fn status(requester: &ipc::Requester) -> Result<ipc::Status, Failure> {
+    let response = requester.ask(ipc::Request::Status);
+    match (response.status, response.error) {
+        (Some(status), _) => Ok(status),
+        (None, error) => Err(Failure(
+            503,
+            error.unwrap_or_else(|| "daemon sent no status".to_string()),
+        )),
+    }
+}
+
+/// Totals from the capture loop, as OpenMetrics if the scraper asks for
+/// it and the Prometheus text format otherwise.
+fn metrics(
+    requester: &ipc::Requester,
+    headers: &HashMap<String, String>,
+) -> Result<(&'static str, String), Failure> {
+    let response = requester.ask(ipc::Request::Metrics);
+    let Some(metrics) = response.metrics else {
+        let error = response.error;
+        return Err(Failure(
+            503,
+            error.unwrap_or_else(|| "daemon sent no metrics".to_string()),
+        ));
+    };
+    let openmetrics = headers
+        .get("accept")
+        .is_some_and(|accept| accept.contains("application/openmetrics-text"));
+    Ok(if openmetrics {
+        (
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            metrics.render(true),
+        )
+    } else {
+        (
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(false),
+        )
+    })
+}
+
+/// Sends the daemon's status as a server-sent event every second, until
+/// the client goes away.
+fn stream_live(stream: &mut impl Write, requester: &ipc::Requester) {
+    let head = "HTTP/1.1 200 OK\r\n\
+                Content-Type: text/event-stream\r\n\
+                Cache-Control: no-store\r\n\
+                Connection: close\r\n\r\n";
+    if stream.write_all(head.as_bytes()).is_err() {
+        return;
+    }
+    loop {
+        let event = match status(requester) {
+            Ok(status) => {
+                let json = serde_json::to_string(&status).unwrap_or_default();
+                format!("event: status\ndata: {json}\n\n")
+            }
+            // the daemon is going away
+            Err(Failure(_, error)) => {
+                let _ = write!(stream, "event: error\ndata: {error}\n\n");
+                return;
+            }
+        };
+        if stream
+            .write_all(event.as_bytes())
+            .and_then(|_| stream.flush())
+            .is_err()
+        {
+            return;
+        }
+        std::thread::sleep(LIVE_INTERVAL);
+    }
+}
+
+fn send(stream: &mut impl Write, code: u16, content_type: &str, headers: &[String], body: &str) {
+    let reason = match code {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let mut head = format!(
+        "HTTP/1.1 {code} {reason}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n",
+        body.len()
+    );
+    for header in headers {
+        head.push_str(header);
+        head.push_str("\r\n");
+    }
+    head.push_str("\r\n");
+    let _ = stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(body.as_bytes()))
+        .and_then(|_| stream.flush());
+}
+
+fn send_failure(stream: &mut impl Write, Failure(code, message): Failure) {
+    let body = serde_json::json!({ "error": message }).to_string();
+    send(stream, code, "application/json", &[], &body);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_parsed_and_decoded() {
+        let raw = "GET /api/report?last=30d&device=My%20Board+2&token=abc HTTP/1.1\r\n\
+                   Host: 127.0.0.1\r\n\
+                   Cookie: theme=dark; keyheat_token=abc\r\n\r\n";
+        let request = read_request(raw.as_bytes()).ok().unwrap();
+        assert_eq!(request.path, "/api/report");
+        assert_eq!(request.query["last"], "30d");
+        assert_eq!(request.query["device"], "My Board 2");
+        assert_eq!(request.headers["cookie"], "theme=dark; keyheat_token=abc");
+
+        assert!(matches!(
+            read_request("POST / HTTP/1.1\r\n\r\n".as_bytes()),
+            Err(Failure(405, _))
+        ));
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%e2%96%88"), "█");
+
+        assert!(same_token("abc", "abc"));
+        assert!(!same_token("abd", "abc"));
+        assert!(!same_token("ab", "abc"));
+    }
+
+    #[test]
+    fn metrics_port_serves_nothing_else() {
+        let server = Server {
+            token: None,
+            metrics_only: true,
+            requester: ipc::Inbox::new().requester(),
+        };
+        let raw = "GET /api/status HTTP/1.1\r\n\r\n";
+        let mut response = Vec::new();
+        handle(raw.as_bytes(), &mut response, &server);
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+    }
+}
@@ -5,12 +5,37 @@ pub enum Error {
     #[error("evdev error: {0}")]
     Evdev(#[from] std::io::Error),
 
+    /// files and pipes, as opposed to input devices
+    #[error("{0}")]
+    Io(std::io::Error),
+
     #[error("database error: {0}")]
     Database(#[from] rusqlite::Error),
 
     #[error("no keyboard devices found")]
     NoKeyboards,
 
+    #[error("layout error: {0}")]
+    Layout(String),
+
+    #[error("import failed: {0}")]
+    Import(String),
+
+    #[error("invalid key chord '{0}', expected modifiers and a key like ctrl+alt+shift+p")]
+    Chord(String),
+
+    #[error("another daemon is listening on {0}")]
+    AlreadyRunning(String),
+
+    #[error("invalid USB id '{0}' in a device rule, expected hex like 1050 or 0x1050")]
+    DeviceId(String),
+
+    #[error("no machine named {name}, known machines: {known}")]
+    UnknownMachine { name: String, known: String },
+
+    #[error("unknown timezone '{0}', expected an IANA name like Europe/Berlin")]
+    Timezone(String),
+
     #[cfg(target_os = "windows")]
     #[error("hook error: {0}")]
     Hook(String),
@@ -0,0 +1,308 @@
+use crate::error::Error;
+use crate::timezone::Zone;
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, Utc};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection};
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Rows to export: local dates for the daily tables, UTC instants for the
+/// timestamped ones.
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+impl Bounds {
+    /// Local days `start..=end`.
+    pub fn days(start: NaiveDate, end: NaiveDate, zone: Zone) -> Self {
+        let (from, to) = zone.day_bounds(start, end);
+        Self {
+            start,
+            end,
+            from,
+            to,
+        }
+    }
+
+    pub fn all() -> Self {
+        let start = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default();
+        let end = NaiveDate::from_ymd_opt(9999, 12, 31).unwrap_or_default();
+        // the day after `end` has a five-digit year SQLite can't read
+        Self {
+            start,
+            end,
+            from: start.and_time(NaiveTime::MIN).and_utc(),
+            to: end.and_hms_opt(23, 59, 59).unwrap_or_default().and_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    /// `?1`/`?2` are inclusive YYYY-MM-DD dates
+    Date,
+    /// `?1`/`?2` are half-open RFC 3339 instants
+    Instant,
+}
+
+/// One exported table. Columns are part of the export contract in
+/// `docs/export.md`: only ever append to them.
+pub struct Table {
+    pub name: &'static str,
+    pub columns: &'static [&'static str],
+    /// columns holding instants, rewritten as UTC with millisecond precision
+    timestamps: &'static [&'static str],
+    filter: Filter,
+    sql: &'static str,
+}
+
+pub const TABLES: [Table; 6] = [
+    Table {
+        name: "key_counts",
+        columns: &["date", "device", "key_code", "count", "machine"],
+        timestamps: &[],
+        filter: Filter::Date,
+        sql: "SELECT date, device, key_code, count, machine FROM key_counts
+              WHERE date >= ?1 AND date <= ?2
+              ORDER BY date, machine, device, key_code",
+    },
+    Table {
+        name: "shortcut_counts",
+        columns: &["date", "device", "combo", "count", "machine"],
+        timestamps: &[],
+        filter: Filter::Date,
+        sql: "SELECT date, device, combo, count, machine FROM shortcut_counts
+              WHERE date >= ?1 AND date <= ?2
+              ORDER BY date, machine, device, combo",
+    },
+    Table {
+        name: "sessions",
+        columns: &[
+            "session_id",
+            "start_time",
+            "end_time",
+            "keystroke_count",
+            "avg_wpm",
+            "peak_wpm",
+            "machine",
+        ],
+        timestamps: &["start_time", "end_time"],
+        filter: Filter::Instant,
+        sql: "SELECT id, start_time, end_time, keystroke_count, avg_wpm, peak_wpm, machine
+              FROM sessions
+              WHERE julianday(start_time) >= julianday(?1)
+                AND julianday(start_time) < julianday(?2)
+              ORDER BY start_time",
+    },
+    Table {
+        name: "wpm_samples",
+        columns: &[
+            "timestamp",
+            "session_id",
+            "device",
+            "wpm",
+            "keystrokes_in_window",
+            "machine",
+        ],
+        timestamps: &["timestamp"],
+        filter: Filter::Instant,
+        sql: "SELECT timestamp, session_id, device, wpm, keystrokes_in_window, machine
+              FROM wpm_samples
+              WHERE julianday(timestamp) >= julianday(?1)
+                AND julianday(timestamp) < julianday(?2)
+              ORDER BY timestamp",
+    },
+    Table {
+        name: "wpm_rollups",
+        columns: &[
+            "slot",
+            "session_id",
+            "device",
+            "wpm_bin",
+            "samples",
+            "wpm_sum",
+            "wpm_max",
+            "max_at",
+            "machine",
+        ],
+        timestamps: &["slot", "max_at"],
+        filter: Filter::Instant,
+        sql: "SELECT slot, session_id, device, wpm_bin, samples, wpm_sum, wpm_max, max_at, machine
+              FROM wpm_rollups
+              WHERE julianday(slot) >= julianday(?1)
+                AND julianday(slot) < julianday(?2)
+              ORDER BY slot, session_id, device, machine, wpm_bin",
+    },
+    Table {
+        name: "pauses",
+        columns: &["pause_id", "start_time", "end_time", "reason", "machine"],
+        timestamps: &["start_time", "end_time"],
+        filter: Filter::Instant,
+        sql: "SELECT id, start_time, end_time, reason, machine
+              FROM pauses
+              WHERE julianday(start_time) >= julianday(?1)
+                AND julianday(start_time) < julianday(?2)
+              ORDER BY start_time",
+    },
+];
+
+impl Table {
+    /// Rows in column order, as JSON values so both formats share them.
+    pub fn rows(&self, conn: &Connection, bounds: &Bounds) -> Result<Vec<Vec<Value>>, Error> {
+        let (lo, hi) = match self.filter {
+            Filter::Date => (
+                bounds.start.format("%Y-%m-%d").to_string(),
+                bounds.end.format("%Y-%m-%d").to_string(),
+            ),
+            Filter::Instant => (bounds.from.to_rfc3339(), bounds.to.to_rfc3339()),
+        };
+
+        let mut stmt = conn.prepare(self.sql)?;
+        let mut rows = stmt.query(params![lo, hi])?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut values = Vec::with_capacity(self.columns.len());
+            for (i, column) in self.columns.iter().enumerate() {
+                let value = match row.get_ref(i)? {
+                    ValueRef::Null => Value::Null,
+                    ValueRef::Integer(n) => Value::from(n),
+                    ValueRef::Real(x) => Value::from(x),
+                    ValueRef::Text(text) | ValueRef::Blob(text) => {
+                        let text = String::from_utf8_lossy(text);
+                        if self.timestamps.contains(column) {
+                            Value::from(normalize_timestamp(&text))
+                        } else {
+                            Value::from(text.into_owned())
+                        }
+                    }
+                };
+                values.push(value);
+            }
+            out.push(values);
+        }
+        Ok(out)
+    }
+}
+
+/// Writes one `<table>.csv` per table into `dir`, returning the files
+/// and how many rows each got.
+pub fn write_csv(
+    conn: &Connection,
+    bounds: &Bounds,
+    dir: &Path,
+) -> Result<Vec<(PathBuf, usize)>, Error> {
+    fs::create_dir_all(dir).map_err(Error::Io)?;
+
+    let mut written = Vec::new();
+    for table in &TABLES {
+        let rows = table.rows(conn, bounds)?;
+        let path = dir.join(format!("{}.csv", table.name));
+
+        let mut out = String::new();
+        out.push_str(&table.columns.join(","));
+        out.push('\n');
+        for row in &rows {
+            let fields: Vec<String> = row.iter().map(csv_field).collect();
+            out.push_str(&fields.join(","));
+            out.push('\n');
+        }
+        fs::write(&path, out).map_err(Error::Io)?;
+        written.push((path, rows.len()));
+    }
+    Ok(written)
+}
+
+/// Writes every table as one JSON object per line, tagged with its table:
+/// `{"table":"key_counts","date":"2025-02-03",...}`. Returns the row count.
+pub fn write_jsonl(
+    conn: &Connection,
+    bounds: &Bounds,
+    out: &mut dyn Write,
+) -> Result<usize, Error> {
+    let mut count = 0;
+    for table in &TABLES {
+        for row in table.rows(conn, bounds)? {
+            // built by hand so keys keep column order
+            let mut line = format!("{{\"table\":\"{}\"", table.name);
+            for (column, value) in table.columns.iter().zip(&row) {
+                line.push_str(&format!(",\"{column}\":{value}"));
+            }
+            line.push('}');
+            writeln!(out, "{line}").map_err(Error::Io)?;
+            count += 1;
+        }
+    }
+    out.flush().map_err(Error::Io)?;
+    Ok(count)
+}
+
+/// Stored timestamps vary in precision and offset; exports always use
+/// UTC with milliseconds, e.g. 2025-02-03T08:15:00.250Z.
+fn normalize_timestamp(text: &str) -> String {
+    DateTime::parse_from_rfc3339(text)
+        .map(|t| {
+            t.with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+        })
+        .unwrap_or_else(|_| text.to_string())
+}
+
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) if s.contains([',', '"', '\n', '\r']) => {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        }
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    #[test]
+    fn exports_filter_by_range_and_normalize() {
+        let storage = Storage::in_memory().unwrap();
+        let conn = storage.connection();
+        conn.execute_batch(
+            "INSERT INTO key_counts (key_code, date, device, count) VALUES
+                ('a', '2025-02-03', '1:2:Board, \"Pro\"', 5),
+                ('b', '2025-02-10', '', 1);
+             INSERT INTO sessions (id, start_time, end_time, keystroke_count) VALUES
+                (7, '2025-02-03T09:00:00.123456+01:00', NULL, 40),
+                (8, '2025-02-10T09:00:00+00:00', NULL, 1);",
+        )
+        .unwrap();
+
+        let date = |d| NaiveDate::from_ymd_opt(2025, 2, d).unwrap();
+        let bounds = Bounds::days(date(3), date(9), Zone::Named(chrono_tz::UTC));
+
+        let keys = TABLES[0].rows(conn, &bounds).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(csv_field(&keys[0][1]), "\"1:2:Board, \"\"Pro\"\"\"");
+
+        let sessions = TABLES[2].rows(conn, &bounds).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0][1], "2025-02-03T08:00:00.123Z");
+        assert_eq!(csv_field(&sessions[0][2]), "");
+
+        let mut out = Vec::new();
+        assert_eq!(write_jsonl(conn, &Bounds::all(), &mut out).unwrap(), 4);
+        let first = String::from_utf8(out).unwrap();
+        let first = first.lines().next().unwrap();
+        assert!(
+            first.starts_with("{\"table\":\"key_counts\",\"date\":\"2025-02-03\""),
+            "{first}"
+        );
+        serde_json::from_str::<Value>(first).unwrap();
+    }
+}
@@ -0,0 +1,330 @@
+//! Control socket for the running daemon.
+//!
+//! Clients connect to `keyheat.sock` in the data directory and write one
+//! JSON request per line, e.g. `{"cmd":"status"}`. Each gets one JSON
+//! response line back. See `docs/ipc.md` for the protocol.
+
+use crate::error::Error;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Duration;
+
+/// How long either side waits on the other.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long the daemon keeps a quiet connection open.
+#[cfg(unix)]
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    /// status plus today's per-key counts, for `keyheat live`
+    Live,
+    /// totals since startup, for `/metrics`
+    Metrics,
+    /// drop events until `resume`, or for `for_secs`
+    Pause {
+        #[serde(default)]
+        for_secs: Option<u64>,
+    },
+    Resume,
+    /// flush, close the session and exit, as on SIGTERM
+    Stop,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub live: Option<Live>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<crate::metrics::Metrics>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            error: Some(message.into()),
+            ..Self::default()
+        }
+    }
+}
+
+/// What the daemon is doing right now.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: u64,
+    pub current_wpm: f64,
+    pub session: Option<SessionStatus>,
+    /// stored and not yet flushed, for the local day
+    pub today_keystrokes: u64,
+    /// keyboards being captured
+    pub devices: Vec<DeviceStatus>,
+    pub last_flush: Option<DateTime<Utc>>,
+    /// set while nothing is being recorded
+    pub paused: Option<PauseStatus>,
+    pub errors: ErrorCounts,
+}
+
+/// What `keyheat live` draws besides the status.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Live {
+    /// presses per key today, stored and not yet flushed
+    pub keys: HashMap<String, u64>,
+    /// oldest first
+    pub recent_shortcuts: Vec<RecentShortcut>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentShortcut {
+    pub at: DateTime<Utc>,
+    pub combo: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStatus {
+    pub id: i64,
+    pub started_at: Option<DateTime<Utc>>,
+    pub keystrokes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauseStatus {
+    pub since: DateTime<Utc>,
+    /// None until `resume`
+    pub until: Option<DateTime<Utc>>,
+    /// "command" or "chord"
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceStatus {
+    pub name: String,
+    /// `vendor:product:name`, as stored in the database
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ErrorCounts {
+    /// failed writes in a row; the daemon exits at 3
+    pub database_consecutive: u32,
+    pub database_total: u64,
+}
+
+/// A request waiting for the capture loop to answer it.
+pub struct Pending {
+    pub request: Request,
+    pub reply: Sender<Response>,
+}
+
+/// Requests for the capture loop, from the control socket or the
+/// dashboard. The loop answers them between events.
+pub struct Inbox {
+    sender: Sender<Pending>,
+    receiver: Receiver<Pending>,
+}
+
+impl Inbox {
+    pub fn new() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self { sender, receiver }
+    }
+
+    /// A handle other threads ask through.
+    pub fn requester(&self) -> Requester {
+        Requester(self.sender.clone())
+    }
+
+    /// The next request waiting for an answer, if any.
+    pub fn try_recv(&self) -> Option<Pending> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// Asks the capture loop from another thread.
+#[derive(Clone)]
+pub struct Requester(Sender<Pending>);
+
+impl Requester {
+    /// Waits for the answer, up to a few seconds.
+    pub fn ask(&self, request: Request) -> Response {
+        let (reply, response) = std::sync::mpsc::channel();
+        if self.0.send(Pending { request, reply }).is_err() {
+            return Response::error("daemon is shutting down");
+        }
+        response
+            .recv_timeout(TIMEOUT)
+            .unwrap_or_else(|_| Response::error("daemon did not answer"))
+    }
+}
+
+pub fn socket_path() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("keyheat")
+        .join("keyheat.sock")
+}
+
+/// The daemon's end of the socket. The socket file goes away when this is
+/// dropped.
+pub struct ControlSocket(());
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(socket_path());
+    }
+}
+
+/// Listens on the control socket, passing requests on through `requester`.
+/// Fails with [`Error::AlreadyRunning`] if another daemon answers on it.
+#[cfg(unix)]
+pub fn serve(requester: Requester) -> Result<ControlSocket> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = socket_path();
+    if path.exists() {
+        // a socket nobody answers on is left over from a crash
+        if UnixStream::connect(&path).is_ok() {
+            return Err(Error::AlreadyRunning(path.display().to_string()).into());
+        }
+        std::fs::remove_file(&path).context("failed to remove stale socket")?;
+    }
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("failed to listen on {}", path.display()))?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let _ = stream.set_read_timeout(Some(IDLE_TIMEOUT));
+            let _ = stream.set_write_timeout(Some(TIMEOUT));
+            let Ok(reader) = stream.try_clone() else {
+                continue;
+            };
+
+            // clients like `keyheat bar` stay connected, so each gets a thread
+            let requester = requester.clone();
+            std::thread::spawn(move || {
+                for line in BufReader::new(reader).lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    let response = match serde_json::from_str(&line) {
+                        Ok(request) => requester.ask(request),
+                        Err(e) => Response::error(format!("invalid request: {e}")),
+                    };
+                    let Ok(json) = serde_json::to_string(&response) else {
+                        break;
+                    };
+                    if writeln!(stream, "{json}").is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(ControlSocket(()))
+}
+
+#[cfg(not(unix))]
+pub fn serve(_requester: Requester) -> Result<ControlSocket> {
+    anyhow::bail!("the control socket is only supported on Unix")
+}
+
+/// A connection to the running daemon, for clients that ask repeatedly.
+#[cfg(unix)]
+pub struct Client {
+    stream: std::os::unix::net::UnixStream,
+    reader: std::io::BufReader<std::os::unix::net::UnixStream>,
+}
+
+#[cfg(unix)]
+impl Client {
+    /// Fails if no daemon is listening.
+    pub fn connect() -> Result<Self> {
+        use std::os::unix::net::UnixStream;
+
+        let stream = UnixStream::connect(socket_path()).context("daemon is not listening")?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let reader = std::io::BufReader::new(stream.try_clone()?);
+        Ok(Self { stream, reader })
+    }
+
+    pub fn send(&mut self, request: &Request) -> Result<Response> {
+        use std::io::{BufRead, Write};
+
+        writeln!(self.stream, "{}", serde_json::to_string(request)?)?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            anyhow::bail!("daemon closed the connection");
+        }
+        serde_json::from_str(&line).context("invalid response from daemon")
+    }
+}
+
+#[cfg(not(unix))]
+pub struct Client;
+
+#[cfg(not(unix))]
+impl Client {
+    pub fn connect() -> Result<Self> {
+        anyhow::bail!("the control socket is only supported on Unix")
+    }
+
+    pub fn send(&mut self, _request: &Request) -> Result<Response> {
+        anyhow::bail!("the control socket is only supported on Unix")
+    }
+}
+
+/// Sends one request to the running daemon. Fails if none is listening.
+pub fn request(request: &Request) -> Result<Response> {
+    Client::connect()?.send(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_is_one_json_object_per_line() {
+        let request: Request = serde_json::from_str(r#"{"cmd":"status"}"#).unwrap();
+        assert!(matches!(request, Request::Status));
+        let request: Request = serde_json::from_str(r#"{"cmd":"pause","for_secs":600}"#).unwrap();
+        assert!(matches!(
+            request,
+            Request::Pause {
+                for_secs: Some(600)
+            }
+        ));
+        assert!(serde_json::from_str::<Request>(r#"{"cmd":"reboot"}"#).is_err());
+
+        let json = serde_json::to_string(&Response::ok()).unwrap();
+        assert_eq!(json, r#"{"ok":true}"#);
+        let json = serde_json::to_string(&Response::error("nope")).unwrap();
+        assert_eq!(json, r#"{"ok":false,"error":"nope"}"#);
+    }
+}
@@ -1,5 +1,7 @@
+use crate::error::Error;
 use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Instant;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -135,6 +137,15 @@ pub enum KeyCode {
     NumpadEnter,
     NumpadDecimal,
 
+    // International (ISO and JIS)
+    IntlBackslash,
+    IntlRo,
+    IntlYen,
+    Henkan,
+    Muhenkan,
+    KatakanaHiragana,
+    ZenkakuHankaku,
+
     // Misc
     Menu,
 
@@ -268,6 +279,13 @@ impl fmt::Display for KeyCode {
             KeyCode::NumpadEnter => "numpadenter",
             KeyCode::NumpadDecimal => "numpaddecimal",
 
+            KeyCode::IntlBackslash => "intlbackslash",
+            KeyCode::IntlRo => "intlro",
+            KeyCode::IntlYen => "intlyen",
+            KeyCode::Henkan => "henkan",
+            KeyCode::Muhenkan => "muhenkan",
+            KeyCode::KatakanaHiragana => "katakanahiragana",
+            KeyCode::ZenkakuHankaku => "zenkakuhankaku",
             KeyCode::Menu => "menu",
 
             KeyCode::Unknown(code) => return write!(f, "unknown_{code}"),
@@ -407,6 +425,13 @@ impl FromStr for KeyCode {
             "numpadenter" => KeyCode::NumpadEnter,
             "numpaddecimal" => KeyCode::NumpadDecimal,
 
+            "intlbackslash" => KeyCode::IntlBackslash,
+            "intlro" => KeyCode::IntlRo,
+            "intlyen" => KeyCode::IntlYen,
+            "henkan" => KeyCode::Henkan,
+            "muhenkan" => KeyCode::Muhenkan,
+            "katakanahiragana" => KeyCode::KatakanaHiragana,
+            "zenkakuhankaku" => KeyCode::ZenkakuHankaku,
             "menu" => KeyCode::Menu,
 
             _ => {
@@ -437,6 +462,32 @@ impl KeyCode {
         )
     }
 
+    /// Which group the key belongs to, for totals that don't need every
+    /// key: `letter`, `number`, `punctuation`, `whitespace`, `editing`,
+    /// `modifier`, `navigation`, `function`, `numpad` or `other`.
+    pub fn class(&self) -> &'static str {
+        use KeyCode::*;
+        match self {
+            A | B | C | D | E | F | G | H | I | J | K | L | M | N | O | P | Q | R | S | T | U
+            | V | W | X | Y | Z => "letter",
+            Num0 | Num1 | Num2 | Num3 | Num4 | Num5 | Num6 | Num7 | Num8 | Num9 => "number",
+            Minus | Equal | LeftBracket | RightBracket | Semicolon | Apostrophe | Grave
+            | Backslash | Comma | Period | Slash | IntlBackslash | IntlRo | IntlYen => {
+                "punctuation"
+            }
+            Space | Tab | Enter => "whitespace",
+            Backspace | Delete | Insert => "editing",
+            _ if self.is_modifier() => "modifier",
+            Up | Down | Left | Right | Home | End | PageUp | PageDown => "navigation",
+            F1 | F2 | F3 | F4 | F5 | F6 | F7 | F8 | F9 | F10 | F11 | F12 | F13 | F14 | F15
+            | F16 | F17 | F18 | F19 | F20 | F21 | F22 | F23 | F24 => "function",
+            Numpad0 | Numpad1 | Numpad2 | Numpad3 | Numpad4 | Numpad5 | Numpad6 | Numpad7
+            | Numpad8 | Numpad9 | NumpadAdd | NumpadSubtract | NumpadMultiply | NumpadDivide
+            | NumpadEnter | NumpadDecimal => "numpad",
+            _ => "other",
+        }
+    }
+
     pub fn is_typing_key(&self) -> bool {
         matches!(
             self,
@@ -460,6 +511,7 @@ impl KeyCode {
             KeyCode::Semicolon | KeyCode::Apostrophe |
             KeyCode::Grave | KeyCode::Backslash |
             KeyCode::Comma | KeyCode::Period | KeyCode::Slash |
+            KeyCode::IntlBackslash | KeyCode::IntlRo | KeyCode::IntlYen |
             // Numpad (typing, not navigation)
             KeyCode::Numpad0 | KeyCode::Numpad1 | KeyCode::Numpad2 |
             KeyCode::Numpad3 | KeyCode::Numpad4 | KeyCode::Numpad5 |
@@ -540,6 +592,16 @@ impl ModifierState {
         self.0 == 0
     }
 
+    /// Ctrl, alt or super held, i.e. the key is part of a command rather
+    /// than text. Shift alone still counts as typing.
+    pub fn has_command_modifier(&self) -> bool {
+        self.0 & (Self::CTRL | Self::ALT | Self::SUPER) != 0
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
     pub fn combo_prefix(self) -> String {
         let mut parts = Vec::new();
         if self.has_alt() {
@@ -562,12 +624,95 @@ impl ModifierState {
     }
 }
 
+/// A key pressed with exactly these modifiers held, e.g. `ctrl+alt+shift+p`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    modifiers: ModifierState,
+    key: KeyCode,
+}
+
+impl Chord {
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        event.event_type == EventType::KeyDown
+            && event.key_code == self.key
+            && event.modifiers == self.modifiers
+    }
+}
+
+impl FromStr for Chord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Chord(s.to_string());
+        let lower = s.to_lowercase();
+        let mut parts: Vec<&str> = lower.split('+').map(str::trim).collect();
+        let key: KeyCode = parts
+            .pop()
+            .and_then(|k| k.parse().ok())
+            .ok_or_else(invalid)?;
+        if key.is_modifier() {
+            return Err(invalid());
+        }
+
+        let mut modifiers = ModifierState::empty();
+        for part in parts {
+            match part {
+                "ctrl" | "control" => modifiers.set_ctrl(true),
+                "alt" => modifiers.set_alt(true),
+                "shift" => modifiers.set_shift(true),
+                "super" | "meta" | "win" => modifiers.set_super(true),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(Self { modifiers, key })
+    }
+}
+
+/// Holds back modifier presses until it's clear they aren't the start of
+/// `chord`, so the keys pressed to pause or resume aren't counted as typing.
+#[derive(Debug)]
+pub struct ChordWatch {
+    chord: Chord,
+    held_back: Vec<KeyEvent>,
+}
+
+impl ChordWatch {
+    pub fn new(chord: Chord) -> Self {
+        Self {
+            chord,
+            held_back: Vec::new(),
+        }
+    }
+
+    /// The events to process now, oldest first, or `None` if `event`
+    /// completes the chord.
+    pub fn feed(&mut self, event: KeyEvent) -> Option<Vec<KeyEvent>> {
+        if self.chord.matches(&event) {
+            self.held_back.clear();
+            return None;
+        }
+        if event.key_code.is_modifier() && event.event_type != EventType::KeyUp {
+            self.held_back.push(event);
+            return Some(Vec::new());
+        }
+        let mut events = std::mem::take(&mut self.held_back);
+        events.push(event);
+        Some(events)
+    }
+}
+
+/// Stable identity of the keyboard an event came from, e.g.
+/// `046d:c52b:Logitech USB Receiver` (vendor:product:name).
+pub type DeviceId = Arc<str>;
+
 #[derive(Debug, Clone)]
 pub struct KeyEvent {
     pub key_code: KeyCode,
     pub event_type: EventType,
     pub timestamp: Instant,
     pub modifiers: ModifierState,
+    /// `None` when the platform can't tell keyboards apart
+    pub device: Option<DeviceId>,
 }
 
 impl KeyEvent {
@@ -577,8 +722,14 @@ impl KeyEvent {
             event_type,
             timestamp: Instant::now(),
             modifiers,
+            device: None,
         }
     }
+
+    pub fn with_device(mut self, device: DeviceId) -> Self {
+        self.device = Some(device);
+        self
+    }
 }
 
 #[cfg(test)]
@@ -595,6 +746,7 @@ mod tests {
             KeyCode::LCtrl,
             KeyCode::Space,
             KeyCode::Numpad5,
+            KeyCode::IntlBackslash,
             KeyCode::Unknown(999),
         ];
 
@@ -619,4 +771,53 @@ mod tests {
         mods.set_alt(true);
         assert_eq!(mods.combo_prefix(), "alt+ctrl+shift+");
     }
+
+    #[test]
+    fn modifier_state_union() {
+        let mut ctrl = ModifierState::empty();
+        ctrl.set_ctrl(true);
+        let mut shift = ModifierState::empty();
+        shift.set_shift(true);
+
+        let both = ctrl.union(shift);
+        assert!(both.has_ctrl() && both.has_shift());
+        assert_eq!(both.union(ModifierState::empty()), both);
+    }
+
+    #[test]
+    fn chord_matches_exact_modifiers() {
+        let chord: Chord = "Shift+Ctrl+Alt+P".parse().unwrap();
+        let mut mods = ModifierState::empty();
+        mods.set_ctrl(true);
+        mods.set_alt(true);
+        assert!(!chord.matches(&KeyEvent::new(KeyCode::P, EventType::KeyDown, mods)));
+        mods.set_shift(true);
+        assert!(chord.matches(&KeyEvent::new(KeyCode::P, EventType::KeyDown, mods)));
+        assert!(!chord.matches(&KeyEvent::new(KeyCode::P, EventType::KeyUp, mods)));
+
+        assert!("ctrl+shift".parse::<Chord>().is_err());
+        assert!("hyper+p".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn chord_presses_are_held_back_and_dropped() {
+        let mut watch = ChordWatch::new("ctrl+alt+p".parse().unwrap());
+        let mut mods = ModifierState::empty();
+        mods.set_ctrl(true);
+        let ctrl = KeyEvent::new(KeyCode::LCtrl, EventType::KeyDown, mods);
+        mods.set_alt(true);
+        let alt = KeyEvent::new(KeyCode::LAlt, EventType::KeyDown, mods);
+
+        assert_eq!(watch.feed(ctrl.clone()).map(|e| e.len()), Some(0));
+        assert_eq!(watch.feed(alt).map(|e| e.len()), Some(0));
+        let p = KeyEvent::new(KeyCode::P, EventType::KeyDown, mods);
+        assert!(watch.feed(p).is_none());
+
+        // ctrl+s is a shortcut, so ctrl is let through with it
+        watch.feed(ctrl.clone());
+        let s = KeyEvent::new(KeyCode::S, EventType::KeyDown, ctrl.modifiers);
+        let events = watch.feed(s).unwrap();
+        let keys: Vec<_> = events.iter().map(|e| e.key_code).collect();
+        assert_eq!(keys, [KeyCode::LCtrl, KeyCode::S]);
+    }
 }
@@ -137,6 +137,15 @@ pub fn map_evdev(key: Key) -> KeyCode {
         // Misc
         Key::KEY_COMPOSE => KeyCode::Menu,
 
+        // International
+        Key::KEY_102ND => KeyCode::IntlBackslash,
+        Key::KEY_RO => KeyCode::IntlRo,
+        Key::KEY_YEN => KeyCode::IntlYen,
+        Key::KEY_HENKAN => KeyCode::Henkan,
+        Key::KEY_MUHENKAN => KeyCode::Muhenkan,
+        Key::KEY_KATAKANAHIRAGANA => KeyCode::KatakanaHiragana,
+        Key::KEY_ZENKAKUHANKAKU => KeyCode::ZenkakuHankaku,
+
         // Unknown
         _ => KeyCode::Unknown(key.code() as u32),
     }
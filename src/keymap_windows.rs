@@ -140,6 +140,14 @@ pub fn map_vk_extended(vk: u32, is_extended: bool) -> KeyCode {
         // Misc
         0x5D => KeyCode::Menu, // VK_APPS
 
+        // International
+        0xE2 => KeyCode::IntlBackslash,    // VK_OEM_102
+        0xC1 => KeyCode::IntlRo,           // VK_ABNT_C1
+        0x1C => KeyCode::Henkan,           // VK_CONVERT
+        0x1D => KeyCode::Muhenkan,         // VK_NONCONVERT
+        0x15 => KeyCode::KatakanaHiragana, // VK_KANA
+        0x19 => KeyCode::ZenkakuHankaku,   // VK_KANJI
+
         // Unknown
         _ => KeyCode::Unknown(vk),
     }
@@ -1,26 +1,76 @@
+use crate::config::{DeviceInfo, DeviceRules, DeviceVerdict};
 use crate::error::Error;
-use crate::keycode::{EventType, KeyEvent, ModifierState};
+use crate::keycode::{DeviceId, EventType, KeyEvent, ModifierState};
 use crate::keymap_linux::{map_evdev, update_modifier_state};
 use evdev::{Device, InputEventKind, Key};
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
 use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{mpsc::Sender, Arc, Mutex};
 use std::thread;
 
-pub fn find_keyboards() -> Result<Vec<Device>, Error> {
-    let mut keyboards = Vec::new();
+const INPUT_DIR: &str = "/dev/input";
+
+pub struct Keyboard {
+    pub path: PathBuf,
+    pub device: Device,
+}
 
-    for entry in fs::read_dir("/dev/input")? {
-        let entry = entry?;
-        let path = entry.path();
+/// Keyboards currently being captured, keyed by device node.
+///
+/// Each entry holds the modifiers pressed on that keyboard. The effective
+/// modifier state is the union of all entries, so shortcuts still work when
+/// modifier and key are on different keyboards, and unplugging a keyboard
+/// mid-chord can't leave a modifier stuck down.
+pub type Attached = Arc<Mutex<HashMap<PathBuf, Attachment>>>;
 
-        if !path.to_string_lossy().contains("event") {
-            continue;
+pub struct Attachment {
+    generation: u64,
+    name: String,
+    id: DeviceId,
+    port: Option<String>,
+    modifiers: ModifierState,
+}
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// An input device that looks like a keyboard, and what the configured
+/// rules decide about it.
+pub struct Candidate {
+    pub path: PathBuf,
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+    pub phys: Option<String>,
+    pub verdict: DeviceVerdict,
+}
+
+/// The `[devices]` rules, and the nodes they've turned away. Each node is
+/// reported once, not again every time udev touches it.
+pub struct DeviceFilter {
+    rules: DeviceRules,
+    ignored: HashSet<PathBuf>,
+}
+
+impl DeviceFilter {
+    pub fn new(rules: DeviceRules) -> Self {
+        Self {
+            rules,
+            ignored: HashSet::new(),
         }
+    }
+}
 
-        if let Ok(device) = Device::open(&path) {
-            if is_keyboard(&device) {
-                keyboards.push(device);
-            }
+pub fn find_keyboards(filter: &mut DeviceFilter) -> Result<Vec<Keyboard>, Error> {
+    let mut keyboards = Vec::new();
+
+    for path in event_nodes()? {
+        if let Some(keyboard) = open_keyboard(&path, filter) {
+            keyboards.push(keyboard);
         }
     }
 
@@ -31,6 +81,115 @@ pub fn find_keyboards() -> Result<Vec<Device>, Error> {
     Ok(keyboards)
 }
 
+/// Every keyboard-like device that can be opened, whether or not the rules
+/// would capture it.
+pub fn list_candidates(rules: &DeviceRules) -> Result<Vec<Candidate>, Error> {
+    let mut candidates = Vec::new();
+
+    for path in event_nodes()? {
+        let Ok(device) = Device::open(&path) else {
+            continue;
+        };
+        if !is_keyboard(&device) {
+            continue;
+        }
+
+        let id = device.input_id();
+        let verdict = rules.evaluate(&device_info(&device));
+        candidates.push(Candidate {
+            path,
+            name: device.name().unwrap_or("unknown").to_string(),
+            vendor: id.vendor(),
+            product: id.product(),
+            phys: device.physical_path().map(str::to_string),
+            verdict,
+        });
+    }
+
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(candidates)
+}
+
+fn event_nodes() -> Result<Vec<PathBuf>, Error> {
+    let mut nodes = Vec::new();
+    for entry in fs::read_dir(INPUT_DIR)? {
+        let path = entry?.path();
+        if is_event_node(&path) {
+            nodes.push(path);
+        }
+    }
+    Ok(nodes)
+}
+
+fn is_event_node(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy().starts_with("event"))
+        .unwrap_or(false)
+}
+
+fn open_keyboard(path: &Path, filter: &mut DeviceFilter) -> Option<Keyboard> {
+    let device = Device::open(path).ok()?;
+    if !is_keyboard(&device) {
+        return None;
+    }
+
+    let reason = match filter.rules.evaluate(&device_info(&device)) {
+        DeviceVerdict::Capture => None,
+        DeviceVerdict::NotIncluded => Some("not in [devices] include rules".to_string()),
+        DeviceVerdict::Excluded(idx) => Some(format!(
+            "excluded by [devices] rule {}",
+            filter.rules.exclude[idx]
+        )),
+    };
+    if let Some(reason) = reason {
+        if filter.ignored.insert(path.to_path_buf()) {
+            eprintln!(
+                "ignoring {} ({}): {reason}",
+                device.name().unwrap_or("unknown"),
+                path.display()
+            );
+        }
+        return None;
+    }
+
+    Some(Keyboard {
+        path: path.to_path_buf(),
+        device,
+    })
+}
+
+/// Identity that survives replugging and moving between USB ports, unlike the
+/// `/dev/input/eventN` node or the phys path. Devices that report a serial
+/// number get it after the name, so identical boards stay apart.
+pub fn device_id(device: &Device) -> DeviceId {
+    let id = device.input_id();
+    let name = device.name().unwrap_or("unknown");
+    match device.unique_name().filter(|uniq| !uniq.is_empty()) {
+        Some(uniq) => format!("{:04x}:{:04x}:{name} ({uniq})", id.vendor(), id.product()),
+        None => format!("{:04x}:{:04x}:{name}", id.vendor(), id.product()),
+    }
+    .into()
+}
+
+/// The port a device is plugged into: its phys path without the interface,
+/// which tells apart boards with no serial number without splitting one
+/// board's interfaces.
+fn device_port(device: &Device) -> Option<String> {
+    let phys = device.physical_path()?;
+    let port = phys.rsplit_once("/input").map_or(phys, |(port, _)| port);
+    Some(port.to_string()).filter(|port| !port.is_empty())
+}
+
+fn device_info(device: &Device) -> DeviceInfo<'_> {
+    let id = device.input_id();
+    DeviceInfo {
+        name: device.name().unwrap_or(""),
+        vendor: id.vendor(),
+        product: id.product(),
+        phys: device.physical_path(),
+    }
+}
+
 fn is_keyboard(device: &Device) -> bool {
     let Some(keys) = device.supported_keys() else {
         return false;
@@ -39,79 +198,287 @@ fn is_keyboard(device: &Device) -> bool {
     keys.contains(Key::KEY_A) && keys.contains(Key::KEY_Z) && keys.contains(Key::KEY_SPACE)
 }
 
-pub fn run_capture(devices: Vec<Device>, sender: Sender<KeyEvent>) -> Result<(), Error> {
-    if devices.is_empty() {
-        return Err(Error::NoKeyboards);
+/// Captures from `keyboards` and from any keyboard plugged in later.
+///
+/// Blocks for the lifetime of the daemon. If `/dev/input` can't be watched,
+/// hot-plug is disabled and this returns once every initial keyboard is gone.
+pub fn run_capture(
+    keyboards: Vec<Keyboard>,
+    mut filter: DeviceFilter,
+    sender: Sender<KeyEvent>,
+    attached: Attached,
+) -> Result<(), Error> {
+    let mut handles = Vec::new();
+
+    for keyboard in keyboards {
+        handles.push(attach(keyboard, &sender, &attached));
     }
 
-    let mut handles = Vec::new();
+    let watcher = match DeviceWatcher::new() {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("cannot watch {INPUT_DIR} for new keyboards: {e}, hot-plug disabled");
+            if handles.is_empty() {
+                return Err(Error::NoKeyboards);
+            }
+            for handle in handles {
+                let _ = handle.join();
+            }
+            return Ok(());
+        }
+    };
+
+    // threads clean up after themselves on detach, nothing left to join
+    drop(handles);
+
+    loop {
+        for change in watcher.read_changes()? {
+            let path = Path::new(INPUT_DIR).join(&change.name);
+            if !is_event_node(&path) {
+                continue;
+            }
 
-    // Shared modifier state across all keyboard devices
-    // This ensures shortcuts work correctly when using multiple keyboards
-    let modifiers = Arc::new(Mutex::new(ModifierState::empty()));
-
-    // Spawn a thread for each keyboard device
-    for mut device in devices {
-        let sender = sender.clone();
-        let modifiers = Arc::clone(&modifiers);
-
-        let handle = thread::spawn(move || {
-            loop {
-                match device.fetch_events() {
-                    Ok(events) => {
-                        for event in events {
-                            if let InputEventKind::Key(key) = event.kind() {
-                                let key_code = map_evdev(key);
-                                let value = event.value();
-
-                                let event_type = match value {
-                                    1 => EventType::KeyDown,
-                                    0 => EventType::KeyUp,
-                                    2 => EventType::Repeat,
-                                    _ => continue,
-                                };
-
-                                // Lock modifier state and update it
-                                let current_modifiers = if let Ok(mut mods) = modifiers.lock() {
-                                    if key_code.is_modifier() {
-                                        update_modifier_state(&mut mods, key_code, value == 1);
-                                    }
-                                    *mods
-                                } else {
-                                    // Mutex poisoned, continue with empty modifiers
-                                    ModifierState::empty()
-                                };
-
-                                let key_event = KeyEvent::new(key_code, event_type, current_modifiers);
-
-                                if sender.send(key_event).is_err() {
-                                    // receiver dropped, exit cleanly
-                                    return;
-                                }
-                            }
+            // A new node always means a new device, even if a stale entry for
+            // the same path hasn't been cleaned up yet. Attribute changes fire
+            // when udev fixes up permissions, which is often the first point
+            // the node can be opened, but don't change what a node the rules
+            // turned away is.
+            let already_attached = attached
+                .lock()
+                .map(|a| a.contains_key(&path))
+                .unwrap_or(false);
+            if change.is_attrib() && (already_attached || filter.ignored.contains(&path)) {
+                continue;
+            }
+            if !change.is_attrib() {
+                filter.ignored.remove(&path);
+            }
+
+            if let Some(keyboard) = open_keyboard(&path, &mut filter) {
+                attach(keyboard, &sender, &attached);
+            }
+        }
+    }
+}
+
+fn attach(
+    keyboard: Keyboard,
+    sender: &Sender<KeyEvent>,
+    attached: &Attached,
+) -> thread::JoinHandle<()> {
+    let Keyboard { path, mut device } = keyboard;
+    let name = device.name().unwrap_or("unknown").to_string();
+    let mut id = device_id(&device);
+    let port = device_port(&device);
+    let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+
+    if let Ok(mut attached) = attached.lock() {
+        // an identical board without a serial number is told apart by port
+        let twin = attached
+            .values()
+            .any(|other| other.id == id && other.port != port);
+        if let (true, Some(port)) = (twin, &port) {
+            id = format!("{id} ({port})").into();
+        }
+        attached.insert(
+            path.clone(),
+            Attachment {
+                generation,
+                name: name.clone(),
+                id: id.clone(),
+                port,
+                modifiers: ModifierState::empty(),
+            },
+        );
+    }
+    eprintln!("keyboard attached: {name} ({})", path.display());
+
+    let sender = sender.clone();
+    let attached = Arc::clone(attached);
+
+    thread::spawn(move || {
+        let reason = capture_device(&mut device, &path, &id, &sender, &attached);
+
+        // only remove our own entry, the node may already belong to a new device
+        if let Ok(mut attached) = attached.lock() {
+            if attached.get(&path).map(|a| a.generation) == Some(generation) {
+                attached.remove(&path);
+            }
+        }
+
+        match reason {
+            Some(e) if e.raw_os_error() == Some(libc::ENODEV) => {
+                eprintln!("keyboard detached: {name} ({})", path.display());
+            }
+            Some(e) => {
+                eprintln!(
+                    "keyboard device error: {e}, detaching {name} ({})",
+                    path.display()
+                );
+            }
+            None => {}
+        }
+    })
+}
+
+/// Forwards events from one keyboard until it fails or the receiver is gone.
+/// Returns the device error, or `None` if the receiver was dropped.
+fn capture_device(
+    device: &mut Device,
+    path: &Path,
+    id: &DeviceId,
+    sender: &Sender<KeyEvent>,
+    attached: &Attached,
+) -> Option<io::Error> {
+    loop {
+        let events = match device.fetch_events() {
+            Ok(events) => events,
+            Err(e) => return Some(e),
+        };
+
+        for event in events {
+            let InputEventKind::Key(key) = event.kind() else {
+                continue;
+            };
+
+            let key_code = map_evdev(key);
+            let value = event.value();
+
+            let event_type = match value {
+                1 => EventType::KeyDown,
+                0 => EventType::KeyUp,
+                2 => EventType::Repeat,
+                _ => continue,
+            };
+
+            let current_modifiers = match attached.lock() {
+                Ok(mut attached) => {
+                    if key_code.is_modifier() {
+                        if let Some(entry) = attached.get_mut(path) {
+                            // repeats mean the modifier is still held
+                            update_modifier_state(&mut entry.modifiers, key_code, value != 0);
                         }
                     }
-                    Err(e) => {
-                        eprintln!("keyboard device error: {e}, thread exiting");
-                        return;
-                    }
+                    attached
+                        .values()
+                        .fold(ModifierState::empty(), |acc, a| acc.union(a.modifiers))
                 }
+                // Mutex poisoned, continue with empty modifiers
+                Err(_) => ModifierState::empty(),
+            };
+
+            let key_event =
+                KeyEvent::new(key_code, event_type, current_modifiers).with_device(id.clone());
+
+            if sender.send(key_event).is_err() {
+                // receiver dropped, exit cleanly
+                return None;
             }
-        });
-        handles.push(handle);
+        }
     }
+}
+
+struct DeviceChange {
+    name: String,
+    mask: u32,
+}
 
-    // Wait for all threads to complete (they run indefinitely unless there's an error)
-    for handle in handles {
-        let _ = handle.join();
+impl DeviceChange {
+    fn is_attrib(&self) -> bool {
+        self.mask & libc::IN_ATTRIB != 0 && self.mask & libc::IN_CREATE == 0
     }
+}
 
-    Ok(())
+/// inotify watch on `/dev/input` for nodes being created or having their
+/// permissions changed.
+struct DeviceWatcher {
+    fd: OwnedFd,
 }
 
-pub fn device_names(devices: &[Device]) -> Vec<String> {
-    devices
+impl DeviceWatcher {
+    fn new() -> io::Result<Self> {
+        let raw = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        let dir = CString::new(INPUT_DIR).expect("path has no interior NUL");
+        let wd = unsafe {
+            libc::inotify_add_watch(
+                fd.as_raw_fd(),
+                dir.as_ptr(),
+                libc::IN_CREATE | libc::IN_ATTRIB,
+            )
+        };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { fd })
+    }
+
+    /// Blocks until at least one change is available.
+    fn read_changes(&self) -> io::Result<Vec<DeviceChange>> {
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+        let mut buf = [0u8; 4096];
+
+        let n = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(Vec::new());
+            }
+            return Err(err);
+        }
+
+        let n = n as usize;
+        let mut changes = Vec::new();
+        let mut offset = 0;
+
+        while offset + HEADER <= n {
+            let event: libc::inotify_event = unsafe {
+                std::ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+            };
+            let name_start = offset + HEADER;
+            let name_end = (name_start + event.len as usize).min(n);
+            let name = String::from_utf8_lossy(&buf[name_start..name_end])
+                .trim_end_matches('\0')
+                .to_string();
+
+            if !name.is_empty() {
+                changes.push(DeviceChange {
+                    name,
+                    mask: event.mask,
+                });
+            }
+
+            offset = name_end;
+        }
+
+        Ok(changes)
+    }
+}
+
+pub fn device_names(keyboards: &[Keyboard]) -> Vec<String> {
+    keyboards
         .iter()
-        .map(|d| d.name().unwrap_or("unknown").to_string())
+        .map(|k| k.device.name().unwrap_or("unknown").to_string())
         .collect()
 }
+
+/// Name and id of each keyboard `run_capture` is reading, by name.
+pub fn attached_devices(attached: &Attached) -> Vec<(String, DeviceId)> {
+    let mut devices: Vec<_> = attached
+        .lock()
+        .map(|a| a.values().map(|k| (k.name.clone(), k.id.clone())).collect())
+        .unwrap_or_default();
+    devices.sort();
+    devices
+}
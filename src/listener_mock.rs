@@ -1,4 +1,4 @@
-use crate::keycode::{EventType, KeyCode, KeyEvent, ModifierState};
+use crate::keycode::{DeviceId, EventType, KeyCode, KeyEvent, ModifierState};
 use std::sync::mpsc::Sender;
 use std::thread;
 use std::time::Duration;
@@ -145,6 +145,7 @@ pub fn run_mock(sender: Sender<KeyEvent>) -> Result<(), crate::error::Error> {
     let mut rng = MockRng::new();
     let shortcuts = get_shortcuts();
     let speeds = [TypingSpeed::Fast, TypingSpeed::Normal, TypingSpeed::Slow];
+    let device: DeviceId = "0000:0000:KeyHeat Mock Keyboard".into();
 
     eprintln!("mock listener: generating synthetic key events");
 
@@ -200,12 +201,21 @@ pub fn run_mock(sender: Sender<KeyEvent>) -> Result<(), crate::error::Error> {
                 }
             };
 
-            if sender.send(event).is_err() {
+            let (key, modifiers) = (event.key_code, event.modifiers);
+            if sender.send(event.with_device(device.clone())).is_err() {
                 return Ok(());
             }
 
+            // hold for part of the gap to the next key, then release
             let delay = current_speed.delay_ms(&mut rng);
-            thread::sleep(Duration::from_millis(delay));
+            let hold = rng.range(40, 120).min(delay * 3 / 4);
+            thread::sleep(Duration::from_millis(hold));
+
+            let release = KeyEvent::new(key, EventType::KeyUp, modifiers);
+            if sender.send(release.with_device(device.clone())).is_err() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(delay - hold));
         }
 
         // pause between bursts
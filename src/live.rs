@@ -0,0 +1,289 @@
+//! `keyheat live`: a full-screen view of the running daemon.
+//!
+//! Asks the daemon for its state over the control socket once a second and
+//! redraws. The daemon answers from the aggregator and its own cache of
+//! today's counts, so nothing here reads the database.
+
+use crate::daemon;
+use crate::ipc;
+use crate::report::{self, ColorMode, Layout};
+use crate::timezone::Zone;
+use crate::{format_number, format_shortcut_name};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// WPM readings kept for the sparkline, one per refresh.
+const HISTORY: usize = 600;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+pub fn run(layout: &Layout) -> Result<()> {
+    let is_tty = |fd| unsafe { libc::isatty(fd) } == 1;
+    if !is_tty(libc::STDIN_FILENO) || !is_tty(libc::STDOUT_FILENO) {
+        anyhow::bail!("keyheat live needs a terminal");
+    }
+    // check before taking over the screen
+    let mut last = fetch().context("KeyHeat is not running, start it with `keyheat start`")?;
+    daemon::handle_shutdown_signals()?;
+
+    let color = ColorMode::detect();
+    let zone = Zone::configured();
+    let _screen = Screen::enter()?;
+    let mut history = VecDeque::with_capacity(HISTORY);
+    let mut error = None;
+
+    loop {
+        if error.is_none() {
+            if history.len() == HISTORY {
+                history.pop_front();
+            }
+            history.push_back(last.0.current_wpm);
+        }
+
+        let frame = draw(
+            &last,
+            error.as_deref(),
+            &history,
+            layout,
+            color,
+            zone,
+            terminal_size(),
+        );
+        let mut stdout = std::io::stdout().lock();
+        write!(stdout, "\x1b[H{frame}\x1b[J")?;
+        stdout.flush()?;
+        drop(stdout);
+
+        let next = Instant::now() + REFRESH;
+        loop {
+            if daemon::shutdown_requested() {
+                return Ok(());
+            }
+            let remaining = next.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            if let Some(b'q' | b'Q') = read_key(remaining) {
+                return Ok(());
+            }
+        }
+
+        match fetch() {
+            Ok(state) => {
+                last = state;
+                error = None;
+            }
+            Err(e) => error = Some(format!("{e:#}")),
+        }
+    }
+}
+
+fn fetch() -> Result<(ipc::Status, ipc::Live)> {
+    let response = ipc::request(&ipc::Request::Live)?;
+    if let Some(error) = response.error {
+        anyhow::bail!(error);
+    }
+    match (response.status, response.live) {
+        (Some(status), Some(live)) => Ok((status, live)),
+        _ => anyhow::bail!("daemon sent no live data, try restarting it"),
+    }
+}
+
+/// One screenful: `columns` wide, at most `rows` lines.
+fn draw(
+    (status, live): &(ipc::Status, ipc::Live),
+    error: Option<&str>,
+    history: &VecDeque<f64>,
+    layout: &Layout,
+    color: ColorMode,
+    zone: Zone,
+    (columns, rows): (usize, usize),
+) -> String {
+    let local = |t: DateTime<Utc>| zone.to_local(t);
+    let width = columns.saturating_sub(2);
+    let mut lines = Vec::new();
+
+    let clock = local(Utc::now()).format("%H:%M:%S").to_string();
+    let title = "KeyHeat live";
+    let help = "q to quit";
+    let gap = width.saturating_sub(title.len() + clock.len() + help.len() + 3);
+    let gap = " ".repeat(gap);
+    lines.push(format!(
+        "{BOLD}{title}{RESET}{gap}  {clock}  {DIM}{help}{RESET}"
+    ));
+    lines.push(match error {
+        Some(error) => format!("daemon not responding: {error}"),
+        None => String::new(),
+    });
+
+    let peak = history.iter().copied().fold(0.0, f64::max);
+    lines.push(format!(
+        "{BOLD}WPM{RESET}      {:.0} now, {peak:.0} peak",
+        status.current_wpm
+    ));
+    let values: Vec<f64> = history.iter().copied().collect();
+    lines.push(sparkline(&values, width));
+    lines.push(String::new());
+
+    let session = if let Some(pause) = &status.paused {
+        let until = match pause.until {
+            Some(until) => format!("until {}", local(until).format("%H:%M")),
+            None => "until `keyheat resume`".to_string(),
+        };
+        format!(
+            "paused since {}, {until}",
+            local(pause.since).format("%H:%M")
+        )
+    } else if let Some(session) = &status.session {
+        let elapsed = session
+            .started_at
+            .map(|t| (Utc::now() - t).num_seconds().max(0))
+            .unwrap_or(0);
+        format!(
+            "#{}  {:02}:{:02}:{:02}  {} keystrokes",
+            session.id,
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60,
+            format_number(session.keystrokes)
+        )
+    } else {
+        "idle".to_string()
+    };
+    lines.push(format!("{BOLD}Session{RESET}  {session}"));
+    lines.push(format!(
+        "{BOLD}Today{RESET}    {} keystrokes",
+        format_number(status.today_keystrokes)
+    ));
+    lines.push(String::new());
+
+    lines.extend(report::render_keyboard(layout, &live.keys, width, color));
+    lines.push(String::new());
+
+    lines.push(format!("{BOLD}Recent shortcuts{RESET}"));
+    if live.recent_shortcuts.is_empty() {
+        lines.push(format!("{DIM}none yet{RESET}"));
+    }
+    for shortcut in live.recent_shortcuts.iter().rev() {
+        lines.push(format!(
+            "{DIM}{}{RESET}  {}",
+            local(shortcut.at).format("%H:%M:%S"),
+            format_shortcut_name(&shortcut.combo)
+        ));
+    }
+
+    let mut frame = String::new();
+    for line in lines.iter().take(rows) {
+        // clear what the last frame left to the right
+        let _ = writeln!(frame, " {line}\x1b[K");
+    }
+    frame
+}
+
+/// The last `width` readings as bars scaled to the highest of them. Zero
+/// is left blank so idle stretches show as gaps.
+fn sparkline(values: &[f64], width: usize) -> String {
+    let shown = &values[values.len().saturating_sub(width)..];
+    let max = shown.iter().copied().fold(0.0, f64::max);
+    shown
+        .iter()
+        .map(|&v| {
+            if v <= 0.0 || max <= 0.0 {
+                ' '
+            } else {
+                BARS[((v / max) * (BARS.len() - 1) as f64).round() as usize]
+            }
+        })
+        .collect()
+}
+
+/// Columns and rows, or 80x24 if the terminal won't say.
+fn terminal_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 && size.ws_row > 0 {
+        (size.ws_col as usize, size.ws_row as usize)
+    } else {
+        (80, 24)
+    }
+}
+
+/// Waits up to `timeout` for a key press.
+fn read_key(timeout: Duration) -> Option<u8> {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // a signal cuts the wait short, which is what Ctrl+C needs
+    let ready = unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) };
+    if ready <= 0 {
+        return None;
+    }
+    let mut byte = 0u8;
+    let read = unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) };
+    (read == 1).then_some(byte)
+}
+
+/// The alternate screen with the cursor hidden and keys read unbuffered and
+/// unechoed. Dropping it restores the terminal, panics included.
+struct Screen {
+    saved: libc::termios,
+}
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("failed to read terminal settings");
+        }
+        // ISIG stays on so Ctrl+C still quits
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("failed to change terminal settings");
+        }
+
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "\x1b[?1049h\x1b[?25l");
+        let _ = stdout.flush();
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparkline_scales_to_the_visible_peak() {
+        assert_eq!(sparkline(&[0.0, 10.0, 20.0, 40.0], 3), "▃▅█");
+        assert_eq!(sparkline(&[100.0, 0.0, 35.0, 70.0], 10), "█ ▃▆");
+        assert_eq!(sparkline(&[], 5), "");
+    }
+}
@@ -1,13 +1,19 @@
 mod aggregator;
+mod bar;
 mod config;
 mod daemon;
+mod dashboard;
 mod error;
+mod export;
+mod ipc;
 mod keycode;
 
 #[cfg(target_os = "linux")]
 mod keymap_linux;
 #[cfg(target_os = "linux")]
 mod listener_linux;
+#[cfg(target_os = "linux")]
+mod live;
 
 #[cfg(target_os = "windows")]
 mod keymap_windows;
@@ -15,14 +21,19 @@ mod keymap_windows;
 mod listener_windows;
 
 mod listener_mock;
+mod metrics;
 mod report;
+mod service;
 mod storage;
+mod timezone;
 
 use aggregator::Aggregator;
 use anyhow::{Context, Result};
-use chrono::{Local, NaiveDate, Utc};
-use clap::{Parser, Subcommand, ValueEnum};
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use keycode::KeyEvent;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
@@ -43,23 +54,139 @@ enum Commands {
     Stop,
     /// Show daemon status
     Status,
+    /// Watch WPM, the session and a keyboard heatmap update as you type
+    Live,
+    /// Print live status for waybar, i3bar, polybar or i3blocks, one update per line
+    Bar {
+        /// Output for this kind of bar
+        #[arg(long, value_enum, default_value = "waybar")]
+        protocol: bar::Protocol,
+        /// What to show, e.g. "{wpm} WPM, {session}" (default from the config file)
+        #[arg(long)]
+        format: Option<String>,
+        /// Time between updates, e.g. 1s (default from the config file)
+        #[arg(long, value_parser = parse_duration)]
+        interval: Option<Duration>,
+    },
+    /// Open the web dashboard served by the daemon
+    Dashboard,
+    /// Stop recording until `keyheat resume`, or for a while with --for
+    Pause {
+        /// Resume on its own after this long, e.g. 90s, 10m or 1h30m
+        #[arg(long = "for", value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// Start recording again after a pause
+    Resume,
+    /// List keyboard-like input devices and whether they would be captured
+    Devices,
     /// Run the capture loop in the foreground
     Run {
         /// Use mock listener for testing (generates synthetic events)
         #[arg(long)]
         mock: bool,
     },
-    /// Generate a weekly report
+    /// Generate a report, for the current week unless a range is given
     Report {
-        /// Week to report on (YYYY-Www format, e.g. 2025-W06)
+        #[command(flatten)]
+        range: RangeArgs,
+        /// Only include keystrokes from keyboards whose name or id contains this text
+        #[arg(long)]
+        device: Option<String>,
+        /// Only include one machine's data, by name; "local" is this machine
+        #[arg(long)]
+        machine: Option<String>,
+        /// Keyboard layout as physical[:logical], e.g. iso, ansi:dvorak, split:colemak-dh, corne.json
         #[arg(long)]
-        week: Option<String>,
+        layout: Option<report::LayoutSpec>,
         /// Output format
         #[arg(long, value_enum, default_value = "terminal")]
         format: ReportFormat,
+        /// Write the JSON or HTML report to this file instead of the default
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Export raw key counts, shortcuts, sessions and WPM samples, all of them unless a range is given
+    Export {
+        #[command(flatten)]
+        range: RangeArgs,
+        /// Output format
+        #[arg(long, value_enum, default_value = "csv")]
+        format: ExportFormat,
+        /// Directory for the CSV files (default ./keyheat-export), or file for JSON Lines (default stdout)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Roll up old WPM samples and key counts, as set under [retention] in the config
+    Compact,
+    /// Close sessions a crash left open, estimating when they ended
+    Repair,
+    /// Merge another machine's keyheat.db into this one; importing it again replaces the earlier import
+    Import {
+        /// Path to the other machine's database
+        path: PathBuf,
+        /// Name to show for that machine (default: its hostname)
+        #[arg(long)]
+        machine: Option<String>,
+    },
+    /// Run the daemon as a systemd user service instead of with start/stop
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
     },
 }
 
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Write ~/.config/systemd/user/keyheat.service, then enable and start it
+    Install,
+    /// Stop and disable the service and remove its unit
+    Uninstall,
+    /// Show whether the service is installed and running
+    Status,
+}
+
+/// Which days a report covers. At most one of these may be given.
+#[derive(Args)]
+#[group(skip)]
+struct RangeArgs {
+    /// Week to report on (YYYY-Www format, e.g. 2025-W06)
+    #[arg(long, group = "range", value_parser = report::parse_week)]
+    week: Option<NaiveDate>,
+    /// Single day (YYYY-MM-DD, today or yesterday)
+    #[arg(long, group = "range", value_parser = report::parse_day)]
+    day: Option<NaiveDate>,
+    /// Calendar month (YYYY-MM)
+    #[arg(long, group = "range", value_parser = report::parse_month)]
+    month: Option<NaiveDate>,
+    /// Calendar year (YYYY)
+    #[arg(long, group = "range", value_parser = clap::value_parser!(i32).range(1970..=9999))]
+    year: Option<i32>,
+    /// First day of a custom range (YYYY-MM-DD), through --to or today
+    #[arg(long, group = "range", value_parser = report::parse_day)]
+    from: Option<NaiveDate>,
+    /// Last day of a custom range (YYYY-MM-DD)
+    #[arg(long, requires = "from", value_parser = report::parse_day)]
+    to: Option<NaiveDate>,
+    /// Recent span ending today, e.g. 30d, 4w, 6m or 1y
+    #[arg(long, group = "range")]
+    last: Option<report::Span>,
+}
+
+impl From<RangeArgs> for report::RangeChoice {
+    fn from(args: RangeArgs) -> Self {
+        report::RangeChoice {
+            week: args.week,
+            day: args.day,
+            month: args.month,
+            year: args.year,
+            from: args.from,
+            to: args.to,
+            last: args.last,
+        }
+    }
+}
+
 #[derive(Clone, ValueEnum)]
 enum ReportFormat {
     Terminal,
@@ -67,6 +194,14 @@ enum ReportFormat {
     Html,
 }
 
+#[derive(Clone, ValueEnum)]
+enum ExportFormat {
+    /// one file per table
+    Csv,
+    /// one JSON object per line, tagged with its table
+    Jsonl,
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
@@ -74,17 +209,49 @@ fn main() -> Result<()> {
         Commands::Start => cmd_start(),
         Commands::Stop => cmd_stop(),
         Commands::Status => cmd_status(),
+        Commands::Devices => cmd_devices(),
         Commands::Run { mock } => run_foreground(mock),
-        Commands::Report { week, format } => generate_report(week, format),
+        Commands::Report {
+            range,
+            device,
+            machine,
+            layout,
+            format,
+            output,
+        } => generate_report(range, device, machine, layout, format, output),
+        Commands::Export {
+            range,
+            format,
+            output,
+        } => cmd_export(range, format, output),
+        Commands::Compact => cmd_compact(),
+        Commands::Repair => cmd_repair(),
+        Commands::Pause { duration } => cmd_pause(duration),
+        Commands::Resume => cmd_resume(),
+        Commands::Live => cmd_live(),
+        Commands::Bar {
+            protocol,
+            format,
+            interval,
+        } => cmd_bar(protocol, format, interval),
+        Commands::Dashboard => cmd_dashboard(),
+        Commands::Service { action } => cmd_service(action),
+        Commands::Import { path, machine } => cmd_import(&path, machine.as_deref()),
     }
 }
 
 fn cmd_start() -> Result<()> {
-    if let Some(pid) = daemon::check_running() {
+    if let Some(pid) = daemon::check_running().or_else(|| query_status().map(|s| s.pid)) {
         println!("KeyHeat is already running (PID {pid})");
         return Ok(());
     }
 
+    if service::is_installed() {
+        service::start()?;
+        println!("KeyHeat started ({})", service::UNIT_NAME);
+        return Ok(());
+    }
+
     let pid = daemon::spawn_daemon()?;
     daemon::write_pid(pid)?;
     println!("KeyHeat started (PID {pid})");
@@ -92,90 +259,557 @@ fn cmd_start() -> Result<()> {
 }
 
 fn cmd_stop() -> Result<()> {
-    match daemon::check_running() {
-        Some(pid) => {
-            daemon::kill_process(pid)?;
-            daemon::remove_pid_file()?;
-            println!("KeyHeat stopped.");
-            Ok(())
+    // a daemon started with `run` has no PID file, but still answers
+    let Some(pid) = daemon::check_running().or_else(|| query_status().map(|s| s.pid)) else {
+        println!("KeyHeat is not running.");
+        return Ok(());
+    };
+
+    let asked = ipc::request(&ipc::Request::Stop).is_ok_and(|response| response.ok);
+    if !asked {
+        daemon::kill_process(pid)?;
+    }
+    // the daemon flushes and closes its session before exiting
+    if !daemon::wait_for_exit(pid, STOP_TIMEOUT) {
+        anyhow::bail!(
+            "KeyHeat (PID {pid}) did not exit within {} seconds",
+            STOP_TIMEOUT.as_secs()
+        );
+    }
+    daemon::remove_pid_file()?;
+    println!("KeyHeat stopped.");
+    Ok(())
+}
+
+/// How long `stop` waits for the daemon's final flush.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Asks the running daemon what it's doing, if one is listening.
+fn query_status() -> Option<ipc::Status> {
+    ipc::request(&ipc::Request::Status).ok()?.status
+}
+
+fn cmd_status() -> Result<()> {
+    if let Some(status) = query_status() {
+        print_daemon_status(&status);
+    } else {
+        match daemon::check_running() {
+            Some(pid) => {
+                let uptime = daemon::get_process_start_time(pid)
+                    .map(daemon::format_uptime)
+                    .unwrap_or_else(|| "unknown".to_string());
+                println!("KeyHeat is running (PID {pid}, uptime {uptime})");
+            }
+            None => {
+                println!("KeyHeat is not running.");
+            }
         }
-        None => {
-            println!("KeyHeat is not running.");
-            Ok(())
+    }
+
+    let storage = storage::Storage::open().context("failed to open database")?;
+    let machines = storage.machines().context("failed to read machines")?;
+    for machine in machines.iter().skip(1) {
+        if let Some(imported_at) = machine.imported_at {
+            println!(
+                "Imported from {} on {}",
+                machine.name,
+                timezone::Zone::configured()
+                    .to_local(imported_at)
+                    .format("%b %-d, %Y %H:%M")
+            );
         }
     }
+    Ok(())
 }
 
-fn cmd_status() -> Result<()> {
-    match daemon::check_running() {
-        Some(pid) => {
-            let uptime = daemon::get_process_start_time(pid)
-                .map(daemon::format_uptime)
-                .unwrap_or_else(|| "unknown".to_string());
-            println!("KeyHeat is running (PID {pid}, uptime {uptime})");
+fn print_daemon_status(status: &ipc::Status) {
+    let zone = timezone::Zone::configured();
+    let uptime = daemon::format_uptime(status.started_at.into());
+    println!(
+        "KeyHeat {} is running (PID {}, uptime {uptime})",
+        status.version, status.pid
+    );
+
+    if let Some(pause) = &status.paused {
+        let local = |t: DateTime<Utc>| zone.to_local(t).format("%H:%M");
+        let until = match pause.until {
+            Some(until) => format!("until {}", local(until)),
+            None => "until `keyheat resume`".to_string(),
+        };
+        println!(
+            "  Paused:      since {}, {until} (by {})",
+            local(pause.since),
+            pause.reason
+        );
+    }
+    if let Some(session) = &status.session {
+        let since = session
+            .started_at
+            .map(|t| format!(" since {}", zone.to_local(t).format("%H:%M")))
+            .unwrap_or_default();
+        println!(
+            "  Session:     #{}, {} keystrokes{since}",
+            session.id,
+            format_number(session.keystrokes)
+        );
+        println!("  Current WPM: {:.0}", status.current_wpm);
+    } else {
+        println!("  Session:     idle");
+    }
+    println!(
+        "  Today:       {} keystrokes",
+        format_number(status.today_keystrokes)
+    );
+
+    if status.devices.is_empty() {
+        println!("  Keyboards:   none attached");
+    } else {
+        let names: Vec<_> = status.devices.iter().map(|d| d.name.as_str()).collect();
+        println!("  Keyboards:   {}", names.join(", "));
+    }
+
+    let last_flush = status
+        .last_flush
+        .map(|t| zone.to_local(t).format("%H:%M:%S").to_string())
+        .unwrap_or_else(|| "not yet".to_string());
+    println!("  Last flush:  {last_flush}");
+
+    if status.errors.database_total > 0 {
+        println!(
+            "  DB errors:   {} ({} in a row)",
+            status.errors.database_total, status.errors.database_consecutive
+        );
+    }
+}
+
+fn cmd_service(action: ServiceAction) -> Result<()> {
+    match action {
+        ServiceAction::Install => {
+            // systemd has to own the daemon, or it would start a second one
+            if let Some(pid) = daemon::check_running().or_else(|| query_status().map(|s| s.pid)) {
+                anyhow::bail!(
+                    "KeyHeat is already running (PID {pid}), run `keyheat stop` before installing the service"
+                );
+            }
+            let path = service::install()?;
+            println!("Installed {}", path.display());
+            println!("KeyHeat now starts with your session. Logs: journalctl --user -u keyheat");
         }
-        None => {
-            println!("KeyHeat is not running.");
+        ServiceAction::Uninstall => {
+            let path = service::uninstall()?;
+            println!("Removed {}", path.display());
+        }
+        ServiceAction::Status => {
+            let path = service::unit_path();
+            if !service::is_installed() {
+                println!("Not installed ({} does not exist).", path.display());
+                return Ok(());
+            }
+            println!("Installed at {}", path.display());
+            service::print_status()?;
         }
     }
     Ok(())
 }
 
-fn generate_report(week: Option<String>, format: ReportFormat) -> Result<()> {
-    let storage = storage::Storage::open().context("failed to open database")?;
+fn cmd_pause(duration: Option<Duration>) -> Result<()> {
+    let request = ipc::Request::Pause {
+        for_secs: duration.map(|d| d.as_secs()),
+    };
+    let response = ipc::request(&request).context("KeyHeat is not running")?;
+    if let Some(error) = response.error {
+        anyhow::bail!(error);
+    }
 
-    let week_date = match week {
-        Some(w) => {
-            Some(parse_iso_week(&w).context("invalid week format, use YYYY-Www (e.g. 2025-W06)")?)
+    match duration {
+        Some(duration) => {
+            let until = timezone::Zone::configured().to_local(Utc::now()) + duration;
+            println!("Paused until {}.", until.format("%H:%M"));
         }
-        None => None,
+        None => println!("Paused. Run `keyheat resume` to record again."),
+    }
+    Ok(())
+}
+
+fn cmd_resume() -> Result<()> {
+    let response = ipc::request(&ipc::Request::Resume).context("KeyHeat is not running")?;
+    if let Some(error) = response.error {
+        anyhow::bail!(error);
+    }
+    println!("Resumed.");
+    Ok(())
+}
+
+fn cmd_live() -> Result<()> {
+    let config = config::Config::load();
+    let layout = report::Layout::build(&report::configured_layout(&config)?)
+        .context("failed to load keyboard layout")?;
+
+    #[cfg(target_os = "linux")]
+    return live::run(&layout);
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = layout;
+        anyhow::bail!("keyheat live is only supported on Linux")
+    }
+}
+
+fn cmd_bar(
+    protocol: bar::Protocol,
+    format: Option<String>,
+    interval: Option<Duration>,
+) -> Result<()> {
+    let config = config::Config::load();
+    // --format and --interval win over the config file
+    let format: bar::Format = match format {
+        Some(format) => format.parse().context("invalid --format")?,
+        None => config.bar.format.parse().with_context(|| {
+            format!(
+                "invalid bar format in {}",
+                config::Config::config_path().display()
+            )
+        })?,
     };
+    let interval = interval.unwrap_or(Duration::from_secs(config.bar.interval_secs.max(1)));
+    bar::run(protocol, format, interval)
+}
+
+fn cmd_dashboard() -> Result<()> {
+    let config = config::Config::load();
+    if !config.dashboard.enabled {
+        anyhow::bail!(
+            "the dashboard is off, set enabled = true under [dashboard] in {} and restart KeyHeat",
+            config::Config::config_path().display()
+        );
+    }
+    if query_status().is_none() && daemon::check_running().is_none() {
+        anyhow::bail!("KeyHeat is not running, start it with `keyheat start`");
+    }
+
+    if config.dashboard.unix_socket {
+        let path = dashboard::socket_path();
+        println!("The dashboard is on {}. Try:", path.display());
+        println!(
+            "  curl --unix-socket {} http://localhost/api/status",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let token = dashboard::token(&config.dashboard)
+        .context("no dashboard token yet, restart KeyHeat to create one")?;
+    let url = format!("http://127.0.0.1:{}/?token={token}", config.dashboard.port);
+    if let Err(e) = open::that(&url) {
+        eprintln!("Could not open browser: {e}");
+        eprintln!("Open it manually: {url}");
+    }
+    Ok(())
+}
+
+/// The layout set in the config file, or ANSI QWERTY.
+/// Parses a length of time like `90s`, `10m` or `1h30m`.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let err = || format!("invalid duration '{s}', expected something like 90s, 10m or 1h30m");
+    let mut secs = 0u64;
+    let mut rest = s.trim();
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(err)?;
+        let count: u64 = rest[..split].parse().map_err(|_| err())?;
+        let unit = match rest[split..].chars().next() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some('d') => 24 * 60 * 60,
+            _ => return Err(err()),
+        };
+        secs = count
+            .checked_mul(unit)
+            .and_then(|n| n.checked_add(secs))
+            .ok_or_else(err)?;
+        rest = &rest[split + 1..];
+    }
+    if secs == 0 {
+        return Err(err());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+#[cfg(target_os = "linux")]
+fn cmd_devices() -> Result<()> {
+    use config::DeviceVerdict;
+
+    let config = config::Config::load();
+    let candidates = listener_linux::list_candidates(&config.devices)
+        .context("failed to list input devices")?;
+    if let Err(e) = config.devices.validate() {
+        println!("warning: {e}, that rule never matches");
+        println!();
+    }
+
+    if candidates.is_empty() {
+        println!("No keyboard devices found.");
+        println!("If you have a keyboard attached, check that you are in the `input` group.");
+        return Ok(());
+    }
+
+    for candidate in &candidates {
+        let status = match candidate.verdict {
+            DeviceVerdict::Capture => "capture".to_string(),
+            DeviceVerdict::NotIncluded => "skip (no include rule matches)".to_string(),
+            DeviceVerdict::Excluded(idx) => {
+                format!("skip (excluded by {})", config.devices.exclude[idx])
+            }
+        };
+
+        println!("{}", candidate.name);
+        println!("  path:   {}", candidate.path.display());
+        println!("  id:     {:04x}:{:04x}", candidate.vendor, candidate.product);
+        println!("  phys:   {}", candidate.phys.as_deref().unwrap_or("-"));
+        println!("  status: {status}");
+        println!();
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cmd_devices() -> Result<()> {
+    anyhow::bail!("listing devices is only supported on Linux")
+}
+
+fn generate_report(
+    range: RangeArgs,
+    device: Option<String>,
+    machine: Option<String>,
+    layout: Option<report::LayoutSpec>,
+    format: ReportFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    if output.is_some() && matches!(format, ReportFormat::Terminal) {
+        anyhow::bail!("--output needs --format json or --format html");
+    }
+
+    let storage = storage::Storage::open().context("failed to open database")?;
+
+    let range = report::RangeChoice::from(range)
+        .into_spec()
+        .map_err(anyhow::Error::msg)?;
+    let config = config::Config::load();
+    let options = report::ReportOptions::build(&storage, &config, range, device, machine, layout)?;
 
     let report_data =
-        report::build_report(storage.connection(), week_date).context("failed to build report")?;
+        report::build_report(storage.connection(), &options).context("failed to build report")?;
+
+    // daily counts can't be re-bucketed, so say when they're from another zone
+    let (start, end) = (report_data.range.start, report_data.range.end);
+    let recorded = storage
+        .timezones_between(&start.to_string(), &end.to_string())
+        .context("failed to read recorded timezones")?;
+    let others: Vec<_> = recorded
+        .into_iter()
+        .filter(|name| *name != report_data.timezone)
+        .collect();
+    if !others.is_empty() {
+        eprintln!(
+            "note: daily key counts were recorded in {}, times in this report use {}",
+            others.join(", "),
+            report_data.timezone
+        );
+    }
 
     match format {
         ReportFormat::Terminal => print_terminal_report(&report_data),
-        ReportFormat::Json => print_json_report(&report_data)?,
-        ReportFormat::Html => save_html_report(&report_data)?,
+        ReportFormat::Json => write_json_report(&report_data, output)?,
+        ReportFormat::Html => save_html_report(&report_data, output)?,
     }
 
     Ok(())
 }
 
-fn parse_iso_week(s: &str) -> Result<NaiveDate> {
-    let parts: Vec<&str> = s.split("-W").collect();
-    if parts.len() != 2 {
-        anyhow::bail!("expected YYYY-Www format");
+fn cmd_compact() -> Result<()> {
+    let mut storage = storage::Storage::open().context("failed to open database")?;
+    let config = config::Config::load();
+    let zone = timezone::Zone::from_config(&config).with_context(|| {
+        format!(
+            "invalid timezone in {}",
+            config::Config::config_path().display()
+        )
+    })?;
+
+    let cutoffs = storage::Cutoffs::from_retention(&config.retention, zone, Utc::now());
+    let compaction = storage
+        .compact(&cutoffs, zone)
+        .context("failed to compact database")?;
+    print_compaction(&compaction);
+    if compaction.is_empty() {
+        println!("Nothing to compact.");
+        return Ok(());
     }
 
-    let year: i32 = parts[0]
-        .parse()
-        .map_err(|_| anyhow::anyhow!("invalid year"))?;
-    let week: u32 = parts[1]
-        .parse()
-        .map_err(|_| anyhow::anyhow!("invalid week number"))?;
+    if !storage.vacuum().context("failed to vacuum database")? {
+        println!(
+            "The database is in use, so its file was not shrunk. The freed space will be reused."
+        );
+    }
+    Ok(())
+}
+
+/// How long a running daemon's session can go without an update before
+/// `repair` treats it as orphaned. The daemon updates it every 5 seconds.
+const REPAIR_GRACE: Duration = Duration::from_secs(120);
+
+fn cmd_repair() -> Result<()> {
+    let mut storage = storage::Storage::open().context("failed to open database")?;
+
+    // a running daemon's own session is open too, but still being updated
+    let mut idle_before = Utc::now();
+    if daemon::check_running().is_some() {
+        idle_before -= REPAIR_GRACE;
+    }
 
-    NaiveDate::from_isoywd_opt(year, week, chrono::Weekday::Mon)
-        .ok_or_else(|| anyhow::anyhow!("invalid ISO week"))
+    let recovered = storage
+        .close_orphaned_sessions(idle_before)
+        .context("failed to close unfinished sessions")?;
+    if recovered.is_empty() {
+        println!("No unfinished sessions.");
+    }
+    for session in &recovered {
+        println!("Closed {}", describe_recovered(session));
+    }
+    Ok(())
+}
+
+fn describe_recovered(session: &storage::RecoveredSession) -> String {
+    let minutes = (session.end_time - session.start_time).num_minutes();
+    let wpm = match session.avg_wpm {
+        Some(wpm) => format!(", avg {wpm:.0} WPM"),
+        None => String::new(),
+    };
+    format!(
+        "session {} from {} ({minutes} min{wpm})",
+        session.id,
+        timezone::Zone::configured()
+            .to_local(session.start_time)
+            .format("%b %-d, %Y %H:%M")
+    )
+}
+
+fn print_compaction(compaction: &storage::Compaction) {
+    if compaction.samples_rolled_up > 0 {
+        println!(
+            "Rolled up {} WPM samples",
+            format_number(compaction.samples_rolled_up as u64)
+        );
+    }
+    for (table, rows) in &compaction.rolled_up {
+        if *rows > 0 {
+            println!("Rolled up {} rows of {table}", format_number(*rows as u64));
+        }
+    }
+}
+
+fn cmd_import(path: &Path, name: Option<&str>) -> Result<()> {
+    let mut storage = storage::Storage::open().context("failed to open database")?;
+    let imported = storage
+        .import(path, name)
+        .with_context(|| format!("failed to import {}", path.display()))?;
+
+    for machine in imported {
+        println!(
+            "Imported {}: {} keystrokes, {} sessions",
+            machine.name,
+            format_number(machine.keystrokes),
+            format_number(machine.sessions as u64)
+        );
+    }
+    Ok(())
+}
+
+fn cmd_export(range: RangeArgs, format: ExportFormat, output: Option<PathBuf>) -> Result<()> {
+    let storage = storage::Storage::open().context("failed to open database")?;
+
+    let range = report::RangeChoice::from(range);
+    let bounds = if range.is_empty() {
+        export::Bounds::all()
+    } else {
+        let config = config::Config::load();
+        let zone = timezone::Zone::from_config(&config).with_context(|| {
+            format!(
+                "invalid timezone in {}",
+                config::Config::config_path().display()
+            )
+        })?;
+        let range = range.into_spec().map_err(anyhow::Error::msg)?.resolve();
+        export::Bounds::days(range.start, range.end, zone)
+    };
+    let conn = storage.connection();
+
+    match format {
+        ExportFormat::Csv => {
+            let dir = output.unwrap_or_else(|| PathBuf::from("keyheat-export"));
+            let written = export::write_csv(conn, &bounds, &dir)
+                .with_context(|| format!("failed to export to {}", dir.display()))?;
+            for (path, rows) in written {
+                eprintln!("{}: {} rows", path.display(), format_number(rows as u64));
+            }
+        }
+        ExportFormat::Jsonl => {
+            let rows = match output {
+                Some(path) => {
+                    let file = std::fs::File::create(&path)
+                        .with_context(|| format!("failed to create {}", path.display()))?;
+                    let mut out = std::io::BufWriter::new(file);
+                    let rows = export::write_jsonl(conn, &bounds, &mut out)
+                        .with_context(|| format!("failed to export to {}", path.display()))?;
+                    eprintln!("{}: {} rows", path.display(), format_number(rows as u64));
+                    rows
+                }
+                None => {
+                    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+                    match export::write_jsonl(conn, &bounds, &mut out) {
+                        // piped into head or similar, which is fine
+                        Err(error::Error::Io(e))
+                            if e.kind() == std::io::ErrorKind::BrokenPipe =>
+                        {
+                            return Ok(())
+                        }
+                        result => result.context("failed to export")?,
+                    }
+                }
+            };
+            if rows == 0 {
+                eprintln!("nothing recorded in that range");
+            }
+        }
+    }
+
+    Ok(())
 }
 
 fn print_terminal_report(data: &report::ReportData) {
     println!();
-    println!("KeyHeat \u{2014} {}", data.week.label);
+    println!("KeyHeat \u{2014} {}", data.range.label);
+    if let Some(device) = &data.device {
+        println!("  Device: {device}");
+    }
+    if let Some(machine) = &data.machine {
+        println!("  Machine: {machine}");
+    }
     println!();
 
     let delta = data
-        .prev_week_keystrokes
+        .prev_keystrokes
         .map(|prev| {
             if prev == 0 {
                 String::new()
             } else {
                 let pct = ((data.total_keystrokes as f64 - prev as f64) / prev as f64) * 100.0;
+                let previous = data.range.previous_name();
                 if pct >= 0.0 {
-                    format!("  (+{pct:.0}% from last week)")
+                    format!("  (+{pct:.0}% from {previous})")
                 } else {
-                    format!("  ({pct:.0}% from last week)")
+                    format!("  ({pct:.0}% from {previous})")
                 }
             }
         })
@@ -195,6 +829,20 @@ fn print_terminal_report(data: &report::ReportData) {
         hours,
         mins
     );
+    if !data.pauses.is_empty() {
+        let paused: f64 = data.pauses.iter().map(|p| p.duration_minutes).sum();
+        let times = if data.pauses.len() == 1 {
+            "time"
+        } else {
+            "times"
+        };
+        println!(
+            "         paused {} {times}, {}h {}m not recorded",
+            data.pauses.len(),
+            (paused / 60.0).floor() as u32,
+            (paused % 60.0).round() as u32
+        );
+    }
     println!();
 
     let peak_time = data
@@ -207,6 +855,40 @@ fn print_terminal_report(data: &report::ReportData) {
         data.avg_wpm, data.peak_wpm, peak_time
     );
 
+    if let (Some(dwell), Some(flight)) =
+        (data.timing.median_dwell_ms, data.timing.median_flight_ms)
+    {
+        println!("  Rhythm: {dwell:.0} ms key hold, {flight:.0} ms between keys (median)");
+    }
+
+    if data.ngrams.bigrams > 0 {
+        println!(
+            "  Layout: {:.1}% same-finger bigrams, {:.0}% alternation, {:.0}% rolls",
+            data.ngrams.same_finger_ratio * 100.0,
+            data.ngrams.alternation_ratio * 100.0,
+            data.ngrams.roll_ratio * 100.0
+        );
+    }
+
+    if let Some(busiest) = data.fingers.fingers.iter().max_by_key(|l| l.keystrokes) {
+        println!(
+            "  Hands: {:.0}% left, {:.0}% right (thumbs excluded), busiest finger {} ({:.0}%)",
+            data.fingers.left_hand_share * 100.0,
+            (1.0 - data.fingers.left_hand_share) * 100.0,
+            busiest.finger,
+            busiest.share * 100.0
+        );
+    }
+    for warning in &data.fingers.warnings {
+        let keys: Vec<String> = warning.top_keys.iter().map(|k| format_key_name(k)).collect();
+        println!(
+            "  Warning: {} takes {:.0}% of keystrokes, mostly {}",
+            warning.finger,
+            warning.share * 100.0,
+            keys.join(", ")
+        );
+    }
+
     let top_keys: Vec<String> = data
         .key_frequencies
         .iter()
@@ -225,6 +907,102 @@ fn print_terminal_report(data: &report::ReportData) {
         println!("  Top shortcuts: {}", top_shortcuts.join(", "));
     }
 
+    let slowest_keys: Vec<String> = data
+        .timing
+        .slowest_keys
+        .iter()
+        .take(3)
+        .map(|t| format!("{} ({:.0} ms)", format_key_name(&t.key), t.median_ms))
+        .collect();
+    if !slowest_keys.is_empty() {
+        println!("  Longest holds: {}", slowest_keys.join(", "));
+    }
+
+    let slowest_transitions: Vec<String> = data
+        .timing
+        .slowest_transitions
+        .iter()
+        .take(3)
+        .map(|t| {
+            format!(
+                "{} \u{2192} {} ({:.0} ms)",
+                format_key_name(&t.from),
+                format_key_name(&t.to),
+                t.median_ms
+            )
+        })
+        .collect();
+    if !slowest_transitions.is_empty() {
+        println!("  Slowest transitions: {}", slowest_transitions.join(", "));
+    }
+
+    let color = report::ColorMode::detect();
+    if data.total_keystrokes > 0 {
+        let counts: HashMap<String, u64> = data.key_frequencies.iter().cloned().collect();
+        println!();
+        println!("  Keyboard ({}):", data.layout.name);
+        for line in report::render_keyboard(&data.layout, &counts, 76, color) {
+            println!("    {line}");
+        }
+    }
+
+    if data
+        .hourly_activity
+        .iter()
+        .any(|(_, hours)| hours.iter().any(|&c| c > 0))
+    {
+        println!();
+        println!("  Activity by hour:");
+        for line in report::render_activity(&data.hourly_activity, &data.paused_hours, color) {
+            println!("    {line}");
+        }
+    }
+
+    if data.day_periods.iter().any(|p| p.keystrokes > 0) {
+        println!();
+        println!("  Time of day:");
+        for period in &data.day_periods {
+            if period.keystrokes == 0 {
+                continue;
+            }
+            let keys: Vec<String> = period
+                .top_keys
+                .iter()
+                .take(3)
+                .map(|(k, _)| format_key_name(k))
+                .collect();
+            let standout = period
+                .standout
+                .as_deref()
+                .map(|k| format!(", more {} than usual", format_key_name(k)))
+                .unwrap_or_default();
+            println!(
+                "    {:<10} {:>8} keystrokes, mostly {}{}",
+                period.label,
+                format_number(period.keystrokes),
+                keys.join(", "),
+                standout
+            );
+        }
+    }
+
+    if data.devices.len() > 1 {
+        println!();
+        println!("  Keyboards:");
+        for usage in &data.devices {
+            let wpm = usage
+                .avg_wpm
+                .map(|w| format!(", {w:.0} WPM avg"))
+                .unwrap_or_default();
+            println!(
+                "    {} ({} keystrokes{})",
+                usage.display_name(),
+                format_number(usage.keystrokes),
+                wpm
+            );
+        }
+    }
+
     println!();
 
     if let Some(insight) = &data.shortcut_insight {
@@ -238,47 +1016,52 @@ fn print_terminal_report(data: &report::ReportData) {
         .join("reports");
 
     println!(
-        "  Full report: {}/week-{}.html",
-        reports_dir.display(),
-        data.week.start.format("%Y-W%V")
+        "  Full report: {}",
+        reports_dir.join(html_report_filename(data)).display()
     );
     println!();
 }
 
-fn print_json_report(data: &report::ReportData) -> Result<()> {
-    // simple manual JSON output to avoid serde dependency for now
-    println!("{{");
-    println!("  \"week\": {{");
-    println!("    \"start\": \"{}\",", data.week.start);
-    println!("    \"end\": \"{}\",", data.week.end);
-    println!("    \"label\": \"{}\"", data.week.label);
-    println!("  }},");
-    println!("  \"total_keystrokes\": {},", data.total_keystrokes);
-    println!("  \"all_time_keystrokes\": {},", data.all_time_keystrokes);
-    println!("  \"avg_wpm\": {:.1},", data.avg_wpm);
-    println!("  \"peak_wpm\": {:.1},", data.peak_wpm);
-    println!(
-        "  \"total_typing_minutes\": {:.1},",
-        data.total_typing_minutes
-    );
-    println!("  \"session_count\": {},", data.sessions.len());
-    println!("  \"backspace_ratio\": {:.3},", data.backspace_ratio);
-    println!("  \"finger_travel_mm\": {:.1},", data.finger_travel_mm);
-    println!("  \"night_owl_pct\": {:.1}", data.night_owl_pct);
-    println!("}}");
-    Ok(())
+fn html_report_filename(data: &report::ReportData) -> String {
+    let range = data.range.slug();
+    match &data.device {
+        Some(device) => {
+            let slug: String = device
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+                .collect();
+            format!("{range}-{slug}.html")
+        }
+        None => format!("{range}.html"),
+    }
 }
 
-fn save_html_report(data: &report::ReportData) -> Result<()> {
-    let reports_dir = dirs::data_local_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
-        .join("keyheat")
-        .join("reports");
-
-    std::fs::create_dir_all(&reports_dir).context("failed to create reports directory")?;
+fn write_json_report(data: &report::ReportData, output: Option<PathBuf>) -> Result<()> {
+    let json = report::render_json(data).context("failed to serialize report")?;
+    match output {
+        Some(path) => {
+            std::fs::write(&path, json + "\n")
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            println!("Report saved to: {}", path.display());
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
 
-    let filename = format!("week-{}.html", data.week.start.format("%Y-W%V"));
-    let filepath = reports_dir.join(&filename);
+fn save_html_report(data: &report::ReportData, output: Option<PathBuf>) -> Result<()> {
+    let filepath = match output {
+        Some(path) => path,
+        None => {
+            let reports_dir = dirs::data_local_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("keyheat")
+                .join("reports");
+            std::fs::create_dir_all(&reports_dir)
+                .context("failed to create reports directory")?;
+            reports_dir.join(html_report_filename(data))
+        }
+    };
 
     let html = report::render_html(data);
     std::fs::write(&filepath, &html).context("failed to write HTML report")?;
@@ -330,11 +1113,292 @@ fn format_shortcut_name(shortcut: &str) -> String {
         .join("+")
 }
 
+/// Writes out what the aggregator still holds, closes the open session and
+/// pause and removes the PID file, so stopping loses nothing.
+fn shut_down(
+    storage: &mut storage::Storage,
+    aggregator: &mut Aggregator,
+    zone: timezone::Zone,
+    stats: &mut DaemonStats,
+) -> Result<()> {
+    service::notify("STOPPING=1");
+    let now = Utc::now();
+
+    let batch = aggregator.take_batch();
+    let flushed = if batch.is_empty() {
+        Ok(())
+    } else {
+        let today = zone.naive_local(now).format("%Y-%m-%d").to_string();
+        let hour = storage::hour_key(zone.hour_start(now));
+        storage
+            .flush_all(&batch, &today, &hour)
+            .context("failed to flush pending counts")
+    };
+
+    let ended = end_open_session(storage, aggregator, now);
+    if let Some(pause) = stats.paused.take() {
+        end_pause(storage, aggregator, &pause, now);
+    }
+
+    daemon::remove_own_pid_file()?;
+    flushed.and(ended)?;
+    eprintln!("shutdown complete");
+    Ok(())
+}
+
+/// Closes the aggregator's session, if it has one, as of `now`.
+fn end_open_session(
+    storage: &mut storage::Storage,
+    aggregator: &mut Aggregator,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let Some((session_id, keystroke_count)) = aggregator.current_session() else {
+        return Ok(());
+    };
+    let device_counts = aggregator.session_device_counts();
+    let (avg_wpm, peak_wpm) = aggregator.end_session().unwrap_or((0.0, 0.0));
+    storage
+        .end_session(
+            session_id,
+            now,
+            keystroke_count,
+            &device_counts,
+            Some(avg_wpm),
+            Some(peak_wpm),
+        )
+        .context("failed to end session")?;
+    eprintln!(
+        "session {session_id} ended ({keystroke_count} keystrokes, avg {avg_wpm:.1} WPM, peak {peak_wpm:.1} WPM)"
+    );
+    Ok(())
+}
+
+/// A pause in progress. Events are dropped until it ends.
+struct Pause {
+    /// row in `pauses`, None if it couldn't be recorded
+    id: Option<i64>,
+    since: DateTime<Utc>,
+    until: Option<DateTime<Utc>>,
+    reason: &'static str,
+}
+
+/// Starts dropping events. The open session ends here, so the pause
+/// doesn't count as typing time.
+fn start_pause(
+    storage: &mut storage::Storage,
+    aggregator: &mut Aggregator,
+    until: Option<DateTime<Utc>>,
+    reason: &'static str,
+) -> Pause {
+    let now = Utc::now();
+    if let Err(e) = end_open_session(storage, aggregator, now) {
+        eprintln!("{e:#}");
+    }
+    aggregator.interrupt();
+    let id = storage
+        .start_pause(now, reason)
+        .map_err(|e| eprintln!("failed to record pause: {e}"))
+        .ok();
+    match until {
+        Some(until) => eprintln!(
+            "paused by {reason} until {}",
+            timezone::Zone::configured()
+                .to_local(until)
+                .format("%Y-%m-%d %H:%M")
+        ),
+        None => eprintln!("paused by {reason}"),
+    }
+    Pause {
+        id,
+        since: now,
+        until,
+        reason,
+    }
+}
+
+fn end_pause(
+    storage: &mut storage::Storage,
+    aggregator: &mut Aggregator,
+    pause: &Pause,
+    now: DateTime<Utc>,
+) {
+    // keys pressed during the pause were never seen going down
+    aggregator.interrupt();
+    if let Some(id) = pause.id {
+        if let Err(e) = storage.end_pause(id, now) {
+            eprintln!("failed to record end of pause: {e}");
+        }
+    }
+    eprintln!("resumed after {} min", (now - pause.since).num_minutes());
+}
+
+/// What `status` reports that neither the aggregator nor the database
+/// knows.
+struct DaemonStats {
+    started_at: DateTime<Utc>,
+    session_started_at: Option<DateTime<Utc>>,
+    last_flush: Option<DateTime<Utc>>,
+    db_failures_total: u64,
+    paused: Option<Pause>,
+    today_keys: Option<TodayKeys>,
+    /// for `/metrics`
+    metrics: metrics::Metrics,
+}
+
+/// Today's stored key counts, kept up to date on each flush so `status`
+/// and `live` don't read the database for every request.
+struct TodayKeys {
+    date: String,
+    counts: HashMap<String, u64>,
+}
+
+impl DaemonStats {
+    /// Adds a batch just flushed to `date` to the cached counts. A new day
+    /// drops the cache, and the next request reloads it.
+    fn count_flushed(&mut self, batch: &aggregator::FlushBatch, date: &str) {
+        match &mut self.today_keys {
+            Some(cached) if cached.date == date => {
+                for ((_, key), &count) in &batch.key_counts {
+                    *cached.counts.entry(key.to_string()).or_insert(0) += count;
+                }
+            }
+            _ => self.today_keys = None,
+        }
+    }
+}
+
+/// Presses per key today, stored and pending. Only the first request of
+/// the day reads the database.
+fn today_key_counts(
+    storage: &storage::Storage,
+    aggregator: &Aggregator,
+    zone: timezone::Zone,
+    stats: &mut DaemonStats,
+) -> Result<HashMap<String, u64>> {
+    let today = zone.naive_local(Utc::now()).format("%Y-%m-%d").to_string();
+    let cached = match stats.today_keys.take() {
+        Some(cached) if cached.date == today => cached,
+        _ => TodayKeys {
+            counts: storage
+                .key_counts_on(&today)
+                .context("failed to read today's keystrokes")?,
+            date: today,
+        },
+    };
+
+    let mut counts = cached.counts.clone();
+    stats.today_keys = Some(cached);
+    for (key, count) in aggregator.pending_key_counts() {
+        *counts.entry(key.to_string()).or_insert(0) += count;
+    }
+    Ok(counts)
+}
+
+fn daemon_status(
+    aggregator: &Aggregator,
+    stats: &DaemonStats,
+    today_keys: &HashMap<String, u64>,
+    db_failure_count: u32,
+    devices: Vec<(String, keycode::DeviceId)>,
+) -> ipc::Status {
+    let now = Utc::now();
+
+    ipc::Status {
+        pid: std::process::id(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        started_at: stats.started_at,
+        uptime_secs: (now - stats.started_at).num_seconds().max(0) as u64,
+        current_wpm: aggregator.current_wpm(),
+        session: aggregator
+            .current_session()
+            .map(|(id, keystrokes)| ipc::SessionStatus {
+                id,
+                started_at: stats.session_started_at,
+                keystrokes,
+            }),
+        today_keystrokes: today_keys.values().sum(),
+        devices: devices
+            .into_iter()
+            .map(|(name, id)| ipc::DeviceStatus {
+                name,
+                id: id.to_string(),
+            })
+            .collect(),
+        last_flush: stats.last_flush,
+        paused: stats.paused.as_ref().map(|pause| ipc::PauseStatus {
+            since: pause.since,
+            until: pause.until,
+            reason: pause.reason.to_string(),
+        }),
+        errors: ipc::ErrorCounts {
+            database_consecutive: db_failure_count,
+            database_total: stats.db_failures_total,
+        },
+    }
+}
+
+/// A daemon other than this process, found by asking on the control
+/// socket or by the PID file. `start` writes the file after spawning, so
+/// it may already name this process.
+fn other_daemon() -> Option<u32> {
+    query_status()
+        .map(|status| status.pid)
+        .or_else(|| daemon::check_running().filter(|&pid| pid != std::process::id()))
+}
+
 fn run_foreground(use_mock: bool) -> Result<()> {
+    // recovery below would close the running daemon's session
+    if let Some(pid) = other_daemon() {
+        anyhow::bail!("KeyHeat is already running (PID {pid})");
+    }
+
     let mut storage = storage::Storage::open().context("failed to open database")?;
     eprintln!("database ready");
 
+    // no other daemon writes sessions, so any still open were cut off
+    let recovered = storage
+        .close_orphaned_sessions(Utc::now())
+        .context("failed to close unfinished sessions")?;
+    for session in &recovered {
+        eprintln!("closed unfinished {}", describe_recovered(session));
+    }
+    storage
+        .close_open_pauses(Utc::now())
+        .context("failed to close unfinished pauses")?;
+
+    let config = config::Config::load();
+    let zone = timezone::Zone::from_config(&config).with_context(|| {
+        format!("invalid timezone in {}", config::Config::config_path().display())
+    })?;
+    let today = zone.today().format("%Y-%m-%d").to_string();
+    storage
+        .record_timezone(&zone.name(), &today)
+        .context("failed to record timezone")?;
+    eprintln!("counting days in {}", zone.name());
+
+    let mut chord = match config.privacy_chord.trim() {
+        "" => None,
+        text => Some(keycode::ChordWatch::new(
+            text.parse::<keycode::Chord>().with_context(|| {
+                format!(
+                    "invalid privacy_chord in {}",
+                    config::Config::config_path().display()
+                )
+            })?,
+        )),
+    };
+
+    config.devices.validate().with_context(|| {
+        format!(
+            "invalid [devices] rule in {}",
+            config::Config::config_path().display()
+        )
+    })?;
+
     let (sender, receiver) = mpsc::channel::<KeyEvent>();
+    // stays empty with --mock, nothing real is attached
+    #[cfg(target_os = "linux")]
+    let attached = listener_linux::Attached::default();
 
     if use_mock {
         eprintln!("starting mock listener");
@@ -346,17 +1410,27 @@ fn run_foreground(use_mock: bool) -> Result<()> {
     } else {
         #[cfg(target_os = "linux")]
         {
-            let devices =
-                listener_linux::find_keyboards().context("failed to find keyboard devices")?;
+            let mut filter = listener_linux::DeviceFilter::new(config.devices.clone());
+            let keyboards = match listener_linux::find_keyboards(&mut filter) {
+                Ok(keyboards) => keyboards,
+                Err(error::Error::NoKeyboards) => {
+                    eprintln!("no keyboards found yet, waiting for one to be plugged in");
+                    Vec::new()
+                }
+                Err(e) => return Err(e).context("failed to find keyboard devices"),
+            };
 
-            eprintln!(
-                "found {} keyboard(s): {:?}",
-                devices.len(),
-                listener_linux::device_names(&devices)
-            );
+            if !keyboards.is_empty() {
+                eprintln!(
+                    "found {} keyboard(s): {:?}",
+                    keyboards.len(),
+                    listener_linux::device_names(&keyboards)
+                );
+            }
 
+            let attached = attached.clone();
             thread::spawn(move || {
-                if let Err(e) = listener_linux::run_capture(devices, sender) {
+                if let Err(e) = listener_linux::run_capture(keyboards, filter, sender, attached) {
                     eprintln!("listener error: {e}");
                 }
             });
@@ -383,10 +1457,37 @@ fn run_foreground(use_mock: bool) -> Result<()> {
         }
     }
 
+    daemon::handle_shutdown_signals()?;
+    let inbox = ipc::Inbox::new();
+    // removes the socket file when dropped
+    let _control = match ipc::serve(inbox.requester()) {
+        Ok(control) => Some(control),
+        // two daemons would count every keystroke twice
+        Err(e) if matches!(e.downcast_ref(), Some(error::Error::AlreadyRunning(_))) => {
+            return Err(e)
+        }
+        Err(e) => {
+            eprintln!("control socket unavailable: {e:#}");
+            None
+        }
+    };
+    let _dashboard = if config.dashboard.enabled {
+        dashboard::serve(&config.dashboard, inbox.requester())
+            .inspect_err(|e| eprintln!("dashboard unavailable: {e:#}"))
+            .ok()
+    } else {
+        None
+    };
+    let _metrics = if config.metrics.enabled {
+        dashboard::serve_metrics(&config.metrics, inbox.requester())
+            .inspect_err(|e| eprintln!("metrics unavailable: {e:#}"))
+            .ok()
+    } else {
+        None
+    };
+    service::notify("READY=1");
     eprintln!("capturing keystrokes, ctrl+c to stop");
 
-    // Load configuration
-    let config = config::Config::load();
     let idle_threshold = Duration::from_secs(config.idle_threshold_secs);
     let wpm_window = Duration::from_secs(config.wpm_window_secs);
     let wpm_sample_interval = Duration::from_secs(config.wpm_sample_interval_secs);
@@ -394,22 +1495,164 @@ fn run_foreground(use_mock: bool) -> Result<()> {
     let mut aggregator = Aggregator::new(idle_threshold, wpm_window, wpm_sample_interval);
     let mut db_failure_count = 0;
     const MAX_DB_FAILURES: u32 = 3;
+    let mut stats = DaemonStats {
+        started_at: Utc::now(),
+        session_started_at: None,
+        last_flush: None,
+        db_failures_total: 0,
+        paused: None,
+        today_keys: None,
+        metrics: metrics::Metrics::default(),
+    };
+
+    const SIGNAL_POLL: Duration = Duration::from_millis(200);
+
+    // compact a minute after startup, then once a day
+    const COMPACT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+    let mut next_compaction = std::time::Instant::now() + Duration::from_secs(60);
 
     loop {
         let deadline = std::time::Instant::now() + Duration::from_secs(5);
 
         loop {
             let timeout = deadline.saturating_duration_since(std::time::Instant::now());
-            match receiver.recv_timeout(timeout) {
+            if timeout.is_zero() || daemon::shutdown_requested() {
+                break;
+            }
+            // signals don't interrupt the wait, so wake up to check for one
+            match receiver.recv_timeout(timeout.min(SIGNAL_POLL)) {
                 Ok(event) => {
-                    aggregator.process_event(event);
+                    let events = match &mut chord {
+                        Some(chord) => chord.feed(event),
+                        None => Some(vec![event]),
+                    };
+                    match events {
+                        Some(events) => {
+                            if stats.paused.is_none() {
+                                for event in events {
+                                    aggregator.process_event(event);
+                                }
+                            }
+                        }
+                        // the chord's own presses were held back and dropped
+                        None => match stats.paused.take() {
+                            Some(pause) => {
+                                end_pause(&mut storage, &mut aggregator, &pause, Utc::now())
+                            }
+                            None => {
+                                stats.paused =
+                                    Some(start_pause(&mut storage, &mut aggregator, None, "chord"));
+                            }
+                        },
+                    }
                 }
-                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
                 Err(mpsc::RecvTimeoutError::Disconnected) => {
                     eprintln!("listener disconnected, exiting");
-                    return Ok(());
+                    return shut_down(&mut storage, &mut aggregator, zone, &mut stats);
                 }
             }
+
+            while let Some(pending) = inbox.try_recv() {
+                let response = match pending.request {
+                    ipc::Request::Status | ipc::Request::Live => {
+                        #[cfg(target_os = "linux")]
+                        let devices = listener_linux::attached_devices(&attached);
+                        #[cfg(not(target_os = "linux"))]
+                        let devices = Vec::new();
+
+                        match today_key_counts(&storage, &aggregator, zone, &mut stats) {
+                            Ok(keys) => {
+                                let status = daemon_status(
+                                    &aggregator,
+                                    &stats,
+                                    &keys,
+                                    db_failure_count,
+                                    devices,
+                                );
+                                let live =
+                                    matches!(pending.request, ipc::Request::Live).then(|| {
+                                        ipc::Live {
+                                            keys,
+                                            recent_shortcuts: aggregator
+                                                .recent_shortcuts()
+                                                .map(|(at, combo)| ipc::RecentShortcut {
+                                                    at: *at,
+                                                    combo: combo.clone(),
+                                                })
+                                                .collect(),
+                                        }
+                                    });
+                                ipc::Response {
+                                    ok: true,
+                                    status: Some(status),
+                                    live,
+                                    ..ipc::Response::default()
+                                }
+                            }
+                            Err(e) => ipc::Response::error(format!("{e:#}")),
+                        }
+                    }
+                    ipc::Request::Metrics => ipc::Response {
+                        ok: true,
+                        metrics: Some(metrics::Metrics {
+                            current_wpm: aggregator.current_wpm(),
+                            ..stats.metrics.clone()
+                        }),
+                        ..ipc::Response::default()
+                    },
+                    ipc::Request::Pause { for_secs } => {
+                        let until = for_secs
+                            .map(|secs| Utc::now() + chrono::TimeDelta::seconds(secs as i64));
+                        match &mut stats.paused {
+                            // pausing again only changes when it ends
+                            Some(pause) => pause.until = until,
+                            None => {
+                                stats.paused = Some(start_pause(
+                                    &mut storage,
+                                    &mut aggregator,
+                                    until,
+                                    "command",
+                                ));
+                            }
+                        }
+                        ipc::Response::ok()
+                    }
+                    ipc::Request::Resume => match stats.paused.take() {
+                        Some(pause) => {
+                            end_pause(&mut storage, &mut aggregator, &pause, Utc::now());
+                            ipc::Response::ok()
+                        }
+                        None => ipc::Response::error("capture is not paused"),
+                    },
+                    ipc::Request::Stop => {
+                        eprintln!("stop requested over control socket");
+                        daemon::request_shutdown();
+                        ipc::Response::ok()
+                    }
+                };
+                let _ = pending.reply.send(response);
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let devices = listener_linux::attached_devices(&attached);
+            let ids: Vec<_> = devices.into_iter().map(|(_, id)| id).collect();
+            aggregator.forget_detached(&ids);
+        }
+
+        if daemon::shutdown_requested() {
+            eprintln!("shutting down");
+            return shut_down(&mut storage, &mut aggregator, zone, &mut stats);
+        }
+
+        let now = Utc::now();
+        if let Some(pause) = stats
+            .paused
+            .take_if(|p| p.until.is_some_and(|until| until <= now))
+        {
+            end_pause(&mut storage, &mut aggregator, &pause, now);
         }
 
         if aggregator.needs_session_start() {
@@ -417,11 +1660,14 @@ fn run_foreground(use_mock: bool) -> Result<()> {
             match storage.start_session(now) {
                 Ok(session_id) => {
                     aggregator.start_session(session_id);
+                    stats.session_started_at = Some(now);
+                    stats.metrics.sessions += 1;
                     eprintln!("session {session_id} started");
                     db_failure_count = 0;
                 }
                 Err(e) => {
                     db_failure_count += 1;
+                    stats.db_failures_total += 1;
                     eprintln!("failed to start session: {e} (failure {db_failure_count}/{MAX_DB_FAILURES})");
                     if db_failure_count >= MAX_DB_FAILURES {
                         eprintln!("ERROR: Database persistence has failed {MAX_DB_FAILURES} times consecutively. Exiting to prevent data loss.");
@@ -434,17 +1680,21 @@ fn run_foreground(use_mock: bool) -> Result<()> {
 
         if let Some((session_id, keystroke_count)) = aggregator.check_idle() {
             let now = Utc::now();
+            let device_counts = aggregator.session_device_counts();
             let wpm_stats = aggregator.end_session();
+            stats.session_started_at = None;
             let (avg_wpm, peak_wpm) = wpm_stats.unwrap_or((0.0, 0.0));
 
             if let Err(e) = storage.end_session(
                 session_id,
                 now,
                 keystroke_count,
+                &device_counts,
                 Some(avg_wpm),
                 Some(peak_wpm),
             ) {
                 db_failure_count += 1;
+                stats.db_failures_total += 1;
                 eprintln!("failed to end session: {e} (failure {db_failure_count}/{MAX_DB_FAILURES})");
                 if db_failure_count >= MAX_DB_FAILURES {
                     eprintln!("ERROR: Database persistence has failed {MAX_DB_FAILURES} times consecutively. Exiting to prevent data loss.");
@@ -458,8 +1708,12 @@ fn run_foreground(use_mock: bool) -> Result<()> {
                 db_failure_count = 0;
             }
         } else if let Some((session_id, keystroke_count)) = aggregator.current_session() {
-            if let Err(e) = storage.update_session_keystrokes(session_id, keystroke_count) {
+            let device_counts = aggregator.session_device_counts();
+            if let Err(e) =
+                storage.update_session_keystrokes(session_id, keystroke_count, &device_counts)
+            {
                 db_failure_count += 1;
+                stats.db_failures_total += 1;
                 eprintln!("failed to update session: {e} (failure {db_failure_count}/{MAX_DB_FAILURES})");
                 if db_failure_count >= MAX_DB_FAILURES {
                     eprintln!("ERROR: Database persistence has failed {MAX_DB_FAILURES} times consecutively. Exiting to prevent data loss.");
@@ -471,30 +1725,39 @@ fn run_foreground(use_mock: bool) -> Result<()> {
             }
         }
 
-        let key_snapshot = aggregator.take_counts();
-        let shortcut_snapshot = aggregator.take_shortcuts();
-        let wpm_samples = aggregator.take_wpm_samples();
+        let batch = aggregator.take_batch();
+        stats.metrics.record_batch(&batch);
 
-        let today = Local::now().format("%Y-%m-%d").to_string();
+        let now = Utc::now();
+        let today = zone.naive_local(now).format("%Y-%m-%d").to_string();
+        let hour = storage::hour_key(zone.hour_start(now));
 
         // Flush all data in a single transaction for atomicity
-        let has_data = !key_snapshot.is_empty() || !shortcut_snapshot.is_empty() || !wpm_samples.is_empty();
-
-        if has_data {
-            match storage.flush_all(&key_snapshot, &shortcut_snapshot, &wpm_samples, &today) {
+        if !batch.is_empty() {
+            match storage.flush_all(&batch, &today, &hour) {
                 Ok(_) => {
                     db_failure_count = 0;
+                    stats.last_flush = Some(now);
+                    stats.count_flushed(&batch, &today);
                     let wpm = aggregator.current_wpm();
 
                     let mut parts = Vec::new();
-                    if !key_snapshot.is_empty() {
-                        parts.push(format!("{} key types", key_snapshot.len()));
+                    if !batch.key_counts.is_empty() {
+                        parts.push(format!("{} key types", batch.key_counts.len()));
+                    }
+                    if !batch.shortcut_counts.is_empty() {
+                        parts.push(format!("{} shortcuts", batch.shortcut_counts.len()));
                     }
-                    if !shortcut_snapshot.is_empty() {
-                        parts.push(format!("{} shortcuts", shortcut_snapshot.len()));
+                    if !batch.wpm_samples.is_empty() {
+                        parts.push(format!("{} WPM samples", batch.wpm_samples.len()));
                     }
-                    if !wpm_samples.is_empty() {
-                        parts.push(format!("{} WPM samples", wpm_samples.len()));
+                    let timings = batch.dwell_counts.len() + batch.flight_counts.len();
+                    if timings > 0 {
+                        parts.push(format!("{timings} timing buckets"));
+                    }
+                    let ngrams = batch.bigram_counts.len() + batch.trigram_counts.len();
+                    if ngrams > 0 {
+                        parts.push(format!("{ngrams} n-grams"));
                     }
 
                     let summary = parts.join(", ");
@@ -506,6 +1769,8 @@ fn run_foreground(use_mock: bool) -> Result<()> {
                 }
                 Err(e) => {
                     db_failure_count += 1;
+                    stats.db_failures_total += 1;
+                    stats.metrics.flush_failures += 1;
                     eprintln!("database flush error: {e} (failure {db_failure_count}/{MAX_DB_FAILURES})");
 
                     if db_failure_count >= MAX_DB_FAILURES {
@@ -520,5 +1785,89 @@ fn run_foreground(use_mock: bool) -> Result<()> {
                 }
             }
         }
+
+        if config.retention.auto_compact && std::time::Instant::now() >= next_compaction {
+            next_compaction = std::time::Instant::now() + COMPACT_INTERVAL;
+            let cutoffs = storage::Cutoffs::from_retention(&config.retention, zone, Utc::now());
+            match storage.compact(&cutoffs, zone) {
+                Ok(compaction) if compaction.is_empty() => {}
+                Ok(compaction) => {
+                    let rolled_up: usize = compaction.rolled_up.iter().map(|(_, rows)| rows).sum();
+                    eprintln!(
+                        "compacted: rolled up {} WPM samples and {rolled_up} count rows",
+                        compaction.samples_rolled_up
+                    );
+                }
+                // compaction can wait, capture can't
+                Err(e) => eprintln!("compaction failed: {e}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keycode::{EventType, KeyCode, ModifierState};
+    use std::time::Instant;
+
+    #[test]
+    fn shutdown_flushes_and_closes_the_session() {
+        let mut storage = storage::Storage::in_memory().unwrap();
+        let mut aggregator = Aggregator::default();
+        let mut stats = DaemonStats {
+            started_at: Utc::now(),
+            session_started_at: None,
+            last_flush: None,
+            db_failures_total: 0,
+            paused: None,
+            today_keys: None,
+            metrics: metrics::Metrics::default(),
+        };
+
+        let session_id = storage.start_session(Utc::now()).unwrap();
+        aggregator.start_session(session_id);
+        let start = Instant::now();
+        for i in 0..30 {
+            aggregator.process_event(KeyEvent {
+                key_code: KeyCode::E,
+                event_type: EventType::KeyDown,
+                timestamp: start + Duration::from_millis(i * 200),
+                modifiers: ModifierState::empty(),
+                device: None,
+            });
+        }
+        let wpm = aggregator.current_wpm();
+        assert!(wpm > 0.0);
+
+        let before = Utc::now();
+        let zone = timezone::Zone::default();
+        shut_down(&mut storage, &mut aggregator, zone, &mut stats).unwrap();
+        let after = Utc::now();
+
+        let pressed: i64 = storage
+            .connection()
+            .query_row(
+                "SELECT SUM(count) FROM key_counts WHERE key_code = 'e'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(pressed, 30);
+
+        let (end_time, keystrokes, avg_wpm, peak_wpm): (String, i64, f64, f64) = storage
+            .connection()
+            .query_row(
+                "SELECT end_time, keystroke_count, avg_wpm, peak_wpm FROM sessions WHERE id = ?1",
+                [session_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        let end_time = DateTime::parse_from_rfc3339(&end_time).unwrap();
+        assert!(end_time >= before && end_time <= after);
+        assert_eq!(keystrokes, 30);
+        assert!(avg_wpm > 0.0);
+        assert!(peak_wpm >= wpm);
+        assert!(aggregator.current_session().is_none());
     }
 }
@@ -0,0 +1,190 @@
+//! Running totals for Prometheus, served at `/metrics` by the dashboard.
+//!
+//! The capture loop adds each batch it takes from the aggregator, before
+//! writing it, so the counters include batches that failed to save. See
+//! `docs/dashboard.md` for the metric names.
+
+use crate::aggregator::FlushBatch;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Upper bounds of the WPM histogram buckets.
+pub const WPM_BUCKETS: [f64; 8] = [20.0, 40.0, 60.0, 80.0, 100.0, 120.0, 150.0, 200.0];
+
+/// Totals since the daemon started, and the WPM right now.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metrics {
+    /// key presses per [`KeyCode::class`](crate::keycode::KeyCode::class)
+    pub keystrokes: BTreeMap<String, u64>,
+    pub shortcuts: u64,
+    /// sessions started
+    pub sessions: u64,
+    pub flush_failures: u64,
+    pub current_wpm: f64,
+    pub wpm_samples: Histogram,
+}
+
+/// Samples per bucket of [`WPM_BUCKETS`], not cumulative. The last entry
+/// counts samples above every bound.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Histogram {
+    pub buckets: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; WPM_BUCKETS.len() + 1];
+        }
+        let bucket = WPM_BUCKETS
+            .iter()
+            .position(|&bound| value <= bound)
+            .unwrap_or(WPM_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    /// Adds a batch taken from the aggregator.
+    pub fn record_batch(&mut self, batch: &FlushBatch) {
+        for ((_, key), &count) in &batch.key_counts {
+            *self.keystrokes.entry(key.class().to_string()).or_insert(0) += count;
+        }
+        self.shortcuts += batch.shortcut_counts.values().sum::<u64>();
+        for sample in &batch.wpm_samples {
+            self.wpm_samples.observe(sample.wpm);
+        }
+    }
+
+    /// The Prometheus text format, or OpenMetrics if `openmetrics` is set.
+    /// The two differ only in how counters are declared and in the end
+    /// marker.
+    pub fn render(&self, openmetrics: bool) -> String {
+        let mut out = String::new();
+        // OpenMetrics names the counter family without `_total`
+        let counter = |out: &mut String, name: &str, help: &str| {
+            let family = if openmetrics {
+                name.trim_end_matches("_total")
+            } else {
+                name
+            };
+            let _ = writeln!(out, "# HELP {family} {help}");
+            let _ = writeln!(out, "# TYPE {family} counter");
+        };
+
+        counter(
+            &mut out,
+            "keyheat_keystrokes_total",
+            "Key presses since the daemon started, by key class.",
+        );
+        for (class, count) in &self.keystrokes {
+            let _ = writeln!(out, "keyheat_keystrokes_total{{class=\"{class}\"}} {count}");
+        }
+        counter(
+            &mut out,
+            "keyheat_shortcuts_total",
+            "Shortcuts pressed since the daemon started.",
+        );
+        let _ = writeln!(out, "keyheat_shortcuts_total {}", self.shortcuts);
+        counter(
+            &mut out,
+            "keyheat_sessions_total",
+            "Typing sessions started since the daemon started.",
+        );
+        let _ = writeln!(out, "keyheat_sessions_total {}", self.sessions);
+        counter(
+            &mut out,
+            "keyheat_db_flush_failures_total",
+            "Failed writes of pending counts to the database.",
+        );
+        let _ = writeln!(
+            out,
+            "keyheat_db_flush_failures_total {}",
+            self.flush_failures
+        );
+
+        let _ = writeln!(out, "# HELP keyheat_wpm Words per minute right now.");
+        let _ = writeln!(out, "# TYPE keyheat_wpm gauge");
+        let _ = writeln!(out, "keyheat_wpm {}", self.current_wpm);
+
+        let histogram = &self.wpm_samples;
+        let _ = writeln!(
+            out,
+            "# HELP keyheat_wpm_samples WPM samples recorded while typing."
+        );
+        let _ = writeln!(out, "# TYPE keyheat_wpm_samples histogram");
+        let mut cumulative = 0;
+        for (i, bound) in WPM_BUCKETS.iter().enumerate() {
+            cumulative += histogram.buckets.get(i).copied().unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "keyheat_wpm_samples_bucket{{le=\"{bound:.1}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "keyheat_wpm_samples_bucket{{le=\"+Inf\"}} {}",
+            histogram.count
+        );
+        let _ = writeln!(out, "keyheat_wpm_samples_sum {}", histogram.sum);
+        let _ = writeln!(out, "keyheat_wpm_samples_count {}", histogram.count);
+
+        if openmetrics {
+            out.push_str("# EOF\n");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::WpmSample;
+    use crate::keycode::KeyCode;
+    use chrono::Utc;
+
+    #[test]
+    fn batches_add_up_into_the_exposition() {
+        let sample = |wpm| WpmSample {
+            timestamp: Utc::now(),
+            session_id: 1,
+            wpm,
+            keystrokes_in_window: 20,
+            device: None,
+        };
+        let mut batch = FlushBatch::default();
+        batch.key_counts.insert((None, KeyCode::E), 5);
+        batch.key_counts.insert((None, KeyCode::A), 3);
+        batch.key_counts.insert((None, KeyCode::Space), 2);
+        batch
+            .shortcut_counts
+            .insert((None, "ctrl+c".to_string()), 4);
+        batch.wpm_samples = vec![sample(35.0), sample(72.5), sample(250.0)];
+
+        let mut metrics = Metrics::default();
+        metrics.record_batch(&batch);
+        metrics.record_batch(&batch);
+        metrics.sessions = 1;
+
+        let text = metrics.render(false);
+        assert!(text.contains("keyheat_keystrokes_total{class=\"letter\"} 16\n"));
+        assert!(text.contains("keyheat_keystrokes_total{class=\"whitespace\"} 4\n"));
+        assert!(text.contains("keyheat_shortcuts_total 8\n"));
+        assert!(text.contains("keyheat_sessions_total 1\n"));
+        assert!(text.contains("keyheat_wpm_samples_bucket{le=\"20.0\"} 0\n"));
+        assert!(text.contains("keyheat_wpm_samples_bucket{le=\"40.0\"} 2\n"));
+        assert!(text.contains("keyheat_wpm_samples_bucket{le=\"200.0\"} 4\n"));
+        assert!(text.contains("keyheat_wpm_samples_bucket{le=\"+Inf\"} 6\n"));
+        assert!(text.contains("keyheat_wpm_samples_sum 715\n"));
+        assert!(!text.contains("# EOF"));
+
+        let text = metrics.render(true);
+        assert!(text.contains("# TYPE keyheat_shortcuts counter\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
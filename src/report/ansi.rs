@@ -0,0 +1,286 @@
+//! Report pieces drawn with ANSI escapes, for terminals.
+
+use super::layout::Layout;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+
+/// How many colours the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    TrueColor,
+    Ansi256,
+    /// no escapes; shading falls back to block characters
+    Plain,
+}
+
+impl ColorMode {
+    /// What stdout can show. Plain when it isn't a terminal or `NO_COLOR`
+    /// is set, truecolor when `$COLORTERM` says the terminal has it, 256
+    /// colours otherwise.
+    pub fn detect() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        if no_color || !std::io::stdout().is_terminal() {
+            return Self::Plain;
+        }
+        match std::env::var("COLORTERM").as_deref() {
+            Ok("truecolor" | "24bit") => Self::TrueColor,
+            _ => Self::Ansi256,
+        }
+    }
+
+    /// `text` on `background`, or just `text` when plain.
+    fn paint(self, background: Rgb, foreground: Rgb, text: &str) -> String {
+        let ((br, bg, bb), (fr, fg, fb)) = (background, foreground);
+        match self {
+            Self::TrueColor => {
+                format!("\x1b[48;2;{br};{bg};{bb}m\x1b[38;2;{fr};{fg};{fb}m{text}{RESET}")
+            }
+            Self::Ansi256 => format!(
+                "\x1b[48;5;{}m\x1b[38;5;{}m{text}{RESET}",
+                ansi256(background),
+                ansi256(foreground)
+            ),
+            Self::Plain => text.to_string(),
+        }
+    }
+}
+
+type Rgb = (u8, u8, u8);
+
+const RESET: &str = "\x1b[0m";
+
+/// Same stops as the HTML report's keyboard, cold to hot.
+const HEAT_STOPS: [Rgb; 5] = [
+    (13, 20, 36),
+    (19, 78, 74),
+    (13, 148, 136),
+    (34, 211, 238),
+    (245, 158, 11),
+];
+
+/// Same as the HTML report's activity grid, empty then levels 1 to 5.
+const GRID_LEVELS: [Rgb; 6] = [
+    (17, 24, 39),
+    (19, 78, 74),
+    (15, 118, 110),
+    (13, 148, 136),
+    (20, 184, 166),
+    (34, 211, 238),
+];
+
+/// Shading without colour, from nothing to most.
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+const LIGHT_TEXT: Rgb = (240, 246, 252);
+const DARK_TEXT: Rgb = (3, 7, 18);
+const DIM_TEXT: Rgb = (75, 85, 99);
+
+/// `count` out of `max` from 0 to 1, eased like the HTML heatmap so rarely
+/// used keys still show.
+fn heat(count: u64, max: u64) -> f64 {
+    if count == 0 || max == 0 {
+        return 0.0;
+    }
+    (count as f64 / max as f64).min(1.0).powf(0.45)
+}
+
+fn heat_color(t: f64) -> Rgb {
+    let idx = t * (HEAT_STOPS.len() - 1) as f64;
+    let lo = (idx.floor() as usize).min(HEAT_STOPS.len() - 1);
+    let hi = (lo + 1).min(HEAT_STOPS.len() - 1);
+    let f = idx - lo as f64;
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f).round() as u8;
+    let (lo, hi) = (HEAT_STOPS[lo], HEAT_STOPS[hi]);
+    (mix(lo.0, hi.0), mix(lo.1, hi.1), mix(lo.2, hi.2))
+}
+
+/// Nearest colour in the 6x6x6 cube of the 256-colour palette.
+fn ansi256((r, g, b): Rgb) -> u8 {
+    let level = |v: u8| {
+        if v < 48 {
+            0
+        } else if v < 115 {
+            1
+        } else {
+            (v - 35) / 40
+        }
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+/// Draws `layout` as rows of key cells shaded by `counts`, at most
+/// `max_width` columns wide.
+pub fn render_keyboard(
+    layout: &Layout,
+    counts: &HashMap<String, u64>,
+    max_width: usize,
+    color: ColorMode,
+) -> Vec<String> {
+    let (width, height) = layout.bounds();
+    // columns per key unit; four fits a full-size board in 80 columns
+    let unit = (max_width as f64 / width.max(1.0)).floor().clamp(1.0, 4.0);
+    let max = layout
+        .keys
+        .iter()
+        .filter_map(|k| counts.get(&k.key_code))
+        .copied()
+        .max()
+        .unwrap_or(0);
+
+    let rows = height.ceil().max(1.0) as usize;
+    let mut lines = Vec::new();
+    for row in 0..rows {
+        let mut keys: Vec<_> = layout
+            .keys
+            .iter()
+            .filter(|k| k.y.round() as usize == row)
+            .collect();
+        if keys.is_empty() {
+            continue;
+        }
+        keys.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+        let mut line = String::new();
+        let mut column = 0;
+        for key in keys {
+            let start = ((key.x * unit).round() as usize).max(column);
+            let end = (((key.x + key.width) * unit).round() as usize).min(max_width);
+            // one column of gap between keys
+            let cell = end.saturating_sub(start + 1);
+            if cell == 0 {
+                continue;
+            }
+
+            let t = heat(counts.get(&key.key_code).copied().unwrap_or(0), max);
+            let label: String = key.label.chars().take(cell).collect();
+            line.push_str(&" ".repeat(start - column));
+            if color == ColorMode::Plain {
+                // pad the label with a shade instead
+                let shade = SHADES[(t * (SHADES.len() - 1) as f64).ceil() as usize];
+                let pad = cell - label.chars().count();
+                let left = shade.to_string().repeat(pad / 2);
+                let right = shade.to_string().repeat(pad - pad / 2);
+                line.push_str(&format!("{left}{label}{right}"));
+            } else {
+                let background = heat_color(t);
+                let (r, g, b) = background;
+                let luma = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+                let text = if luma > 140.0 { DARK_TEXT } else { LIGHT_TEXT };
+                line.push_str(&color.paint(background, text, &format!("{label:^cell$}")));
+            }
+            column = start + cell;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Draws rows of keystrokes per hour as two columns per hour, under an
+/// hour axis. Hours paused with nothing recorded show as `//`.
+pub fn render_activity(
+    grid: &[(String, [u64; 24])],
+    paused: &[[bool; 24]],
+    color: ColorMode,
+) -> Vec<String> {
+    let label_width = grid
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max = grid
+        .iter()
+        .flat_map(|(_, hours)| hours)
+        .copied()
+        .max()
+        .unwrap_or(0);
+
+    let axis: String = (0..24)
+        .step_by(6)
+        .map(|hour| format!("{hour:<12}"))
+        .collect();
+    let mut lines = vec![format!("{:label_width$} {}", "", axis.trim_end())];
+    for (i, (label, hours)) in grid.iter().enumerate() {
+        let mut line = format!("{label:<label_width$} ");
+        for (hour, &count) in hours.iter().enumerate() {
+            let was_paused = paused.get(i).is_some_and(|row| row[hour]);
+            // same levels as the HTML grid
+            let level = if count == 0 || max == 0 {
+                0
+            } else {
+                (count as f64 / max as f64 * 5.0).ceil().min(5.0) as usize
+            };
+            let cell = match color {
+                ColorMode::Plain if was_paused && count == 0 => "//".to_string(),
+                ColorMode::Plain if level == 0 => "··".to_string(),
+                // five levels onto four shades
+                ColorMode::Plain => SHADES[(level + 1) * 4 / 6].to_string().repeat(2),
+                _ if was_paused && count == 0 => color.paint(GRID_LEVELS[0], DIM_TEXT, "//"),
+                _ => color.paint(GRID_LEVELS[level], LIGHT_TEXT, "  "),
+            };
+            line.push_str(&cell);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::LayoutSpec;
+
+    fn visible_width(line: &str) -> usize {
+        let mut width = 0;
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                width += 1;
+            }
+        }
+        width
+    }
+
+    #[test]
+    fn keyboard_is_shaded_by_count_and_fits_the_width() {
+        let layout = Layout::build(&LayoutSpec::default()).unwrap();
+        let counts = HashMap::from([("e".to_string(), 500), ("a".to_string(), 5)]);
+
+        let lines = render_keyboard(&layout, &counts, 80, ColorMode::TrueColor);
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().all(|line| visible_width(line) <= 80));
+        // the busiest key gets the hottest stop
+        assert!(lines[1].contains("\x1b[48;2;245;158;11m\x1b[38;2;3;7;18m E "));
+
+        let narrow = render_keyboard(&layout, &counts, 40, ColorMode::Ansi256);
+        assert!(narrow.iter().all(|line| visible_width(line) <= 40));
+        assert_eq!(ansi256((245, 158, 11)), 214);
+
+        let plain = render_keyboard(&layout, &counts, 80, ColorMode::Plain);
+        assert!(plain.iter().all(|line| !line.contains('\x1b')));
+        assert!(plain[1].contains("█E█"));
+    }
+
+    #[test]
+    fn activity_grid_has_two_columns_per_hour() {
+        let mut monday = [0; 24];
+        monday[9] = 100;
+        monday[10] = 10;
+        let mut paused = [false; 24];
+        paused[12] = true;
+        let grid = vec![("Mon".to_string(), monday), ("Tue".to_string(), [0; 24])];
+
+        let lines = render_activity(&grid, &[paused, [false; 24]], ColorMode::Plain);
+        assert_eq!(lines[0], "    0           6           12          18");
+        assert_eq!(lines[1].chars().count(), 4 + 48);
+        assert!(lines[1].starts_with("Mon ··"));
+        let nine_to_one: String = lines[1].chars().skip(4 + 9 * 2).take(8).collect();
+        assert_eq!(nine_to_one, "██░░··//");
+        assert!(lines[2].ends_with("····"));
+
+        let lines = render_activity(&grid, &[paused], ColorMode::TrueColor);
+        assert!(lines[1].contains("\x1b[48;2;34;211;238m"));
+        assert_eq!(visible_width(&lines[1]), 4 + 48);
+    }
+}
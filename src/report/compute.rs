@@ -1,69 +1,138 @@
-use super::query::{self, RawSession};
-use super::{ReportData, SessionSummary, ShortcutInsight, WeekRange};
+use super::fingers::{Finger, Hand};
+use super::layout::Layout;
+use super::query::{
+    self, BigramCount, Histogram, HourlyKeyCount, RawPause, RawSession, TrigramCount,
+};
+use super::range::DateRange;
+use super::{
+    DayPeriodKeys, DeviceUsage, FingerLoad, FingerStats, FingerWarning, KeyTiming, NgramStats,
+    PausePeriod, ReportData, ReportOptions, SessionSummary, ShortcutInsight, TimingStats,
+    TransitionTiming,
+};
+use crate::aggregator::{TIMING_BUCKETS_MS, TIMING_MAX};
 use crate::error::Error;
-use chrono::{Datelike, Duration, NaiveDate, Timelike, Utc, Weekday};
+use crate::timezone::Zone;
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, Timelike, Utc, Weekday};
 use rusqlite::Connection;
 use std::collections::HashMap;
 
 const KEY_PITCH_MM: f64 = 19.05;
 
-pub fn build_report(conn: &Connection, week: Option<NaiveDate>) -> Result<ReportData, Error> {
-    let week_range = compute_week_range(week);
-    let prev_week_range = compute_prev_week_range(&week_range);
+/// Fewer samples than this give a median too noisy to call a key slow.
+const MIN_KEY_TIMING_SAMPLES: u64 = 20;
+const MIN_TRANSITION_TIMING_SAMPLES: u64 = 10;
+const SLOWEST_TIMINGS_SHOWN: usize = 5;
+const TOP_SAME_FINGER_SHOWN: usize = 5;
+/// Share of all keystrokes above which a pinky is flagged as overworked.
+const PINKY_OVERLOAD_SHARE: f64 = 0.10;
+const WARNING_KEYS_SHOWN: usize = 3;
+const DAY_PERIODS: [(&str, u8, u8); 4] = [
+    ("Night", 0, 6),
+    ("Morning", 6, 12),
+    ("Afternoon", 12, 18),
+    ("Evening", 18, 24),
+];
+const DAY_PERIOD_KEYS_SHOWN: usize = 5;
+/// A key stands out in a part of the day when its share there is this many
+/// times its share overall, over at least this many presses.
+const STANDOUT_RATIO: f64 = 1.5;
+const MIN_STANDOUT_COUNT: u64 = 20;
+
+pub fn build_report(conn: &Connection, options: &ReportOptions) -> Result<ReportData, Error> {
+    let range = options.range.resolve();
+    let prev_range = options.range.previous();
+    let filter = query::Filter {
+        device: options.device.as_deref(),
+        machine: options.machine.as_ref().map(|m| m.id.as_str()),
+    };
+    let layout = Layout::build(&options.layout)?;
+    let zone = options.zone;
+    let (from, to) = zone.day_bounds(range.start, range.end);
+    let (prev_from, prev_to) = zone.day_bounds(prev_range.start, prev_range.end);
+
+    let key_counts = query::key_counts_for_range(conn, range.start, range.end, filter)?;
+    let total_keystrokes = query::total_keystrokes_for_range(conn, range.start, range.end, filter)?;
+    let prev_keystrokes =
+        query::total_keystrokes_for_range(conn, prev_range.start, prev_range.end, filter).ok();
+    let all_time_keystrokes = query::all_time_keystrokes(conn, filter)?;
+
+    let sessions = query::sessions_for_range(conn, from, to, filter)?;
+    let pauses = query::pauses_for_range(conn, from, to, filter.machine)?;
+    let hourly_counts = query::hourly_key_counts_for_range(conn, from, to, filter)?;
+    let period_counts = query::period_key_counts_for_range(conn, from, to, filter)?;
+    let wpm_bins = query::wpm_bins_for_range(conn, from, to, filter)?;
+    let shortcuts = query::shortcuts_for_range(conn, range.start, range.end, filter)?;
+    let prev_shortcuts = query::shortcuts_for_range(conn, prev_range.start, prev_range.end, filter)
+        .unwrap_or_default();
 
-    let key_counts = query::key_counts_for_range(conn, week_range.start, week_range.end)?;
-    let total_keystrokes =
-        query::total_keystrokes_for_range(conn, week_range.start, week_range.end)?;
-    let prev_week_keystrokes =
-        query::total_keystrokes_for_range(conn, prev_week_range.start, prev_week_range.end).ok();
-    let all_time_keystrokes = query::all_time_keystrokes(conn)?;
+    let peak_wpm_info = query::peak_wpm_session_for_range(conn, from, to, filter)?;
 
-    let sessions = query::sessions_for_range(conn, week_range.start, week_range.end)?;
-    let wpm_samples = query::wpm_samples_for_range(conn, week_range.start, week_range.end)?;
-    let shortcuts = query::shortcuts_for_range(conn, week_range.start, week_range.end)?;
-    let prev_week_shortcuts =
-        query::shortcuts_for_range(conn, prev_week_range.start, prev_week_range.end)
-            .unwrap_or_default();
+    let prev_sessions =
+        query::sessions_for_range(conn, prev_from, prev_to, filter).unwrap_or_default();
+    let prev_avg_wpm = compute_avg_wpm(&prev_sessions);
 
-    let peak_wpm_info = query::peak_wpm_session_for_range(conn, week_range.start, week_range.end)?;
+    let timing = compute_timing_stats(
+        &query::dwell_histograms_for_range(conn, range.start, range.end, filter)?,
+        &query::flight_histograms_for_range(conn, range.start, range.end, filter)?,
+    );
 
-    let prev_sessions = query::sessions_for_range(conn, prev_week_range.start, prev_week_range.end)
-        .unwrap_or_default();
-    let prev_week_avg_wpm = compute_avg_wpm(&prev_sessions);
+    let ngrams = compute_ngram_stats(
+        &query::bigram_counts_for_range(conn, range.start, range.end, filter)?,
+        &query::trigram_counts_for_range(conn, range.start, range.end, filter)?,
+        &layout,
+    );
+
+    let devices = compute_device_usage(
+        query::keystrokes_by_device(conn, range.start, range.end, filter.machine)?,
+        query::avg_wpm_by_device(conn, from, to, filter.machine)?,
+    );
 
     let key_frequencies = compute_key_frequencies(&key_counts);
     let session_summaries = compute_session_summaries(&sessions);
     let longest_session = find_longest_session(&session_summaries);
     let total_typing_minutes = compute_total_typing_minutes(&sessions);
     let avg_wpm = compute_avg_wpm(&sessions).unwrap_or(0.0);
-    let (peak_wpm, peak_wpm_time) = peak_wpm_info.unzip();
-    let daily_wpm = compute_daily_wpm(&sessions, &week_range);
-    let wpm_distribution = compute_wpm_distribution(&wpm_samples);
-    let hourly_activity = compute_hourly_activity(&sessions, &week_range);
-    let finger_travel_mm = compute_finger_travel(&key_counts);
+    let (peak_wpm, peak_wpm_time) = peak_wpm_info
+        .map(|(wpm, time)| (wpm, zone.to_local(time)))
+        .unzip();
+    let wpm_trend = compute_wpm_trend(&sessions, &range, zone);
+    let wpm_distribution = compute_wpm_distribution(&wpm_bins);
+    let hourly_activity = compute_hourly_activity(&hourly_counts, &sessions, &range, zone);
+    let paused_hours = compute_paused_hours(&pauses, &range, zone, (from, to));
+    let day_periods = compute_day_periods(&hourly_counts, &period_counts, zone);
+    let finger_travel_mm = compute_finger_travel(&key_counts, &layout);
+    let fingers = compute_finger_stats(&key_counts, &layout);
     let backspace_ratio = compute_backspace_ratio(&key_counts, total_keystrokes);
-    let fastest_day = compute_fastest_day(&sessions);
+    let fastest_day = compute_fastest_day(&sessions, zone);
     let peak_hour = compute_peak_hour(&hourly_activity);
-    let night_owl_pct = compute_night_owl_pct(&sessions);
-    let shortcut_insight = generate_shortcut_insight(&shortcuts);
+    let night_owl_pct = compute_night_owl_pct(&hourly_activity);
+    let shortcut_insight = generate_shortcut_insight(&shortcuts, &range);
 
     Ok(ReportData {
-        week: week_range,
+        range,
+        timezone: zone.name(),
+        device: options.device.clone(),
+        machine: options.machine.as_ref().map(|m| m.name.clone()),
+        layout,
+        devices,
         total_keystrokes,
-        prev_week_keystrokes,
+        prev_keystrokes,
         key_frequencies,
         avg_wpm,
         peak_wpm: peak_wpm.unwrap_or(0.0),
         peak_wpm_time,
-        prev_week_avg_wpm,
-        daily_wpm,
+        prev_avg_wpm,
+        wpm_trend,
         wpm_distribution,
         sessions: session_summaries,
         total_typing_minutes,
         longest_session,
         hourly_activity,
+        pauses: compute_pause_periods(&pauses),
+        paused_hours,
+        day_periods,
         shortcuts,
-        prev_week_shortcuts,
+        prev_shortcuts,
         all_time_keystrokes,
         finger_travel_mm,
         backspace_ratio,
@@ -71,36 +140,173 @@ pub fn build_report(conn: &Connection, week: Option<NaiveDate>) -> Result<Report
         peak_hour,
         night_owl_pct,
         shortcut_insight,
+        timing,
+        ngrams,
+        fingers,
     })
 }
 
-fn compute_week_range(date: Option<NaiveDate>) -> WeekRange {
-    let target = date.unwrap_or_else(|| Utc::now().date_naive());
+fn compute_device_usage(
+    keystrokes: Vec<(String, u64)>,
+    avg_wpm: HashMap<String, f64>,
+) -> Vec<DeviceUsage> {
+    keystrokes
+        .into_iter()
+        .map(|(device, keystrokes)| DeviceUsage {
+            avg_wpm: avg_wpm.get(&device).copied(),
+            device,
+            keystrokes,
+        })
+        .collect()
+}
 
-    let days_since_monday = target.weekday().num_days_from_monday();
-    let start = target - Duration::days(days_since_monday as i64);
-    let end = start + Duration::days(6);
+fn compute_timing_stats(
+    dwell: &HashMap<String, Histogram>,
+    flight: &HashMap<(String, String), Histogram>,
+) -> TimingStats {
+    let mut slowest_keys: Vec<KeyTiming> = dwell
+        .iter()
+        .filter_map(|(key, hist)| {
+            let samples = histogram_samples(hist);
+            if samples < MIN_KEY_TIMING_SAMPLES {
+                return None;
+            }
+            Some(KeyTiming {
+                key: key.clone(),
+                median_ms: histogram_median(hist)?,
+                samples,
+            })
+        })
+        .collect();
+    slowest_keys.sort_by(|a, b| b.median_ms.total_cmp(&a.median_ms));
+    slowest_keys.truncate(SLOWEST_TIMINGS_SHOWN);
 
-    let label = format!(
-        "Week of {} - {}",
-        start.format("%b %d"),
-        end.format("%b %d, %Y")
-    );
+    let mut slowest_transitions: Vec<TransitionTiming> = flight
+        .iter()
+        .filter_map(|((from, to), hist)| {
+            let samples = histogram_samples(hist);
+            if samples < MIN_TRANSITION_TIMING_SAMPLES {
+                return None;
+            }
+            Some(TransitionTiming {
+                from: from.clone(),
+                to: to.clone(),
+                median_ms: histogram_median(hist)?,
+                samples,
+            })
+        })
+        .collect();
+    slowest_transitions.sort_by(|a, b| b.median_ms.total_cmp(&a.median_ms));
+    slowest_transitions.truncate(SLOWEST_TIMINGS_SHOWN);
+
+    TimingStats {
+        median_dwell_ms: histogram_median(&merge_histograms(dwell.values())),
+        median_flight_ms: histogram_median(&merge_histograms(flight.values())),
+        slowest_keys,
+        slowest_transitions,
+    }
+}
+
+fn compute_ngram_stats(
+    bigrams: &[BigramCount],
+    trigrams: &[TrigramCount],
+    layout: &Layout,
+) -> NgramStats {
+    let mut stats = NgramStats::default();
+    let mut same_finger = 0;
+
+    // bigrams come sorted by count, so the first same-finger ones are the top ones
+    for ((first, second), count) in bigrams {
+        let count = *count;
+        let (Some(a), Some(b)) = (layout.finger(first), layout.finger(second)) else {
+            continue;
+        };
+        if a.is_thumb() || b.is_thumb() {
+            continue;
+        }
+
+        stats.bigrams += count;
+        // repeating a key isn't a finger movement
+        if a == b && first != second {
+            same_finger += count;
+            if stats.top_same_finger.len() < TOP_SAME_FINGER_SHOWN {
+                stats
+                    .top_same_finger
+                    .push((first.clone(), second.clone(), count));
+            }
+        }
+    }
+
+    let (mut alternations, mut rolls) = (0, 0);
+    for ((first, second, third), count) in trigrams {
+        let count = *count;
+        let fingers = [first, second, third].map(|k| layout.finger(k));
+        let [Some(a), Some(b), Some(c)] = fingers else {
+            continue;
+        };
+        if a.is_thumb() || b.is_thumb() || c.is_thumb() {
+            continue;
+        }
+        let (ha, hb, hc) = (a.hand(), b.hand(), c.hand());
+
+        stats.trigrams += count;
+        if ha != hb && hb != hc {
+            alternations += count;
+        } else if (ha == hb && hb != hc && a != b) || (ha != hb && hb == hc && b != c) {
+            rolls += count;
+        }
+    }
 
-    WeekRange { start, end, label }
+    if stats.bigrams > 0 {
+        stats.same_finger_ratio = same_finger as f64 / stats.bigrams as f64;
+    }
+    if stats.trigrams > 0 {
+        stats.alternation_ratio = alternations as f64 / stats.trigrams as f64;
+        stats.roll_ratio = rolls as f64 / stats.trigrams as f64;
+    }
+
+    stats
 }
 
-fn compute_prev_week_range(current: &WeekRange) -> WeekRange {
-    let start = current.start - Duration::days(7);
-    let end = current.end - Duration::days(7);
+fn histogram_samples(hist: &Histogram) -> u64 {
+    hist.iter().map(|(_, count)| count).sum()
+}
 
-    let label = format!(
-        "Week of {} - {}",
-        start.format("%b %d"),
-        end.format("%b %d, %Y")
-    );
+fn merge_histograms<'a>(hists: impl Iterator<Item = &'a Histogram>) -> Histogram {
+    let mut merged: HashMap<u32, u64> = HashMap::new();
+    for (bucket, count) in hists.flatten() {
+        *merged.entry(*bucket).or_insert(0) += count;
+    }
+    merged.into_iter().collect()
+}
 
-    WeekRange { start, end, label }
+/// Median of a bucketed histogram, interpolated within the bucket it falls in.
+fn histogram_median(hist: &Histogram) -> Option<f64> {
+    let total = histogram_samples(hist);
+    if total == 0 {
+        return None;
+    }
+
+    let mut sorted = hist.clone();
+    sorted.sort_by_key(|(bucket, _)| *bucket);
+
+    let target = total as f64 / 2.0;
+    let mut seen = 0.0;
+    for (bucket, count) in sorted {
+        let count = count as f64;
+        if seen + count >= target {
+            let upper = TIMING_BUCKETS_MS
+                .iter()
+                .copied()
+                .find(|&b| b > bucket)
+                .unwrap_or(TIMING_MAX.as_millis() as u32);
+            let fraction = (target - seen) / count;
+            return Some(bucket as f64 + fraction * (upper - bucket) as f64);
+        }
+        seen += count;
+    }
+
+    None
 }
 
 fn compute_key_frequencies(counts: &HashMap<String, u64>) -> Vec<(String, u64)> {
@@ -161,36 +367,55 @@ fn compute_avg_wpm(sessions: &[RawSession]) -> Option<f64> {
     }
 }
 
-fn compute_daily_wpm(sessions: &[RawSession], week: &WeekRange) -> Vec<(NaiveDate, f64)> {
-    let mut daily: HashMap<NaiveDate, Vec<f64>> = HashMap::new();
+/// Average WPM per day, or per week or month for longer ranges.
+fn compute_wpm_trend(
+    sessions: &[RawSession],
+    range: &DateRange,
+    zone: Zone,
+) -> Vec<(NaiveDate, f64)> {
+    let step = range.trend_step();
+    let mut buckets = Vec::new();
+    let mut date = step.first(range.start);
+    while date <= range.end {
+        buckets.push((date, Vec::new()));
+        date = step.next(date);
+    }
 
     for session in sessions {
-        if let Some(wpm) = session.avg_wpm {
-            let date = session.start_time.date_naive();
-            daily.entry(date).or_default().push(wpm);
+        let Some(wpm) = session.avg_wpm else {
+            continue;
+        };
+        let date = zone.naive_local(session.start_time).date();
+        if !range.contains(date) {
+            continue;
+        }
+        // buckets are in order, so the last one starting on or before the date holds it
+        let idx = buckets.partition_point(|(start, _)| *start <= date);
+        if let Some((_, wpms)) = idx.checked_sub(1).and_then(|i| buckets.get_mut(i)) {
+            wpms.push(wpm);
         }
     }
 
-    let mut result = Vec::new();
-    let mut date = week.start;
-    while date <= week.end {
-        let avg = daily
-            .get(&date)
-            .map(|wpms| wpms.iter().sum::<f64>() / wpms.len() as f64)
-            .unwrap_or(0.0);
-        result.push((date, avg));
-        date += Duration::days(1);
-    }
-
-    result
+    buckets
+        .into_iter()
+        .map(|(date, wpms)| {
+            let avg = if wpms.is_empty() {
+                0.0
+            } else {
+                wpms.iter().sum::<f64>() / wpms.len() as f64
+            };
+            (date, avg)
+        })
+        .collect()
 }
 
-fn compute_wpm_distribution(samples: &[query::RawWpmSample]) -> Vec<u32> {
+fn compute_wpm_distribution(bins: &[query::WpmBin]) -> Vec<u32> {
     // buckets: 0-20, 20-40, 40-60, 60-80, 80-100, 100-120, 120+
     let mut buckets = vec![0u32; 7];
 
-    for sample in samples {
-        let idx = match sample.wpm as u32 {
+    // bins are 10 WPM wide, so each falls in exactly one bucket
+    for bin in bins {
+        let idx = match bin.wpm {
             0..=19 => 0,
             20..=39 => 1,
             40..=59 => 2,
@@ -199,93 +424,276 @@ fn compute_wpm_distribution(samples: &[query::RawWpmSample]) -> Vec<u32> {
             100..=119 => 5,
             _ => 6,
         };
-        buckets[idx] += 1;
+        buckets[idx] += bin.samples as u32;
     }
 
     buckets
 }
 
-fn compute_hourly_activity(sessions: &[RawSession], week: &WeekRange) -> [[u64; 24]; 7] {
-    let mut grid = [[0u64; 24]; 7];
-
-    for session in sessions {
-        let date = session.start_time.date_naive();
-        if date < week.start || date > week.end {
-            continue;
+/// Keystrokes per hour, one row per day for up to two weeks. Longer ranges
+/// fold into one row per weekday. Hours recorded before hourly counts
+/// existed fall back to crediting each session to the hour it started.
+fn compute_hourly_activity(
+    hourly: &[HourlyKeyCount],
+    sessions: &[RawSession],
+    range: &DateRange,
+    zone: Zone,
+) -> Vec<(String, [u64; 24])> {
+    let per_day = range.days() <= 14;
+    let mut grid: Vec<(String, [u64; 24])> = if per_day {
+        let format = if range.days() <= 7 { "%a" } else { "%b %-d" };
+        range
+            .start
+            .iter_days()
+            .take(range.days() as usize)
+            .map(|date| (date.format(format).to_string(), [0; 24]))
+            .collect()
+    } else {
+        ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+            .iter()
+            .map(|day| (day.to_string(), [0; 24]))
+            .collect()
+    };
+
+    let mut add = |instant, count| {
+        let local = zone.naive_local(instant);
+        let Some(row) = activity_row(range, local.date()) else {
+            return;
+        };
+        if let Some((_, hours)) = grid.get_mut(row) {
+            hours[local.hour() as usize] += count;
         }
+    };
 
-        let day_idx = (date - week.start).num_days() as usize;
-        if day_idx >= 7 {
-            continue;
-        }
+    for row in hourly {
+        add(row.hour, row.count);
+    }
 
-        let hour = session.start_time.hour() as usize;
-        grid[day_idx][hour] += session.keystroke_count;
+    // rows are sorted, so the first one marks where hourly counts begin
+    let hourly_since = hourly.first().map(|row| row.hour);
+    for session in sessions {
+        if hourly_since.is_none_or(|since| session.start_time < since) {
+            add(session.start_time, session.keystroke_count);
+        }
     }
 
     grid
 }
 
-fn compute_finger_travel(counts: &HashMap<String, u64>) -> f64 {
-    let distances = finger_distance_map();
-    let mut total_mm = 0.0;
+/// Row of the activity grid a local date falls in: one per day for up to
+/// two weeks, otherwise one per weekday.
+fn activity_row(range: &DateRange, date: NaiveDate) -> Option<usize> {
+    if !range.contains(date) {
+        return None;
+    }
+    if range.days() <= 14 {
+        Some((date - range.start).num_days() as usize)
+    } else {
+        Some(date.weekday().num_days_from_monday() as usize)
+    }
+}
 
-    for (key, &count) in counts {
-        if let Some(&distance) = distances.get(key.as_str()) {
-            total_mm += distance * count as f64;
+/// Marks the activity grid cells a pause overlaps, within `bounds`.
+fn compute_paused_hours(
+    pauses: &[RawPause],
+    range: &DateRange,
+    zone: Zone,
+    (from, to): (DateTime<Utc>, DateTime<Utc>),
+) -> Vec<[bool; 24]> {
+    let rows = if range.days() <= 14 {
+        range.days() as usize
+    } else {
+        7
+    };
+    let mut grid = vec![[false; 24]; rows];
+
+    let now = Utc::now();
+    for pause in pauses {
+        let end = pause.end_time.unwrap_or(now).min(to);
+        let mut hour = zone.hour_start(pause.start_time.max(from));
+        while hour < end {
+            let local = zone.naive_local(hour);
+            if let Some(row) = activity_row(range, local.date()).and_then(|r| grid.get_mut(r)) {
+                row[local.hour() as usize] = true;
+            }
+            hour += TimeDelta::hours(1);
         }
     }
 
-    total_mm
+    grid
+}
+
+fn compute_pause_periods(pauses: &[RawPause]) -> Vec<PausePeriod> {
+    let now = Utc::now();
+    pauses
+        .iter()
+        .map(|pause| PausePeriod {
+            start_time: pause.start_time,
+            end_time: pause.end_time,
+            duration_minutes: (pause.end_time.unwrap_or(now) - pause.start_time).num_seconds()
+                as f64
+                / 60.0,
+            reason: pause.reason.clone(),
+        })
+        .collect()
 }
 
-fn finger_distance_map() -> HashMap<&'static str, f64> {
-    let mut map = HashMap::new();
+/// Splits hourly key counts into parts of the local day.
+/// Index into the parts of the day for a local hour.
+pub fn day_period(hour: u32) -> Option<usize> {
+    DAY_PERIODS
+        .iter()
+        .position(|&(_, start, end)| (start as u32..end as u32).contains(&hour))
+}
 
-    // home row keys have 0 distance
-    for key in ["a", "s", "d", "f", "j", "k", "l", "semicolon"] {
-        map.insert(key, 0.0);
+/// `periods` holds compacted counts, each at the first hour of its part of
+/// the day, and adds to `hourly` the same way.
+fn compute_day_periods(
+    hourly: &[HourlyKeyCount],
+    periods: &[HourlyKeyCount],
+    zone: Zone,
+) -> Vec<DayPeriodKeys> {
+    // hour totals carry no key
+    let rows: Vec<_> = periods
+        .iter()
+        .chain(hourly)
+        .filter(|row| !row.key_code.is_empty())
+        .collect();
+    if rows.is_empty() {
+        return Vec::new();
     }
 
-    // one row up
-    for key in ["q", "w", "e", "r", "u", "i", "o", "p"] {
-        map.insert(key, KEY_PITCH_MM);
+    let mut per_period: Vec<HashMap<&str, u64>> = vec![HashMap::new(); DAY_PERIODS.len()];
+    let mut overall: HashMap<&str, u64> = HashMap::new();
+    for row in rows {
+        let Some(idx) = day_period(zone.naive_local(row.hour).hour()) else {
+            continue;
+        };
+        *per_period[idx].entry(&row.key_code).or_insert(0) += row.count;
+        *overall.entry(&row.key_code).or_insert(0) += row.count;
+    }
+    let overall_total: u64 = overall.values().sum();
+
+    DAY_PERIODS
+        .iter()
+        .zip(per_period)
+        .map(|(&(label, start, end), counts)| {
+            let keystrokes: u64 = counts.values().sum();
+
+            let standout = counts
+                .iter()
+                .filter(|(_, &count)| count >= MIN_STANDOUT_COUNT)
+                .map(|(&key, &count)| {
+                    let share = count as f64 / keystrokes as f64;
+                    let overall_share = overall[key] as f64 / overall_total as f64;
+                    (key, share / overall_share)
+                })
+                .filter(|(_, ratio)| *ratio >= STANDOUT_RATIO)
+                .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+                .map(|(key, _)| key.to_string());
+
+            let mut top_keys: Vec<(String, u64)> = counts
+                .into_iter()
+                .map(|(key, count)| (key.to_string(), count))
+                .collect();
+            top_keys.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            top_keys.truncate(DAY_PERIOD_KEYS_SHOWN);
+
+            DayPeriodKeys {
+                label,
+                hours: (start, end),
+                keystrokes,
+                top_keys,
+                standout,
+            }
+        })
+        .collect()
+}
+
+fn compute_finger_stats(counts: &HashMap<String, u64>, layout: &Layout) -> FingerStats {
+    let mut per_finger: HashMap<Finger, (u64, f64)> = HashMap::new();
+    let mut keys_by_finger: HashMap<Finger, Vec<(&str, u64)>> = HashMap::new();
+
+    for (key, &count) in counts {
+        let Some(finger) = layout.finger(key) else {
+            continue;
+        };
+        let travel = layout.travel(key).unwrap_or(0.0) * KEY_PITCH_MM * count as f64;
+        let entry = per_finger.entry(finger).or_insert((0, 0.0));
+        entry.0 += count;
+        entry.1 += travel;
+        keys_by_finger.entry(finger).or_default().push((key, count));
     }
-    map.insert("t", KEY_PITCH_MM * 1.5); // index stretch
-    map.insert("y", KEY_PITCH_MM * 1.5);
 
-    // one row down
-    for key in ["z", "x", "c", "v", "m", "comma", "period", "slash"] {
-        map.insert(key, KEY_PITCH_MM);
+    let total: u64 = per_finger.values().map(|(count, _)| count).sum();
+    if total == 0 {
+        return FingerStats::default();
     }
-    map.insert("b", KEY_PITCH_MM * 1.5);
-    map.insert("n", KEY_PITCH_MM * 1.5);
 
-    // number row (2 rows up)
-    for key in ["1", "2", "3", "4", "5", "6", "7", "8", "9", "0"] {
-        map.insert(key, KEY_PITCH_MM * 2.0);
+    let fingers: Vec<FingerLoad> = Finger::ALL
+        .iter()
+        .map(|&finger| {
+            let (keystrokes, travel_mm) = per_finger.get(&finger).copied().unwrap_or_default();
+            FingerLoad {
+                finger,
+                keystrokes,
+                share: keystrokes as f64 / total as f64,
+                travel_mm,
+            }
+        })
+        .collect();
+
+    // thumbs mostly hit space, which would swamp the hand balance
+    let (mut left, mut right) = (0, 0);
+    for load in fingers.iter().filter(|l| !l.finger.is_thumb()) {
+        match load.finger.hand() {
+            Hand::Left => left += load.keystrokes,
+            Hand::Right => right += load.keystrokes,
+        }
     }
-    map.insert("grave", KEY_PITCH_MM * 2.5);
-    map.insert("minus", KEY_PITCH_MM * 2.0);
-    map.insert("equal", KEY_PITCH_MM * 2.0);
+    let left_hand_share = if left + right > 0 {
+        left as f64 / (left + right) as f64
+    } else {
+        0.0
+    };
 
-    // right side home row extensions
-    map.insert("apostrophe", KEY_PITCH_MM);
-    map.insert("leftbracket", KEY_PITCH_MM * 1.5);
-    map.insert("rightbracket", KEY_PITCH_MM * 2.0);
-    map.insert("backslash", KEY_PITCH_MM * 2.5);
+    let warnings = fingers
+        .iter()
+        .filter(|l| l.finger.is_pinky() && l.share > PINKY_OVERLOAD_SHARE)
+        .map(|load| {
+            let mut keys = keys_by_finger.remove(&load.finger).unwrap_or_default();
+            keys.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            FingerWarning {
+                finger: load.finger,
+                share: load.share,
+                top_keys: keys
+                    .into_iter()
+                    .take(WARNING_KEYS_SHOWN)
+                    .map(|(key, _)| key.to_string())
+                    .collect(),
+            }
+        })
+        .collect();
 
-    // special keys
-    map.insert("space", KEY_PITCH_MM * 0.5); // thumbs move less
-    map.insert("enter", KEY_PITCH_MM);
-    map.insert("backspace", KEY_PITCH_MM * 2.5);
-    map.insert("tab", KEY_PITCH_MM * 1.5);
+    FingerStats {
+        fingers,
+        left_hand_share,
+        warnings,
+    }
+}
+
+/// Total distance in mm, measuring each press from the home key of the
+/// finger the layout assigns to it. The way back is free.
+fn compute_finger_travel(counts: &HashMap<String, u64>, layout: &Layout) -> f64 {
+    let mut total_mm = 0.0;
 
-    // g and h are index finger stretches
-    map.insert("g", KEY_PITCH_MM * 0.5);
-    map.insert("h", KEY_PITCH_MM * 0.5);
+    for (key, &count) in counts {
+        if let Some(distance) = layout.travel(key) {
+            total_mm += distance * KEY_PITCH_MM * count as f64;
+        }
+    }
 
-    map
+    total_mm
 }
 
 fn compute_backspace_ratio(counts: &HashMap<String, u64>, total: u64) -> f64 {
@@ -297,12 +705,12 @@ fn compute_backspace_ratio(counts: &HashMap<String, u64>, total: u64) -> f64 {
     backspace_count as f64 / total as f64
 }
 
-fn compute_fastest_day(sessions: &[RawSession]) -> Option<String> {
+fn compute_fastest_day(sessions: &[RawSession], zone: Zone) -> Option<String> {
     let mut day_wpms: HashMap<Weekday, Vec<f64>> = HashMap::new();
 
     for session in sessions {
         if let Some(wpm) = session.avg_wpm {
-            let weekday = session.start_time.weekday();
+            let weekday = zone.naive_local(session.start_time).weekday();
             day_wpms.entry(weekday).or_default().push(wpm);
         }
     }
@@ -317,10 +725,10 @@ fn compute_fastest_day(sessions: &[RawSession]) -> Option<String> {
         .map(|(day, _)| format!("{day:?}"))
 }
 
-fn compute_peak_hour(grid: &[[u64; 24]; 7]) -> Option<u8> {
+fn compute_peak_hour(grid: &[(String, [u64; 24])]) -> Option<u8> {
     let mut hourly_totals = [0u64; 24];
 
-    for day in grid {
+    for (_, day) in grid {
         for (hour, &count) in day.iter().enumerate() {
             hourly_totals[hour] += count;
         }
@@ -334,25 +742,29 @@ fn compute_peak_hour(grid: &[[u64; 24]; 7]) -> Option<u8> {
         .map(|(hour, _)| hour as u8)
 }
 
-fn compute_night_owl_pct(sessions: &[RawSession]) -> f64 {
-    let total: u64 = sessions.iter().map(|s| s.keystroke_count).sum();
+fn compute_night_owl_pct(grid: &[(String, [u64; 24])]) -> f64 {
+    let total: u64 = grid.iter().flat_map(|(_, hours)| hours).sum();
     if total == 0 {
         return 0.0;
     }
 
-    let night_keystrokes: u64 = sessions
+    let night_keystrokes: u64 = grid
         .iter()
-        .filter(|s| {
-            let hour = s.start_time.hour();
-            !(6..18).contains(&hour)
-        })
-        .map(|s| s.keystroke_count)
+        .flat_map(|(_, hours)| hours.iter().enumerate())
+        .filter(|(hour, _)| !(6..18).contains(hour))
+        .map(|(_, &count)| count)
         .sum();
 
     night_keystrokes as f64 / total as f64 * 100.0
 }
 
-fn generate_shortcut_insight(shortcuts: &[(String, u64)]) -> Option<ShortcutInsight> {
+fn generate_shortcut_insight(
+    shortcuts: &[(String, u64)],
+    range: &DateRange,
+) -> Option<ShortcutInsight> {
+    // thresholds below are per week
+    let weeks = (range.days() as f64 / 7.0).max(1.0);
+    let noun = range.noun();
     let shortcut_map: HashMap<&str, u64> =
         shortcuts.iter().map(|(k, v)| (k.as_str(), *v)).collect();
 
@@ -360,10 +772,10 @@ fn generate_shortcut_insight(shortcuts: &[(String, u64)]) -> Option<ShortcutInsi
 
     // high undo usage
     if let Some(&undo_count) = shortcut_map.get("ctrl+z") {
-        if top_3.contains(&"ctrl+z") && undo_count > 50 {
+        if top_3.contains(&"ctrl+z") && undo_count as f64 > 50.0 * weeks {
             return Some(ShortcutInsight {
                 message: format!(
-                    "You undid {undo_count} actions this week. Experimenting or second-guessing?"
+                    "You undid {undo_count} actions this {noun}. Experimenting or second-guessing?"
                 ),
             });
         }
@@ -383,10 +795,10 @@ fn generate_shortcut_insight(shortcuts: &[(String, u64)]) -> Option<ShortcutInsi
 
     // frequent saves
     if let Some(&save_count) = shortcut_map.get("ctrl+s") {
-        if save_count > 100 {
+        if save_count as f64 > 100.0 * weeks {
             return Some(ShortcutInsight {
                 message: format!(
-                    "You saved {save_count} times this week. Trust issues with your editor?"
+                    "You saved {save_count} times this {noun}. Trust issues with your editor?"
                 ),
             });
         }
@@ -394,7 +806,7 @@ fn generate_shortcut_insight(shortcuts: &[(String, u64)]) -> Option<ShortcutInsi
 
     // window switching
     if let Some(&alt_tab) = shortcut_map.get("alt+tab") {
-        if top_3.contains(&"alt+tab") && alt_tab > 100 {
+        if top_3.contains(&"alt+tab") && alt_tab as f64 > 100.0 * weeks {
             return Some(ShortcutInsight {
                 message: format!("You switched windows {alt_tab} times. Context switching much?"),
             });
@@ -404,9 +816,161 @@ fn generate_shortcut_insight(shortcuts: &[(String, u64)]) -> Option<ShortcutInsi
     // zero undos (conviction)
     if !shortcut_map.contains_key("ctrl+z") && !shortcuts.is_empty() {
         return Some(ShortcutInsight {
-            message: "Zero undos this week. You type with conviction.".to_string(),
+            message: format!("Zero undos this {noun}. You type with conviction."),
         });
     }
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_median_interpolates_within_bucket() {
+        // 10 samples in 50..75, 10 in 75..100: the median sits on the boundary
+        assert_eq!(histogram_median(&vec![(50, 10), (75, 10)]), Some(75.0));
+        // all in the 100..125 bucket: halfway through it
+        assert_eq!(histogram_median(&vec![(100, 4)]), Some(112.5));
+        // last bucket runs up to the timing cutoff
+        assert_eq!(histogram_median(&vec![(1500, 2)]), Some(1750.0));
+        assert_eq!(histogram_median(&Vec::new()), None);
+    }
+
+    #[test]
+    fn ngram_stats_classify_fingers_and_hands() {
+        let key = |k: &str| k.to_string();
+        let bigrams = vec![
+            ((key("d"), key("e")), 3), // same finger
+            ((key("e"), key("e")), 5), // repeat, not same finger
+            ((key("t"), key("h")), 2), // alternation
+            ((key("e"), key("space")), 9), // thumb, ignored
+        ];
+        let trigrams = vec![
+            ((key("t"), key("h"), key("e")), 4), // alternation
+            ((key("a"), key("s"), key("k")), 4), // roll
+            ((key("w"), key("e"), key("r")), 2), // one hand
+        ];
+
+        let layout = Layout::build(&Default::default()).unwrap();
+        let stats = compute_ngram_stats(&bigrams, &trigrams, &layout);
+        assert_eq!(stats.bigrams, 10);
+        assert!((stats.same_finger_ratio - 0.3).abs() < 1e-9);
+        assert_eq!(stats.top_same_finger, vec![(key("d"), key("e"), 3)]);
+        assert_eq!(stats.trigrams, 10);
+        assert!((stats.alternation_ratio - 0.4).abs() < 1e-9);
+        assert!((stats.roll_ratio - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn finger_stats_measure_from_each_fingers_home() {
+        let layout = Layout::build(&Default::default()).unwrap();
+        let counts: HashMap<String, u64> = [
+            ("f", 50),
+            ("j", 30),
+            ("6", 5),
+            ("backspace", 10),
+            ("p", 5),
+            ("space", 10),
+        ]
+        .into_iter()
+        .map(|(k, c)| (k.to_string(), c))
+        .collect();
+
+        // 6 is typed with the right index, so it's measured from j even
+        // though f is closer
+        let expected = f64::hypot(8.25 - 6.5, 2.5 - 0.5);
+        assert!((layout.travel("6").unwrap() - expected).abs() < 1e-9);
+
+        let stats = compute_finger_stats(&counts, &layout);
+        let load = |f: Finger| stats.fingers.iter().find(|l| l.finger == f).unwrap();
+        assert_eq!(load(Finger::RightIndex).keystrokes, 35);
+        assert_eq!(load(Finger::LeftPinky).keystrokes, 0);
+        assert!((stats.left_hand_share - 0.5).abs() < 1e-9);
+
+        assert_eq!(stats.warnings.len(), 1);
+        assert_eq!(stats.warnings[0].finger, Finger::RightPinky);
+        assert_eq!(stats.warnings[0].top_keys, vec!["backspace", "p"]);
+    }
+
+    #[test]
+    fn hourly_counts_replace_session_start_guess() {
+        use super::super::RangeSpec;
+        use chrono::{TimeZone, Utc};
+
+        let zone = Zone::parse("Europe/Berlin").unwrap();
+        let range = RangeSpec::Day(NaiveDate::from_ymd_opt(2026, 7, 1).unwrap()).resolve();
+        let at = |h, m| Utc.with_ymd_and_hms(2026, 7, 1, h, m, 0).unwrap();
+        let session = |start_time, keystroke_count| RawSession {
+            id: 0,
+            start_time,
+            end_time: None,
+            keystroke_count,
+            avg_wpm: None,
+            peak_wpm: None,
+        };
+        let hourly = |hour, key: &str, count| HourlyKeyCount {
+            hour,
+            key_code: key.to_string(),
+            count,
+        };
+
+        // just after local midnight, before hourly counts were recorded
+        let early = session(at(0, 10) - chrono::Duration::hours(2), 100);
+        // already covered by the hourly rows below
+        let later = session(at(6, 5), 50);
+        let rows = vec![
+            hourly(at(6, 0), "e", 40),
+            hourly(at(6, 0), "t", 10),
+            hourly(at(20, 0), "e", 10),
+            hourly(at(20, 0), "semicolon", 30),
+        ];
+
+        let grid = compute_hourly_activity(&rows, &[early, later], &range, zone);
+        assert_eq!(grid.len(), 1);
+        let hours = grid[0].1;
+        assert_eq!((hours[0], hours[8], hours[22]), (100, 50, 40));
+        assert_eq!(hours.iter().sum::<u64>(), 190);
+        assert!((compute_night_owl_pct(&grid) - 140.0 / 190.0 * 100.0).abs() < 1e-9);
+
+        let periods = compute_day_periods(&rows, &[], zone);
+        let labels: Vec<_> = periods.iter().map(|p| p.label).collect();
+        assert_eq!(labels, ["Night", "Morning", "Afternoon", "Evening"]);
+        assert_eq!(periods[0].keystrokes, 0);
+        assert_eq!(
+            periods[1].top_keys,
+            vec![("e".to_string(), 40), ("t".to_string(), 10)]
+        );
+        assert_eq!(periods[1].standout, None);
+        assert_eq!(periods[3].keystrokes, 40);
+        assert_eq!(periods[3].standout.as_deref(), Some("semicolon"));
+    }
+
+    #[test]
+    fn pauses_mark_the_hours_they_overlap() {
+        use super::super::RangeSpec;
+        use chrono::TimeZone;
+
+        let zone = Zone::parse("Asia/Kolkata").unwrap();
+        let date = NaiveDate::from_ymd_opt(2026, 7, 1).unwrap();
+        let range = RangeSpec::Day(date).resolve();
+        let bounds = zone.day_bounds(date, date);
+        let at = |month, d, h, m| Utc.with_ymd_and_hms(2026, month, d, h, m, 0).unwrap();
+        let pause = |start_time, end_time| RawPause {
+            start_time,
+            end_time: Some(end_time),
+            reason: "command".to_string(),
+        };
+
+        // 23:50 the day before until 00:40 local, then 09:00 until 10:00 local
+        let pauses = [
+            pause(at(6, 30, 18, 20), at(6, 30, 19, 10)),
+            pause(at(7, 1, 3, 30), at(7, 1, 4, 30)),
+        ];
+        let grid = compute_paused_hours(&pauses, &range, zone, bounds);
+        let paused: Vec<usize> = (0..24).filter(|&h| grid[0][h]).collect();
+        assert_eq!(paused, [0, 9]);
+        assert_eq!(compute_pause_periods(&pauses)[1].duration_minutes, 60.0);
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+/// Written in layout files as e.g. `left-pinky` or `right-thumb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Finger {
+    LeftPinky,
+    LeftRing,
+    LeftMiddle,
+    LeftIndex,
+    LeftThumb,
+    RightThumb,
+    RightIndex,
+    RightMiddle,
+    RightRing,
+    RightPinky,
+}
+
+impl Finger {
+    /// Left to right across both hands.
+    pub const ALL: [Finger; 10] = [
+        Finger::LeftPinky,
+        Finger::LeftRing,
+        Finger::LeftMiddle,
+        Finger::LeftIndex,
+        Finger::LeftThumb,
+        Finger::RightThumb,
+        Finger::RightIndex,
+        Finger::RightMiddle,
+        Finger::RightRing,
+        Finger::RightPinky,
+    ];
+
+    pub fn hand(self) -> Hand {
+        match self {
+            Finger::LeftPinky
+            | Finger::LeftRing
+            | Finger::LeftMiddle
+            | Finger::LeftIndex
+            | Finger::LeftThumb => Hand::Left,
+            _ => Hand::Right,
+        }
+    }
+
+    pub fn is_thumb(self) -> bool {
+        matches!(self, Finger::LeftThumb | Finger::RightThumb)
+    }
+
+    pub fn is_pinky(self) -> bool {
+        matches!(self, Finger::LeftPinky | Finger::RightPinky)
+    }
+}
+
+impl fmt::Display for Finger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Finger::LeftPinky => "left pinky",
+            Finger::LeftRing => "left ring",
+            Finger::LeftMiddle => "left middle",
+            Finger::LeftIndex => "left index",
+            Finger::LeftThumb => "left thumb",
+            Finger::RightThumb => "right thumb",
+            Finger::RightIndex => "right index",
+            Finger::RightMiddle => "right middle",
+            Finger::RightRing => "right ring",
+            Finger::RightPinky => "right pinky",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Touch-typing finger for a key on a row-staggered QWERTY board.
+pub fn standard_finger(key: &str) -> Option<Finger> {
+    let finger = match key {
+        "escape" | "grave" | "1" | "q" | "a" | "z" | "tab" | "capslock" | "lshift" | "lctrl"
+        | "intlbackslash" => Finger::LeftPinky,
+        "2" | "w" | "s" | "x" => Finger::LeftRing,
+        "3" | "e" | "d" | "c" => Finger::LeftMiddle,
+        "4" | "5" | "r" | "t" | "f" | "g" | "v" | "b" => Finger::LeftIndex,
+        "lalt" | "lsuper" | "muhenkan" => Finger::LeftThumb,
+        // either thumb can take space, most people use the right one
+        "space" | "ralt" | "rsuper" | "henkan" | "katakanahiragana" => Finger::RightThumb,
+        "6" | "7" | "y" | "u" | "h" | "j" | "n" | "m" => Finger::RightIndex,
+        "8" | "i" | "k" | "comma" => Finger::RightMiddle,
+        "9" | "o" | "l" | "period" => Finger::RightRing,
+        "0" | "minus" | "equal" | "p" | "leftbracket" | "rightbracket" | "backslash"
+        | "semicolon" | "apostrophe" | "slash" | "enter" | "backspace" | "rshift" | "rctrl"
+        | "intlro" | "intlyen" => Finger::RightPinky,
+        _ => return None,
+    };
+    Some(finger)
+}
@@ -1,13 +1,15 @@
 #![allow(clippy::uninlined_format_args)]
 
-use super::layout::qwerty_ansi;
+use super::fingers::Finger;
+use super::layout::{KeyPosition, LayoutSpec};
+use super::range::TrendStep;
 use super::ReportData;
 use std::collections::HashMap;
 
 pub fn render(data: &ReportData) -> String {
     let mut html = String::with_capacity(96 * 1024);
 
-    html.push_str(&render_head(&data.week.label));
+    html.push_str(&render_head(&data.range.label));
     html.push_str("<body>\n<div class=\"page\">\n");
 
     html.push_str(&render_hero(data));
@@ -16,7 +18,7 @@ pub fn render(data: &ReportData) -> String {
     html.push_str(&render_sessions(data));
     html.push_str(&render_shortcuts(data));
     html.push_str(&render_fun_stats(data));
-    html.push_str(&render_footer());
+    html.push_str(&render_footer(data));
 
     html.push_str("</div>\n");
     html.push_str(&render_scripts(data));
@@ -227,19 +229,17 @@ section + section {{ border-top: 1px solid var(--border); }}
   pointer-events: none;
 }}
 
-.kb {{ width: 100%; }}
-.kb-row {{ display: flex; gap: 3px; margin-bottom: 3px; justify-content: center; }}
+.kb {{ width: 100%; position: relative; }}
 .kb-key {{
-  height: 36px;
+  position: absolute;
+  box-sizing: border-box;
   border-radius: 6px;
   display: flex; align-items: center; justify-content: center;
   font-family: var(--mono); font-size: 9px; font-weight: 500;
   color: rgba(255,255,255,0.45);
-  position: relative;
   transition: all 0.2s ease;
   cursor: default;
   border: 1px solid rgba(255,255,255,0.02);
-  flex-shrink: 0;
 }}
 .kb-key:hover {{
   border-color: var(--cyan);
@@ -375,6 +375,9 @@ section + section {{ border-top: 1px solid var(--border); }}
 .grid-cell[data-level="3"] {{ background: #0d9488; }}
 .grid-cell[data-level="4"] {{ background: #14b8a6; }}
 .grid-cell[data-level="5"] {{ background: #22d3ee; box-shadow: 0 0 6px rgba(34,211,238,0.4); }}
+.grid-cell[data-paused] {{
+  background-image: repeating-linear-gradient(45deg, transparent 0 2px, rgba(255,255,255,0.14) 2px 4px);
+}}
 .hour-labels {{ display: flex; gap: 2px; margin-left: 32px; margin-top: 4px; }}
 .hour-label {{
   flex: 1; text-align: center;
@@ -404,6 +407,7 @@ section + section {{ border-top: 1px solid var(--border); }}
   font-family: var(--mono); font-size: 12px; font-weight: 600;
   color: var(--text-dim);
 }}
+.sc-count.wide {{ width: 120px; }}
 
 .insight-card {{
   margin-top: 16px;
@@ -459,7 +463,7 @@ section + section {{ border-top: 1px solid var(--border); }}
   .cards, .fun-cards {{ grid-template-columns: 1fr; }}
   .top-keys-row {{ flex-direction: column; align-items: center; }}
   .hero {{ padding: 60px 0 40px; }}
-  .kb-key {{ height: 28px; font-size: 7px; }}
+  .kb-key {{ font-size: 7px; }}
 }}
 </style>
 </head>
@@ -469,7 +473,7 @@ section + section {{ border-top: 1px solid var(--border); }}
 }
 
 fn render_hero(data: &ReportData) -> String {
-    let delta_html = if let Some(prev) = data.prev_week_keystrokes {
+    let delta_html = if let Some(prev) = data.prev_keystrokes {
         if prev > 0 {
             let pct = ((data.total_keystrokes as f64 - prev as f64) / prev as f64) * 100.0;
             let (class, arrow) = if pct >= 0.0 {
@@ -493,23 +497,31 @@ fn render_hero(data: &ReportData) -> String {
     let hours = (data.total_typing_minutes / 60.0).floor() as u32;
     let mins = (data.total_typing_minutes % 60.0).round() as u32;
 
+    let mut chip = data.range.title().to_string();
+    for filter in [&data.machine, &data.device].into_iter().flatten() {
+        chip.push_str(&format!(" · {}", escape_html(filter)));
+    }
+
     format!(
         r#"<div class="hero reveal">
-  <div class="hero-chip">Weekly Report</div>
+  <div class="hero-chip">{chip}</div>
   <p class="hero-week">{week_label}</p>
   <div class="hero-number-wrap">
     <div class="hero-number" id="heroNum">{keystrokes_fmt}</div>
   </div>
   <p class="hero-unit">keystrokes</p>
   <p class="hero-meta">
-    <strong>{sessions} sessions</strong> across 7 days · <strong>{hours}h {mins}m</strong> total typing time
+    <strong>{sessions} sessions</strong> across {days} {day_word} · <strong>{hours}h {mins}m</strong> total typing time
     {delta_html}
   </p>
 </div>
 "#,
-        week_label = data.week.label,
+        chip = chip,
+        week_label = data.range.label,
         keystrokes_fmt = format_number(data.total_keystrokes),
         sessions = data.sessions.len(),
+        days = data.range.days(),
+        day_word = if data.range.days() == 1 { "day" } else { "days" },
         hours = hours,
         mins = mins,
         delta_html = delta_html,
@@ -536,11 +548,17 @@ fn render_heatmap(data: &ReportData) -> String {
         })
         .collect();
 
+    let layout = if data.layout.name == LayoutSpec::default().to_string() {
+        String::new()
+    } else {
+        format!(" Shown on {}.", escape_html(&data.layout.name))
+    };
+
     format!(
         r##"<section class="reveal">
   <p class="sec-eyebrow">01 — Heatmap</p>
   <h2 class="sec-title">Your Keyboard</h2>
-  <p class="sec-desc">Where your fingers spent the week. Brighter keys were hit more often.</p>
+  <p class="sec-desc">Where your fingers spent the {noun}. Brighter keys were hit more often.{layout}</p>
 
   <div class="heatmap-wrap">
     <div class="kb" id="keyboard"></div>
@@ -554,9 +572,128 @@ fn render_heatmap(data: &ReportData) -> String {
   <div class="top-keys-row">
     {top_keys}
   </div>
+  {ngrams}
+  {fingers}
 </section>
 "##,
-        top_keys = top_keys.join("\n    ")
+        top_keys = top_keys.join("\n    "),
+        noun = data.range.noun(),
+        layout = layout,
+        ngrams = render_ngrams(data),
+        fingers = render_fingers(data),
+    )
+}
+
+fn render_fingers(data: &ReportData) -> String {
+    let stats = &data.fingers;
+    let Some(busiest) = stats.fingers.iter().max_by_key(|l| l.keystrokes) else {
+        return String::new();
+    };
+
+    let max_share = busiest.share.max(0.01);
+    let bars: Vec<String> = stats
+        .fingers
+        .iter()
+        .map(|load| {
+            format!(
+                r#"<div class="sc-row" title="{keystrokes} keystrokes, {travel:.1} m travel">
+  <span class="sc-name">{name}</span>
+  <div class="sc-bar-track"><div class="sc-bar-fill" style="width:{pct:.0}%"></div></div>
+  <span class="sc-count wide">{share:.1}%</span>
+</div>"#,
+                keystrokes = format_number(load.keystrokes),
+                travel = load.travel_mm / 1000.0,
+                name = format_finger(load.finger),
+                pct = load.share / max_share * 100.0,
+                share = load.share * 100.0,
+            )
+        })
+        .collect();
+
+    let warnings: Vec<String> = stats
+        .warnings
+        .iter()
+        .map(|w| {
+            let keys: Vec<String> = w.top_keys.iter().map(|k| format_key_display(k)).collect();
+            format!(
+                r#"<div class="insight-card">
+  <div class="insight-icon">&#9888;</div>
+  <div class="insight-text">Your <strong>{finger}</strong> takes {share:.0}% of keystrokes, mostly <strong>{keys}</strong>. Moving some of these to stronger fingers can ease the strain.</div>
+</div>"#,
+                finger = w.finger,
+                share = w.share * 100.0,
+                keys = keys.join(", "),
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<div class="cards" style="margin-top:10px">
+    <div class="card">
+      <div class="card-val">{left:.0}<span class="sm">/</span>{right:.0}</div>
+      <div class="card-label">Left / Right Hand</div>
+      <div class="card-sub">Share of keystrokes, thumbs excluded</div>
+    </div>
+    <div class="card">
+      <div class="card-val">{busiest_share:.0}<span class="sm">%</span></div>
+      <div class="card-label">Busiest Finger</div>
+      <div class="card-sub">{busiest_name}</div>
+    </div>
+  </div>
+  <div class="chart-box">
+    <div class="chart-box-title">Finger Load</div>
+    {bars}
+  </div>
+  {warnings}"#,
+        left = stats.left_hand_share * 100.0,
+        right = (1.0 - stats.left_hand_share) * 100.0,
+        busiest_share = busiest.share * 100.0,
+        busiest_name = format_finger(busiest.finger),
+        bars = bars.join("\n    "),
+        warnings = warnings.join("\n  "),
+    )
+}
+
+fn render_ngrams(data: &ReportData) -> String {
+    let ngrams = &data.ngrams;
+    if ngrams.bigrams == 0 {
+        return String::new();
+    }
+
+    let worst = ngrams
+        .top_same_finger
+        .iter()
+        .take(3)
+        .map(|(a, b, _)| format!("{}&rarr;{}", format_key_display(a), format_key_display(b)))
+        .collect::<Vec<_>>();
+    let worst = if worst.is_empty() {
+        "Same finger twice in a row".to_string()
+    } else {
+        format!("Most often: {}", worst.join(", "))
+    };
+
+    format!(
+        r#"<div class="cards" style="margin-top:10px">
+    <div class="card">
+      <div class="card-val">{sfb:.1}<span class="sm">%</span></div>
+      <div class="card-label">Same-Finger Bigrams</div>
+      <div class="card-sub">{worst}</div>
+    </div>
+    <div class="card">
+      <div class="card-val">{alt:.0}<span class="sm">%</span></div>
+      <div class="card-label">Hand Alternation</div>
+      <div class="card-sub">Trigrams switching hands every key</div>
+    </div>
+    <div class="card">
+      <div class="card-val">{roll:.0}<span class="sm">%</span></div>
+      <div class="card-label">Rolls</div>
+      <div class="card-sub">Two fingers on one hand, then the other</div>
+    </div>
+  </div>"#,
+        sfb = ngrams.same_finger_ratio * 100.0,
+        worst = worst,
+        alt = ngrams.alternation_ratio * 100.0,
+        roll = ngrams.roll_ratio * 100.0,
     )
 }
 
@@ -567,7 +704,7 @@ fn render_speed(data: &ReportData) -> String {
         .unwrap_or_else(|| "unknown".to_string());
 
     let wpm_delta = data
-        .prev_week_avg_wpm
+        .prev_avg_wpm
         .map(|prev| {
             if prev > 0.0 {
                 let pct = ((data.avg_wpm - prev) / prev) * 100.0;
@@ -588,8 +725,9 @@ fn render_speed(data: &ReportData) -> String {
         })
         .unwrap_or_default();
 
-    let trend_svg = render_wpm_trend(&data.daily_wpm);
+    let trend_svg = render_wpm_trend(&data.wpm_trend, data.range.trend_step());
     let dist_svg = render_wpm_distribution(&data.wpm_distribution);
+    let timing = render_timing(data);
 
     format!(
         r##"<section class="reveal">
@@ -611,7 +749,7 @@ fn render_speed(data: &ReportData) -> String {
   </div>
 
   <div class="chart-box">
-    <div class="chart-box-title">Daily WPM Trend</div>
+    <div class="chart-box-title">{trend_name} WPM Trend</div>
     {trend_svg}
   </div>
 
@@ -619,18 +757,103 @@ fn render_speed(data: &ReportData) -> String {
     <div class="chart-box-title">Speed Distribution</div>
     {dist_svg}
   </div>
+  {timing}
 </section>
 "##,
         avg_wpm = data.avg_wpm,
         peak_wpm = data.peak_wpm,
         peak_time = peak_time,
         wpm_delta = wpm_delta,
+        trend_name = data.range.trend_step().name(),
         trend_svg = trend_svg,
         dist_svg = dist_svg,
+        timing = timing,
+    )
+}
+
+fn render_timing(data: &ReportData) -> String {
+    let timing = &data.timing;
+    let (Some(dwell), Some(flight)) = (timing.median_dwell_ms, timing.median_flight_ms) else {
+        return String::new();
+    };
+
+    let keys: Vec<(String, f64, u64)> = timing
+        .slowest_keys
+        .iter()
+        .map(|t| (format_key_display(&t.key), t.median_ms, t.samples))
+        .collect();
+    let transitions: Vec<(String, f64, u64)> = timing
+        .slowest_transitions
+        .iter()
+        .map(|t| {
+            (
+                format!(
+                    "{} &rarr; {}",
+                    format_key_display(&t.from),
+                    format_key_display(&t.to)
+                ),
+                t.median_ms,
+                t.samples,
+            )
+        })
+        .collect();
+
+    let mut boxes = String::new();
+    for (title, rows) in [
+        ("Longest Key Holds", keys),
+        ("Slowest Transitions", transitions),
+    ] {
+        if rows.is_empty() {
+            continue;
+        }
+        let max_ms = rows.first().map(|(_, ms, _)| *ms).unwrap_or(1.0).max(1.0);
+        let bars: Vec<String> = rows
+            .iter()
+            .map(|(name, ms, samples)| {
+                format!(
+                    r#"<div class="sc-row" title="median of {samples} samples">
+  <span class="sc-name">{name}</span>
+  <div class="sc-bar-track"><div class="sc-bar-fill" style="width:{pct:.0}%"></div></div>
+  <span class="sc-count wide">{ms:.0} ms</span>
+</div>"#,
+                    name = name,
+                    pct = ms / max_ms * 100.0,
+                    ms = ms,
+                    samples = format_number(*samples),
+                )
+            })
+            .collect();
+        boxes.push_str(&format!(
+            r#"
+  <div class="chart-box">
+    <div class="chart-box-title">{title}</div>
+    {bars}
+  </div>"#,
+            title = title,
+            bars = bars.join("\n    "),
+        ));
+    }
+
+    format!(
+        r#"<div class="cards" style="margin-top:10px">
+    <div class="card">
+      <div class="card-val">{dwell:.0}<span class="sm"> ms</span></div>
+      <div class="card-label">Median Key Hold</div>
+      <div class="card-sub">Press to release</div>
+    </div>
+    <div class="card">
+      <div class="card-val">{flight:.0}<span class="sm"> ms</span></div>
+      <div class="card-label">Median Key Gap</div>
+      <div class="card-sub">Release to next press</div>
+    </div>
+  </div>{boxes}"#,
+        dwell = dwell,
+        flight = flight,
+        boxes = boxes,
     )
 }
 
-fn render_wpm_trend(daily_wpm: &[(chrono::NaiveDate, f64)]) -> String {
+fn render_wpm_trend(daily_wpm: &[(chrono::NaiveDate, f64)], step: TrendStep) -> String {
     if daily_wpm.is_empty() {
         return r##"<svg class="chart-svg" viewBox="0 0 600 140"><text x="300" y="70" fill="#374151" font-family="JetBrains Mono" font-size="12" text-anchor="middle">No data yet</text></svg>"##.to_string();
     }
@@ -646,7 +869,13 @@ fn render_wpm_trend(daily_wpm: &[(chrono::NaiveDate, f64)]) -> String {
         .fold(f64::MAX, |a, b| a.min(b));
     let range = (max_wpm - min_wpm).max(10.0);
 
-    let x_positions: Vec<f64> = (0..7).map(|i| 80.0 + i as f64 * 75.0).collect();
+    // spread points over the same 80..530 span a week has always used
+    let spacing = 450.0 / (daily_wpm.len().max(2) - 1) as f64;
+    let x_positions: Vec<f64> = (0..daily_wpm.len())
+        .map(|i| 80.0 + i as f64 * spacing)
+        .collect();
+    let dot_radius = if daily_wpm.len() > 31 { 2 } else { 4 };
+    let label_every = daily_wpm.len().div_ceil(12);
 
     let points: Vec<String> = daily_wpm
         .iter()
@@ -674,17 +903,23 @@ fn render_wpm_trend(daily_wpm: &[(chrono::NaiveDate, f64)]) -> String {
             let x = x_positions.get(i).copied().unwrap_or(80.0);
             let y = 100.0 - ((wpm - min_wpm) / range) * 70.0;
             format!(
-                r##"<circle cx="{x:.0}" cy="{y:.0}" r="4" fill="#22d3ee" stroke="#0a0f1a" stroke-width="2"/>"##
+                r##"<circle cx="{x:.0}" cy="{y:.0}" r="{dot_radius}" fill="#22d3ee" stroke="#0a0f1a" stroke-width="2"/>"##
             )
         })
         .collect();
 
+    let label_format = match step {
+        TrendStep::Day if daily_wpm.len() <= 7 => "%a",
+        TrendStep::Day | TrendStep::Week => "%b %-d",
+        TrendStep::Month => "%b",
+    };
     let labels: Vec<String> = daily_wpm
         .iter()
         .enumerate()
+        .step_by(label_every)
         .map(|(i, (date, _))| {
             let x = x_positions.get(i).copied().unwrap_or(80.0);
-            let day = date.format("%a").to_string();
+            let day = date.format(label_format).to_string();
             format!(
                 r##"<text x="{x:.0}" y="125" fill="#374151" font-family="JetBrains Mono" font-size="9" text-anchor="middle">{day}</text>"##
             )
@@ -784,11 +1019,13 @@ fn render_sessions(data: &ReportData) -> String {
         })
         .unwrap_or_else(|| "-".to_string());
 
+    let keyboards = render_keyboards(data);
+
     format!(
         r##"<section class="reveal">
   <p class="sec-eyebrow">03 — Rhythm</p>
   <h2 class="sec-title">Session Timeline</h2>
-  <p class="sec-desc">When you were at the keys. Each cell is one hour.</p>
+  <p class="sec-desc">When you were at the keys. Each cell is one hour.{paused}</p>
 
   <div class="chart-box">
     <div class="chart-box-title">Activity by Hour</div>
@@ -806,10 +1043,118 @@ fn render_sessions(data: &ReportData) -> String {
       <div class="card-label">Longest Session</div>
     </div>
   </div>
+  {day_periods}
+  {keyboards}
 </section>
 "##,
         busiest_hour = busiest_hour,
         longest = longest,
+        paused = render_paused(data),
+        day_periods = render_day_periods(data),
+        keyboards = keyboards,
+    )
+}
+
+fn render_paused(data: &ReportData) -> String {
+    if data.pauses.is_empty() {
+        return String::new();
+    }
+    let minutes: f64 = data.pauses.iter().map(|p| p.duration_minutes).sum();
+    let times = if data.pauses.len() == 1 {
+        "time"
+    } else {
+        "times"
+    };
+    format!(
+        " Capture was paused {} {times} ({}h {}m), hatched hours are incomplete.",
+        data.pauses.len(),
+        (minutes / 60.0).floor() as u32,
+        (minutes % 60.0).round() as u32
+    )
+}
+
+fn render_day_periods(data: &ReportData) -> String {
+    if data.day_periods.iter().all(|p| p.keystrokes == 0) {
+        return String::new();
+    }
+
+    let cards: Vec<String> = data
+        .day_periods
+        .iter()
+        .map(|period| {
+            let keys: Vec<String> = period
+                .top_keys
+                .iter()
+                .take(3)
+                .map(|(k, _)| format_key_display(k))
+                .collect();
+            let sub = match &period.standout {
+                Some(key) => format!(
+                    "More <strong>{}</strong> than usual",
+                    format_key_display(key)
+                ),
+                None if keys.is_empty() => "Quiet".to_string(),
+                None => keys.join(", "),
+            };
+            format!(
+                r#"<div class="card" title="{start:02}:00–{end:02}:00">
+      <div class="card-val">{keystrokes}</div>
+      <div class="card-label">{label}</div>
+      <div class="card-sub">{sub}</div>
+    </div>"#,
+                start = period.hours.0,
+                end = period.hours.1,
+                keystrokes = format_number(period.keystrokes),
+                label = period.label,
+                sub = sub,
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<div class="cards" style="margin-top:10px">
+    {cards}
+  </div>"#,
+        cards = cards.join("\n    ")
+    )
+}
+
+fn render_keyboards(data: &ReportData) -> String {
+    if data.devices.len() < 2 {
+        return String::new();
+    }
+
+    let max_count = data.devices.first().map(|d| d.keystrokes).unwrap_or(1).max(1) as f64;
+
+    let rows: Vec<String> = data
+        .devices
+        .iter()
+        .map(|usage| {
+            let pct = (usage.keystrokes as f64 / max_count) * 100.0;
+            let wpm = usage
+                .avg_wpm
+                .map(|w| format!(" · {:.0} wpm", w))
+                .unwrap_or_default();
+            format!(
+                r#"<div class="sc-row">
+  <span class="sc-name">{name}</span>
+  <div class="sc-bar-track"><div class="sc-bar-fill" style="width:{pct:.0}%"></div></div>
+  <span class="sc-count wide">{count}{wpm}</span>
+</div>"#,
+                name = escape_html(usage.display_name()),
+                pct = pct,
+                count = format_number(usage.keystrokes),
+                wpm = wpm,
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<div class="chart-box" style="margin-top:10px">
+    <div class="chart-box-title">Keystrokes by Keyboard</div>
+    {rows}
+  </div>"#,
+        rows = rows.join("\n    ")
     )
 }
 
@@ -860,7 +1205,7 @@ fn render_shortcuts(data: &ReportData) -> String {
         r##"<section class="reveal">
   <p class="sec-eyebrow">04 — Commands</p>
   <h2 class="sec-title">Shortcut Game</h2>
-  <p class="sec-desc">Your most used keyboard combos this week.</p>
+  <p class="sec-desc">Your most used keyboard combos this {noun}.</p>
 
   <div class="chart-box">
     {bars}
@@ -869,6 +1214,7 @@ fn render_shortcuts(data: &ReportData) -> String {
   {insight_html}
 </section>
 "##,
+        noun = data.range.noun(),
         bars = bars.join("\n    "),
         insight_html = insight_html,
     )
@@ -939,15 +1285,17 @@ fn render_fun_stats(data: &ReportData) -> String {
     )
 }
 
-fn render_footer() -> String {
+fn render_footer(data: &ReportData) -> String {
     let now = chrono::Utc::now();
     format!(
         r#"<div class="footer">
   <p>Generated by <a href="https://github.com/0xSaiNova/keyheat">KeyHeat</a> on {date}</p>
+  <p>Times shown in {timezone}</p>
   <p>Run <code>keyheat report</code> to regenerate</p>
 </div>
 "#,
-        date = now.format("%b %d, %Y")
+        date = now.format("%b %d, %Y"),
+        timezone = escape_html(&data.timezone),
     )
 }
 
@@ -958,11 +1306,10 @@ fn render_scripts(data: &ReportData) -> String {
         .map(|(k, v)| (k.as_str(), *v))
         .collect();
 
-    let layout = qwerty_ansi();
-
-    let rows_js = build_keyboard_rows_js(&layout);
+    let keys_js = build_keyboard_js(&data.layout.keys);
+    let (width, height) = data.layout.bounds();
     let counts_js = build_counts_js(&key_counts);
-    let activity_js = build_activity_js(&data.hourly_activity);
+    let activity_js = build_activity_js(&data.hourly_activity, &data.paused_hours);
     let total_keystrokes = data.total_keystrokes;
 
     format!(
@@ -975,7 +1322,7 @@ document.querySelectorAll('.reveal').forEach(el => obs.observe(el));
 
 // Keyboard heatmap
 (function() {{
-  const rows = {rows_js};
+  const keys = {keys_js};
   const counts = {counts_js};
   const max = Math.max(...Object.values(counts), 1);
 
@@ -1000,38 +1347,42 @@ document.querySelectorAll('.reveal').forEach(el => obs.observe(el));
   }}
 
   const kb = document.getElementById('keyboard');
-  const unit = 42, gap = 3;
-
-  rows.forEach(row => {{
-    const rowDiv = document.createElement('div');
-    rowDiv.className = 'kb-row';
-    row.forEach(([label, width, code]) => {{
-      const key = document.createElement('div');
-      key.className = 'kb-key';
-      key.style.width = (width * unit + (width - 1) * gap) + 'px';
-      const count = counts[code] || 0;
-      key.style.background = heatColor(count);
-      key.textContent = label;
-      if (count) {{
-        const tt = document.createElement('div');
-        tt.className = 'kb-tooltip';
-        tt.innerHTML = `${{label}} <span>${{count.toLocaleString()}}</span>`;
-        key.appendChild(tt);
-      }}
-      rowDiv.appendChild(key);
-    }});
-    kb.appendChild(rowDiv);
+  const width = {width};
+  const height = {height};
+  const gap = 0.06;
+  kb.style.aspectRatio = `${{width}} / ${{height}}`;
+
+  keys.forEach(([label, code, x, y, w, h, r]) => {{
+    const key = document.createElement('div');
+    key.className = 'kb-key';
+    key.style.left = (x / width * 100) + '%';
+    key.style.top = (y / height * 100) + '%';
+    key.style.width = ((w - gap) / width * 100) + '%';
+    key.style.height = ((h - gap) / height * 100) + '%';
+    if (r) key.style.transform = `rotate(${{r}}deg)`;
+    const count = counts[code] || 0;
+    key.style.background = heatColor(count);
+    key.textContent = label;
+    if (count) {{
+      const tt = document.createElement('div');
+      tt.className = 'kb-tooltip';
+      tt.textContent = label + ' ';
+      const num = document.createElement('span');
+      num.textContent = count.toLocaleString();
+      tt.appendChild(num);
+      key.appendChild(tt);
+    }}
+    kb.appendChild(key);
   }});
 }})();
 
 // Activity grid
 (function() {{
-  const days = ['Mon','Tue','Wed','Thu','Fri','Sat','Sun'];
   const data = {activity_js};
   const grid = document.getElementById('activityGrid');
-  const maxVal = Math.max(...data.flat(), 1);
+  const maxVal = Math.max(...data.map(r => r[1]).flat(), 1);
 
-  days.forEach((day, di) => {{
+  data.forEach(([day, hours, paused]) => {{
     const row = document.createElement('div');
     row.className = 'grid-row';
     const lbl = document.createElement('div');
@@ -1041,9 +1392,13 @@ document.querySelectorAll('.reveal').forEach(el => obs.observe(el));
     for (let h = 0; h < 24; h++) {{
       const cell = document.createElement('div');
       cell.className = 'grid-cell';
-      const val = data[di][h];
+      const val = hours[h];
       const level = val === 0 ? 0 : Math.min(5, Math.ceil((val / maxVal) * 5));
       cell.dataset.level = level;
+      if (paused[h]) {{
+        cell.dataset.paused = '';
+        cell.title = 'paused';
+      }}
       row.appendChild(cell);
     }}
     grid.appendChild(row);
@@ -1076,57 +1431,58 @@ document.querySelectorAll('.reveal').forEach(el => obs.observe(el));
 }})();
 </script>
 "##,
-        rows_js = rows_js,
+        keys_js = keys_js,
+        width = width,
+        height = height,
         counts_js = counts_js,
         activity_js = activity_js,
         total_keystrokes = total_keystrokes,
     )
 }
 
-fn build_keyboard_rows_js(layout: &[super::layout::KeyPosition]) -> String {
-    let mut rows: Vec<Vec<(&str, f64, &str)>> = vec![vec![]; 5];
-
-    for key in layout {
-        let row_idx = key.y as usize;
-        if row_idx < 5 {
-            rows[row_idx].push((key.label, key.width, key.key_code));
-        }
-    }
-
-    let rows_str: Vec<String> = rows
+fn build_keyboard_js(keys: &[KeyPosition]) -> String {
+    let items: Vec<_> = keys
         .iter()
-        .map(|row| {
-            let items: Vec<String> = row
-                .iter()
-                .map(|(label, width, code)| {
-                    let display_label = if *label == "\\" { "\\\\" } else { label };
-                    format!(r#"["{}",{},"{}"]"#, display_label, width, code)
-                })
-                .collect();
-            format!("[{}]", items.join(","))
+        .map(|key| {
+            (
+                &key.label,
+                &key.key_code,
+                key.x,
+                key.y,
+                key.width,
+                key.height,
+                key.rotation,
+            )
         })
         .collect();
-
-    format!("[{}]", rows_str.join(","))
+    script_json(&items)
 }
 
 fn build_counts_js(counts: &HashMap<&str, u64>) -> String {
-    let items: Vec<String> = counts
-        .iter()
-        .map(|(k, v)| format!(r#""{}": {}"#, k, v))
-        .collect();
-    format!("{{{}}}", items.join(", "))
+    script_json(counts)
 }
 
-fn build_activity_js(grid: &[[u64; 24]; 7]) -> String {
-    let rows: Vec<String> = grid
+fn build_activity_js(grid: &[(String, [u64; 24])], paused: &[[bool; 24]]) -> String {
+    let rows: Vec<_> = grid
         .iter()
-        .map(|row| {
-            let vals: Vec<String> = row.iter().map(|v| v.to_string()).collect();
-            format!("[{}]", vals.join(","))
+        .enumerate()
+        .map(|(i, (label, row))| {
+            let paused: Vec<u8> = paused
+                .get(i)
+                .map(|hours| hours.iter().map(|&p| p as u8).collect())
+                .unwrap_or_else(|| vec![0; 24]);
+            (label, row, paused)
         })
         .collect();
-    format!("[{}]", rows.join(","))
+    script_json(&rows)
+}
+
+/// `value` as JSON that's safe inside an inline `<script>`. Labels come
+/// from layout files, so nothing in them may close the tag.
+fn script_json(value: &impl serde::Serialize) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_else(|_| "null".to_string())
+        .replace('<', "\\u003c")
 }
 
 fn format_number(n: u64) -> String {
@@ -1141,6 +1497,22 @@ fn format_number(n: u64) -> String {
     result
 }
 
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_finger(finger: Finger) -> String {
+    let name = finger.to_string();
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
 fn format_key_display(key: &str) -> String {
     match key {
         "space" => "Space".to_string(),
@@ -1168,3 +1540,27 @@ fn format_shortcut_display(shortcut: &str) -> String {
         .collect::<Vec<_>>()
         .join("+")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{build_report, ReportOptions};
+    use crate::storage::Storage;
+
+    #[test]
+    fn layout_labels_cannot_escape_the_script() {
+        let storage = Storage::in_memory().unwrap();
+        let mut data = build_report(storage.connection(), &ReportOptions::default()).unwrap();
+        data.layout.keys[0].label = "</script><b>x\n\"y\\".to_string();
+        data.key_frequencies = vec![(data.layout.keys[0].key_code.clone(), 3)];
+
+        let html = render(&data);
+        assert_eq!(html.matches("</script>").count(), 1);
+        assert!(!html.contains("<b>x"));
+        assert!(!html.contains("innerHTML"));
+
+        let keys: serde_json::Value =
+            serde_json::from_str(&build_keyboard_js(&data.layout.keys)).unwrap();
+        assert_eq!(keys[0][0], "</script><b>x\n\"y\\");
+    }
+}
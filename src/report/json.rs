@@ -0,0 +1,50 @@
+use super::ReportData;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Version of the JSON report layout, documented in `docs/report-json.md`.
+/// Bump it when a field is renamed, removed or changes meaning; adding a
+/// field doesn't need a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    schema_version: u32,
+    generated_at: DateTime<Utc>,
+    #[serde(flatten)]
+    report: &'a ReportData,
+}
+
+pub fn render(data: &ReportData) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&JsonReport {
+        schema_version: SCHEMA_VERSION,
+        generated_at: Utc::now(),
+        report: data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{build_report, ReportOptions};
+    use crate::storage::Storage;
+    use serde_json::Value;
+
+    #[test]
+    fn report_round_trips_as_json() {
+        let storage = Storage::in_memory().unwrap();
+        let mut data = build_report(storage.connection(), &ReportOptions::default()).unwrap();
+        // labels come from user input and must not break the output
+        data.device = Some("Board \"Pro\" \\ 2".to_string());
+        data.key_frequencies = vec![("a".to_string(), 3)];
+
+        let value: Value = serde_json::from_str(&render(&data).unwrap()).unwrap();
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert_eq!(value["device"], "Board \"Pro\" \\ 2");
+        assert_eq!(value["range"]["kind"], "week");
+        assert_eq!(value["key_frequencies"][0], serde_json::json!(["a", 3]));
+        assert_eq!(value["hourly_activity"].as_array().unwrap().len(), 7);
+        assert_eq!(value["layout"]["keys"][0]["key_code"], "grave");
+        assert_eq!(value["layout"]["keys"][0]["finger"], "left-pinky");
+    }
+}
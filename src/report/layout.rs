@@ -1,445 +1,781 @@
-#[allow(dead_code)] // x used for layout positioning, may be used in future SVG renderer
+use super::fingers::{standard_finger, Finger};
+use super::layout_file;
+use crate::error::Error;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// One key on the board, in key units (1.0 = one standard key) from the
+/// top-left corner.
+#[derive(Debug, Clone, Serialize)]
 pub struct KeyPosition {
-    pub key_code: &'static str,
+    pub key_code: String,
     pub x: f64,
     pub y: f64,
     pub width: f64,
-    pub label: &'static str,
+    pub height: f64,
+    /// degrees clockwise around the key centre
+    pub rotation: f64,
+    pub label: String,
+    pub finger: Option<Finger>,
+    /// a finger rests here
+    pub home: bool,
+}
+
+impl KeyPosition {
+    pub fn center(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    fn corners(&self) -> [(f64, f64); 4] {
+        let (cx, cy) = self.center();
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (hw, hh) = (self.width / 2.0, self.height / 2.0);
+        [(-hw, -hh), (hw, -hh), (hw, hh), (-hw, hh)]
+            .map(|(dx, dy)| (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Layout {
+    pub name: String,
+    pub keys: Vec<KeyPosition>,
+}
+
+impl Layout {
+    pub fn build(spec: &LayoutSpec) -> Result<Self, Error> {
+        let (name, keys) = match &spec.physical {
+            Physical::Ansi => (None, ansi()),
+            Physical::Iso => (None, iso()),
+            Physical::Jis => (None, jis()),
+            Physical::Ortho => (None, ortho()),
+            Physical::Split => (None, split()),
+            Physical::File(path) => layout_file::load(path)?,
+        };
+
+        Ok(Self::new(
+            name.unwrap_or_else(|| spec.to_string()),
+            keys,
+            spec.logical,
+        ))
+    }
+
+    fn new(name: String, mut keys: Vec<KeyPosition>, logical: Logical) -> Self {
+        // fingers and the home row are physical properties, so settle them
+        // before relabelling
+        if !keys.iter().any(|k| k.home) {
+            for key in &mut keys {
+                key.home = HOME_KEYS.contains(&key.key_code.as_str());
+            }
+        }
+        for key in &mut keys {
+            if key.finger.is_none() {
+                key.finger = standard_finger(&key.key_code);
+            }
+            if let Some(code) = logical.remap(&key.key_code) {
+                key.key_code = code.to_string();
+                key.label = legend(code).to_string();
+            }
+        }
+
+        // rotated keys can reach past the origin
+        let (min_x, min_y) = keys
+            .iter()
+            .flat_map(KeyPosition::corners)
+            .fold((f64::MAX, f64::MAX), |(mx, my), (x, y)| (mx.min(x), my.min(y)));
+        if min_x.is_finite() {
+            for key in &mut keys {
+                key.x -= min_x;
+                key.y -= min_y;
+            }
+        }
+
+        Self { name, keys }
+    }
+
+    /// Width and height of the board in key units.
+    pub fn bounds(&self) -> (f64, f64) {
+        self.keys
+            .iter()
+            .flat_map(KeyPosition::corners)
+            .fold((0.0, 0.0), |(w, h), (x, y)| (f64::max(w, x), f64::max(h, y)))
+    }
+
+    pub fn finger(&self, key_code: &str) -> Option<Finger> {
+        self.key(key_code)?.finger
+    }
+
+    /// Distance in key units the assigned finger moves from its home key to
+    /// press this one. Keys whose finger has no home key are measured from
+    /// the nearest home key instead.
+    pub fn travel(&self, key_code: &str) -> Option<f64> {
+        let key = self.key(key_code)?;
+        let (x, y) = key.center();
+        let distance = |home: &KeyPosition| {
+            let (hx, hy) = home.center();
+            ((x - hx).powi(2) + (y - hy).powi(2)).sqrt()
+        };
+
+        let homes = self.keys.iter().filter(|k| k.home);
+        match homes.clone().find(|k| key.finger.is_some() && k.finger == key.finger) {
+            Some(home) => Some(distance(home)),
+            None => homes.map(distance).min_by(f64::total_cmp),
+        }
+    }
+
+    fn key(&self, key_code: &str) -> Option<&KeyPosition> {
+        self.keys.iter().find(|k| k.key_code == key_code)
+    }
+}
+
+/// QWERTY codes of the keys fingers rest on, thumbs on space.
+const HOME_KEYS: &[&str] = &["a", "s", "d", "f", "j", "k", "l", "semicolon", "space"];
+
+/// Board shape plus the layout printed on it, written `physical[:logical]`,
+/// e.g. `iso`, `ansi:dvorak`, `split:colemak-dh` or `corne.toml:colemak`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LayoutSpec {
+    pub physical: Physical,
+    pub logical: Logical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Physical {
+    #[default]
+    Ansi,
+    Iso,
+    Jis,
+    /// 5x12 grid, Preonic style
+    Ortho,
+    /// 3x6 columns plus three thumb keys per half, Corne style
+    Split,
+    /// a TOML, JSON or KLE JSON layout file
+    File(PathBuf),
+}
+
+/// Which letter sits where. Key codes are looked up at the position the
+/// logical layout puts them, which matches keyboards that remap in
+/// firmware and Windows, where key codes follow the OS layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Logical {
+    #[default]
+    Qwerty,
+    Dvorak,
+    Colemak,
+    ColemakDh,
+}
+
+impl Logical {
+    /// The key code this layout produces at the position of a QWERTY key,
+    /// if it differs.
+    fn remap(self, qwerty: &str) -> Option<&'static str> {
+        let table: &[(&str, &str)] = match self {
+            Logical::Qwerty => &[],
+            Logical::Dvorak => DVORAK,
+            Logical::Colemak => COLEMAK,
+            Logical::ColemakDh => COLEMAK_DH,
+        };
+        table
+            .iter()
+            .find(|(from, _)| *from == qwerty)
+            .map(|(_, to)| *to)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseLayoutError(String);
+
+impl fmt::Display for ParseLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown layout '{}', expected physical[:logical] with physical one of \
+             ansi, iso, jis, ortho, split or a .toml/.json layout file, and logical one of \
+             qwerty, dvorak, colemak, colemak-dh",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseLayoutError {}
+
+impl FromStr for LayoutSpec {
+    type Err = ParseLayoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseLayoutError(s.to_string());
+        let s = s.trim();
+
+        // split on the last colon only if what follows is a logical layout,
+        // so Windows paths like C:\layouts\corne.toml still work
+        let (physical, logical) = match s.rsplit_once(':') {
+            Some((physical, logical)) => match parse_logical(logical) {
+                Some(logical) => (physical, logical),
+                None => (s, Logical::Qwerty),
+            },
+            None => (s, Logical::Qwerty),
+        };
+
+        let physical = match physical.to_lowercase().as_str() {
+            "ansi" => Physical::Ansi,
+            "iso" => Physical::Iso,
+            "jis" => Physical::Jis,
+            "ortho" | "ortholinear" => Physical::Ortho,
+            "split" => Physical::Split,
+            p if p.ends_with(".toml") || p.ends_with(".json") => {
+                Physical::File(PathBuf::from(physical))
+            }
+            _ => return Err(err()),
+        };
+
+        Ok(Self { physical, logical })
+    }
+}
+
+impl LayoutSpec {
+    /// Resolves a relative layout file path against `dir`.
+    pub fn relative_to(self, dir: &Path) -> Self {
+        match self.physical {
+            Physical::File(path) if path.is_relative() => Self {
+                physical: Physical::File(dir.join(path)),
+                ..self
+            },
+            _ => self,
+        }
+    }
+}
+
+fn parse_logical(s: &str) -> Option<Logical> {
+    let logical = match s.to_lowercase().as_str() {
+        "qwerty" => Logical::Qwerty,
+        "dvorak" => Logical::Dvorak,
+        "colemak" => Logical::Colemak,
+        "colemak-dh" | "colemak_dh" | "colemakdh" => Logical::ColemakDh,
+        _ => return None,
+    };
+    Some(logical)
 }
 
-pub fn qwerty_ansi() -> Vec<KeyPosition> {
-    vec![
-        // Row 0: Number row
-        KeyPosition {
-            key_code: "grave",
-            x: 0.0,
-            y: 0.0,
-            width: 1.0,
-            label: "`",
-        },
-        KeyPosition {
-            key_code: "1",
-            x: 1.0,
-            y: 0.0,
-            width: 1.0,
-            label: "1",
-        },
-        KeyPosition {
-            key_code: "2",
-            x: 2.0,
-            y: 0.0,
-            width: 1.0,
-            label: "2",
-        },
-        KeyPosition {
-            key_code: "3",
-            x: 3.0,
-            y: 0.0,
-            width: 1.0,
-            label: "3",
-        },
-        KeyPosition {
-            key_code: "4",
-            x: 4.0,
-            y: 0.0,
-            width: 1.0,
-            label: "4",
-        },
-        KeyPosition {
-            key_code: "5",
-            x: 5.0,
-            y: 0.0,
-            width: 1.0,
-            label: "5",
-        },
-        KeyPosition {
-            key_code: "6",
-            x: 6.0,
-            y: 0.0,
-            width: 1.0,
-            label: "6",
-        },
-        KeyPosition {
-            key_code: "7",
-            x: 7.0,
-            y: 0.0,
-            width: 1.0,
-            label: "7",
-        },
-        KeyPosition {
-            key_code: "8",
-            x: 8.0,
-            y: 0.0,
-            width: 1.0,
-            label: "8",
-        },
-        KeyPosition {
-            key_code: "9",
-            x: 9.0,
-            y: 0.0,
-            width: 1.0,
-            label: "9",
-        },
-        KeyPosition {
-            key_code: "0",
-            x: 10.0,
-            y: 0.0,
-            width: 1.0,
-            label: "0",
-        },
-        KeyPosition {
-            key_code: "minus",
-            x: 11.0,
-            y: 0.0,
-            width: 1.0,
-            label: "-",
-        },
-        KeyPosition {
-            key_code: "equal",
-            x: 12.0,
-            y: 0.0,
-            width: 1.0,
-            label: "=",
-        },
-        KeyPosition {
-            key_code: "backspace",
-            x: 13.0,
-            y: 0.0,
-            width: 2.0,
-            label: "Back",
-        },
-        // Row 1: QWERTY row
-        KeyPosition {
-            key_code: "tab",
-            x: 0.0,
-            y: 1.0,
-            width: 1.5,
-            label: "Tab",
-        },
-        KeyPosition {
-            key_code: "q",
-            x: 1.5,
-            y: 1.0,
-            width: 1.0,
-            label: "Q",
-        },
-        KeyPosition {
-            key_code: "w",
-            x: 2.5,
-            y: 1.0,
-            width: 1.0,
-            label: "W",
-        },
-        KeyPosition {
-            key_code: "e",
-            x: 3.5,
-            y: 1.0,
-            width: 1.0,
-            label: "E",
-        },
-        KeyPosition {
-            key_code: "r",
-            x: 4.5,
-            y: 1.0,
-            width: 1.0,
-            label: "R",
-        },
-        KeyPosition {
-            key_code: "t",
-            x: 5.5,
-            y: 1.0,
-            width: 1.0,
-            label: "T",
-        },
-        KeyPosition {
-            key_code: "y",
-            x: 6.5,
-            y: 1.0,
-            width: 1.0,
-            label: "Y",
-        },
-        KeyPosition {
-            key_code: "u",
-            x: 7.5,
-            y: 1.0,
-            width: 1.0,
-            label: "U",
-        },
-        KeyPosition {
-            key_code: "i",
-            x: 8.5,
-            y: 1.0,
-            width: 1.0,
-            label: "I",
-        },
-        KeyPosition {
-            key_code: "o",
-            x: 9.5,
-            y: 1.0,
-            width: 1.0,
-            label: "O",
-        },
-        KeyPosition {
-            key_code: "p",
-            x: 10.5,
-            y: 1.0,
-            width: 1.0,
-            label: "P",
-        },
-        KeyPosition {
-            key_code: "leftbracket",
-            x: 11.5,
-            y: 1.0,
-            width: 1.0,
-            label: "[",
-        },
-        KeyPosition {
-            key_code: "rightbracket",
-            x: 12.5,
-            y: 1.0,
-            width: 1.0,
-            label: "]",
-        },
-        KeyPosition {
-            key_code: "backslash",
-            x: 13.5,
-            y: 1.0,
-            width: 1.5,
-            label: "\\",
-        },
-        // Row 2: Home row
-        KeyPosition {
-            key_code: "capslock",
-            x: 0.0,
-            y: 2.0,
-            width: 1.75,
-            label: "Caps",
-        },
-        KeyPosition {
-            key_code: "a",
-            x: 1.75,
-            y: 2.0,
-            width: 1.0,
-            label: "A",
-        },
-        KeyPosition {
-            key_code: "s",
-            x: 2.75,
-            y: 2.0,
-            width: 1.0,
-            label: "S",
-        },
-        KeyPosition {
-            key_code: "d",
-            x: 3.75,
-            y: 2.0,
-            width: 1.0,
-            label: "D",
-        },
-        KeyPosition {
-            key_code: "f",
-            x: 4.75,
-            y: 2.0,
-            width: 1.0,
-            label: "F",
-        },
-        KeyPosition {
-            key_code: "g",
-            x: 5.75,
-            y: 2.0,
-            width: 1.0,
-            label: "G",
-        },
-        KeyPosition {
-            key_code: "h",
-            x: 6.75,
-            y: 2.0,
-            width: 1.0,
-            label: "H",
-        },
-        KeyPosition {
-            key_code: "j",
-            x: 7.75,
-            y: 2.0,
-            width: 1.0,
-            label: "J",
-        },
-        KeyPosition {
-            key_code: "k",
-            x: 8.75,
-            y: 2.0,
-            width: 1.0,
-            label: "K",
-        },
-        KeyPosition {
-            key_code: "l",
-            x: 9.75,
-            y: 2.0,
-            width: 1.0,
-            label: "L",
-        },
-        KeyPosition {
-            key_code: "semicolon",
-            x: 10.75,
-            y: 2.0,
-            width: 1.0,
-            label: ";",
-        },
-        KeyPosition {
-            key_code: "apostrophe",
-            x: 11.75,
-            y: 2.0,
-            width: 1.0,
-            label: "'",
-        },
-        KeyPosition {
-            key_code: "enter",
-            x: 12.75,
-            y: 2.0,
-            width: 2.25,
-            label: "Enter",
-        },
-        // Row 3: Bottom letter row
-        KeyPosition {
-            key_code: "lshift",
-            x: 0.0,
-            y: 3.0,
-            width: 2.25,
-            label: "Shift",
-        },
-        KeyPosition {
-            key_code: "z",
-            x: 2.25,
-            y: 3.0,
-            width: 1.0,
-            label: "Z",
-        },
-        KeyPosition {
-            key_code: "x",
-            x: 3.25,
-            y: 3.0,
-            width: 1.0,
-            label: "X",
-        },
-        KeyPosition {
-            key_code: "c",
-            x: 4.25,
-            y: 3.0,
-            width: 1.0,
-            label: "C",
-        },
-        KeyPosition {
-            key_code: "v",
-            x: 5.25,
-            y: 3.0,
-            width: 1.0,
-            label: "V",
-        },
-        KeyPosition {
-            key_code: "b",
-            x: 6.25,
-            y: 3.0,
-            width: 1.0,
-            label: "B",
-        },
-        KeyPosition {
-            key_code: "n",
-            x: 7.25,
-            y: 3.0,
-            width: 1.0,
-            label: "N",
-        },
-        KeyPosition {
-            key_code: "m",
-            x: 8.25,
-            y: 3.0,
-            width: 1.0,
-            label: "M",
-        },
-        KeyPosition {
-            key_code: "comma",
-            x: 9.25,
-            y: 3.0,
-            width: 1.0,
-            label: ",",
-        },
-        KeyPosition {
-            key_code: "period",
-            x: 10.25,
-            y: 3.0,
-            width: 1.0,
-            label: ".",
-        },
-        KeyPosition {
-            key_code: "slash",
-            x: 11.25,
-            y: 3.0,
-            width: 1.0,
-            label: "/",
-        },
-        KeyPosition {
-            key_code: "rshift",
-            x: 12.25,
-            y: 3.0,
-            width: 2.75,
-            label: "Shift",
-        },
-        // Row 4: Bottom row
-        KeyPosition {
-            key_code: "lctrl",
-            x: 0.0,
-            y: 4.0,
-            width: 1.25,
-            label: "Ctrl",
-        },
-        KeyPosition {
-            key_code: "lsuper",
-            x: 1.25,
-            y: 4.0,
-            width: 1.25,
-            label: "Win",
-        },
-        KeyPosition {
-            key_code: "lalt",
-            x: 2.5,
-            y: 4.0,
-            width: 1.25,
-            label: "Alt",
-        },
-        KeyPosition {
-            key_code: "space",
-            x: 3.75,
-            y: 4.0,
-            width: 6.25,
-            label: "",
-        },
-        KeyPosition {
-            key_code: "ralt",
-            x: 10.0,
-            y: 4.0,
-            width: 1.25,
-            label: "Alt",
-        },
-        KeyPosition {
-            key_code: "rsuper",
-            x: 11.25,
-            y: 4.0,
-            width: 1.25,
-            label: "Win",
-        },
-        KeyPosition {
-            key_code: "menu",
-            x: 12.5,
-            y: 4.0,
-            width: 1.25,
-            label: "Menu",
-        },
-        KeyPosition {
-            key_code: "rctrl",
-            x: 13.75,
-            y: 4.0,
-            width: 1.25,
-            label: "Ctrl",
-        },
-    ]
+impl fmt::Display for LayoutSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let physical = match &self.physical {
+            Physical::Ansi => "ANSI".into(),
+            Physical::Iso => "ISO".into(),
+            Physical::Jis => "JIS".into(),
+            Physical::Ortho => "Ortholinear".into(),
+            Physical::Split => "Split".into(),
+            Physical::File(path) => path
+                .file_stem()
+                .map(|s| s.to_string_lossy())
+                .unwrap_or_default(),
+        };
+        let logical = match self.logical {
+            Logical::Qwerty => "QWERTY",
+            Logical::Dvorak => "Dvorak",
+            Logical::Colemak => "Colemak",
+            Logical::ColemakDh => "Colemak-DH",
+        };
+        write!(f, "{physical} {logical}")
+    }
+}
+
+const DVORAK: &[(&str, &str)] = &[
+    ("minus", "leftbracket"),
+    ("equal", "rightbracket"),
+    ("q", "apostrophe"),
+    ("w", "comma"),
+    ("e", "period"),
+    ("r", "p"),
+    ("t", "y"),
+    ("y", "f"),
+    ("u", "g"),
+    ("i", "c"),
+    ("o", "r"),
+    ("p", "l"),
+    ("leftbracket", "slash"),
+    ("rightbracket", "equal"),
+    ("s", "o"),
+    ("d", "e"),
+    ("f", "u"),
+    ("g", "i"),
+    ("h", "d"),
+    ("j", "h"),
+    ("k", "t"),
+    ("l", "n"),
+    ("semicolon", "s"),
+    ("apostrophe", "minus"),
+    ("z", "semicolon"),
+    ("x", "q"),
+    ("c", "j"),
+    ("v", "k"),
+    ("b", "x"),
+    ("n", "b"),
+    ("comma", "w"),
+    ("period", "v"),
+    ("slash", "z"),
+];
+
+const COLEMAK: &[(&str, &str)] = &[
+    ("e", "f"),
+    ("r", "p"),
+    ("t", "g"),
+    ("y", "j"),
+    ("u", "l"),
+    ("i", "u"),
+    ("o", "y"),
+    ("p", "semicolon"),
+    ("s", "r"),
+    ("d", "s"),
+    ("f", "t"),
+    ("g", "d"),
+    ("j", "n"),
+    ("k", "e"),
+    ("l", "i"),
+    ("semicolon", "o"),
+    ("n", "k"),
+];
+
+const COLEMAK_DH: &[(&str, &str)] = &[
+    ("e", "f"),
+    ("r", "p"),
+    ("t", "b"),
+    ("y", "j"),
+    ("u", "l"),
+    ("i", "u"),
+    ("o", "y"),
+    ("p", "semicolon"),
+    ("s", "r"),
+    ("d", "s"),
+    ("f", "t"),
+    ("h", "m"),
+    ("j", "n"),
+    ("k", "e"),
+    ("l", "i"),
+    ("semicolon", "o"),
+    ("v", "d"),
+    ("b", "v"),
+    ("n", "k"),
+    ("m", "h"),
+];
+
+/// Legend for a key moved by a logical remap.
+pub fn legend(code: &str) -> &str {
+    match code {
+        "a" => "A",
+        "b" => "B",
+        "c" => "C",
+        "d" => "D",
+        "e" => "E",
+        "f" => "F",
+        "g" => "G",
+        "h" => "H",
+        "i" => "I",
+        "j" => "J",
+        "k" => "K",
+        "l" => "L",
+        "m" => "M",
+        "n" => "N",
+        "o" => "O",
+        "p" => "P",
+        "q" => "Q",
+        "r" => "R",
+        "s" => "S",
+        "t" => "T",
+        "u" => "U",
+        "v" => "V",
+        "w" => "W",
+        "x" => "X",
+        "y" => "Y",
+        "z" => "Z",
+        "minus" => "-",
+        "equal" => "=",
+        "leftbracket" => "[",
+        "rightbracket" => "]",
+        "semicolon" => ";",
+        "apostrophe" => "'",
+        "comma" => ",",
+        "period" => ".",
+        "slash" => "/",
+        other => other,
+    }
+}
+
+/// Lays keys out left to right from `x`, as (code, label, width).
+fn row(y: f64, x: f64, keys: &[(&str, &str, f64)]) -> Vec<KeyPosition> {
+    let mut x = x;
+    keys.iter()
+        .map(|&(key_code, label, width)| {
+            let key = KeyPosition {
+                key_code: key_code.to_string(),
+                x,
+                y,
+                width,
+                height: 1.0,
+                rotation: 0.0,
+                label: label.to_string(),
+                finger: None,
+                home: false,
+            };
+            x += width;
+            key
+        })
+        .collect()
+}
+
+const NUMBERS: [(&str, &str, f64); 10] = [
+    ("1", "1", 1.0),
+    ("2", "2", 1.0),
+    ("3", "3", 1.0),
+    ("4", "4", 1.0),
+    ("5", "5", 1.0),
+    ("6", "6", 1.0),
+    ("7", "7", 1.0),
+    ("8", "8", 1.0),
+    ("9", "9", 1.0),
+    ("0", "0", 1.0),
+];
+
+const TOP_LETTERS: [(&str, &str, f64); 10] = [
+    ("q", "Q", 1.0),
+    ("w", "W", 1.0),
+    ("e", "E", 1.0),
+    ("r", "R", 1.0),
+    ("t", "T", 1.0),
+    ("y", "Y", 1.0),
+    ("u", "U", 1.0),
+    ("i", "I", 1.0),
+    ("o", "O", 1.0),
+    ("p", "P", 1.0),
+];
+
+const HOME_LETTERS: [(&str, &str, f64); 10] = [
+    ("a", "A", 1.0),
+    ("s", "S", 1.0),
+    ("d", "D", 1.0),
+    ("f", "F", 1.0),
+    ("g", "G", 1.0),
+    ("h", "H", 1.0),
+    ("j", "J", 1.0),
+    ("k", "K", 1.0),
+    ("l", "L", 1.0),
+    ("semicolon", ";", 1.0),
+];
+
+const BOTTOM_LETTERS: [(&str, &str, f64); 10] = [
+    ("z", "Z", 1.0),
+    ("x", "X", 1.0),
+    ("c", "C", 1.0),
+    ("v", "V", 1.0),
+    ("b", "B", 1.0),
+    ("n", "N", 1.0),
+    ("m", "M", 1.0),
+    ("comma", ",", 1.0),
+    ("period", ".", 1.0),
+    ("slash", "/", 1.0),
+];
+
+fn keys<'a>(
+    leading: &[(&'a str, &'a str, f64)],
+    middle: &[(&'a str, &'a str, f64)],
+    trailing: &[(&'a str, &'a str, f64)],
+) -> Vec<(&'a str, &'a str, f64)> {
+    [leading, middle, trailing].concat()
+}
+
+/// Bottom row shared by ANSI and ISO.
+fn standard_bottom_row() -> Vec<KeyPosition> {
+    row(
+        4.0,
+        0.0,
+        &[
+            ("lctrl", "Ctrl", 1.25),
+            ("lsuper", "Win", 1.25),
+            ("lalt", "Alt", 1.25),
+            ("space", "", 6.25),
+            ("ralt", "Alt", 1.25),
+            ("rsuper", "Win", 1.25),
+            ("menu", "Menu", 1.25),
+            ("rctrl", "Ctrl", 1.25),
+        ],
+    )
+}
+
+fn number_row(trailing: &[(&str, &str, f64)]) -> Vec<KeyPosition> {
+    row(0.0, 0.0, &keys(&[("grave", "`", 1.0)], &NUMBERS, trailing))
+}
+
+pub fn ansi() -> Vec<KeyPosition> {
+    let mut layout = number_row(&[
+        ("minus", "-", 1.0),
+        ("equal", "=", 1.0),
+        ("backspace", "Back", 2.0),
+    ]);
+    layout.extend(row(
+        1.0,
+        0.0,
+        &keys(
+            &[("tab", "Tab", 1.5)],
+            &TOP_LETTERS,
+            &[
+                ("leftbracket", "[", 1.0),
+                ("rightbracket", "]", 1.0),
+                ("backslash", "\\", 1.5),
+            ],
+        ),
+    ));
+    layout.extend(row(
+        2.0,
+        0.0,
+        &keys(
+            &[("capslock", "Caps", 1.75)],
+            &HOME_LETTERS,
+            &[("apostrophe", "'", 1.0), ("enter", "Enter", 2.25)],
+        ),
+    ));
+    layout.extend(row(
+        3.0,
+        0.0,
+        &keys(
+            &[("lshift", "Shift", 2.25)],
+            &BOTTOM_LETTERS,
+            &[("rshift", "Shift", 2.75)],
+        ),
+    ));
+    layout.extend(standard_bottom_row());
+    layout
+}
+
+/// Tall enter spanning the top two letter rows, as on ISO and JIS boards.
+fn tall_enter() -> KeyPosition {
+    KeyPosition {
+        height: 2.0,
+        ..row(1.0, 13.75, &[("enter", "Enter", 1.25)]).remove(0)
+    }
+}
+
+pub fn iso() -> Vec<KeyPosition> {
+    let mut layout = number_row(&[
+        ("minus", "-", 1.0),
+        ("equal", "=", 1.0),
+        ("backspace", "Back", 2.0),
+    ]);
+    layout.extend(row(
+        1.0,
+        0.0,
+        &keys(
+            &[("tab", "Tab", 1.5)],
+            &TOP_LETTERS,
+            &[("leftbracket", "[", 1.0), ("rightbracket", "]", 1.0)],
+        ),
+    ));
+    layout.push(tall_enter());
+    layout.extend(row(
+        2.0,
+        0.0,
+        &keys(
+            &[("capslock", "Caps", 1.75)],
+            &HOME_LETTERS,
+            &[("apostrophe", "'", 1.0), ("backslash", "#", 1.0)],
+        ),
+    ));
+    layout.extend(row(
+        3.0,
+        0.0,
+        &keys(
+            &[("lshift", "Shift", 1.25), ("intlbackslash", "\\", 1.0)],
+            &BOTTOM_LETTERS,
+            &[("rshift", "Shift", 2.75)],
+        ),
+    ));
+    layout.extend(standard_bottom_row());
+    layout
+}
+
+pub fn jis() -> Vec<KeyPosition> {
+    let mut layout = row(
+        0.0,
+        0.0,
+        &keys(
+            &[("zenkakuhankaku", "半/全", 1.0)],
+            &NUMBERS,
+            &[
+                ("minus", "-", 1.0),
+                ("equal", "^", 1.0),
+                ("intlyen", "¥", 1.0),
+                ("backspace", "Back", 1.0),
+            ],
+        ),
+    );
+    layout.extend(row(
+        1.0,
+        0.0,
+        &keys(
+            &[("tab", "Tab", 1.5)],
+            &TOP_LETTERS,
+            &[("leftbracket", "@", 1.0), ("rightbracket", "[", 1.0)],
+        ),
+    ));
+    layout.push(tall_enter());
+    layout.extend(row(
+        2.0,
+        0.0,
+        &keys(
+            &[("capslock", "Caps", 1.75)],
+            &HOME_LETTERS,
+            &[("apostrophe", ":", 1.0), ("backslash", "]", 1.0)],
+        ),
+    ));
+    layout.extend(row(
+        3.0,
+        0.0,
+        &keys(
+            &[("lshift", "Shift", 2.25)],
+            &BOTTOM_LETTERS,
+            &[("intlro", "\\", 1.0), ("rshift", "Shift", 1.75)],
+        ),
+    ));
+    layout.extend(row(
+        4.0,
+        0.0,
+        &[
+            ("lctrl", "Ctrl", 1.25),
+            ("lsuper", "Win", 1.25),
+            ("lalt", "Alt", 1.25),
+            ("muhenkan", "無変換", 1.25),
+            ("space", "", 3.75),
+            ("henkan", "変換", 1.25),
+            ("katakanahiragana", "かな", 1.25),
+            ("ralt", "Alt", 1.25),
+            ("menu", "Menu", 1.25),
+            ("rctrl", "Ctrl", 1.25),
+        ],
+    ));
+    layout
+}
+
+pub fn ortho() -> Vec<KeyPosition> {
+    let mut layout = number_row(&[("minus", "-", 1.0)]);
+    layout.extend(row(
+        1.0,
+        0.0,
+        &keys(
+            &[("tab", "Tab", 1.0)],
+            &TOP_LETTERS,
+            &[("backspace", "Back", 1.0)],
+        ),
+    ));
+    layout.extend(row(
+        2.0,
+        0.0,
+        &keys(
+            &[("escape", "Esc", 1.0)],
+            &HOME_LETTERS,
+            &[("apostrophe", "'", 1.0)],
+        ),
+    ));
+    layout.extend(row(
+        3.0,
+        0.0,
+        &keys(
+            &[("lshift", "Shift", 1.0)],
+            &BOTTOM_LETTERS,
+            &[("enter", "Enter", 1.0)],
+        ),
+    ));
+    layout.extend(row(
+        4.0,
+        0.0,
+        &[
+            ("lctrl", "Ctrl", 1.0),
+            ("lsuper", "Win", 1.0),
+            ("lalt", "Alt", 1.0),
+            ("leftbracket", "[", 1.0),
+            ("rightbracket", "]", 1.0),
+            ("space", "", 2.0),
+            ("equal", "=", 1.0),
+            ("left", "←", 1.0),
+            ("down", "↓", 1.0),
+            ("up", "↑", 1.0),
+            ("right", "→", 1.0),
+        ],
+    ));
+    layout
+}
+
+/// Gap between the halves of a split board.
+const SPLIT_GAP: f64 = 1.5;
+
+pub fn split() -> Vec<KeyPosition> {
+    let right = 6.0 + SPLIT_GAP;
+    let mut layout = Vec::new();
+
+    let rows: [(&[_], &[_]); 3] = [
+        (&[("tab", "Tab", 1.0)], &[("backspace", "Back", 1.0)]),
+        (&[("lctrl", "Ctrl", 1.0)], &[("apostrophe", "'", 1.0)]),
+        (&[("lshift", "Shift", 1.0)], &[("escape", "Esc", 1.0)]),
+    ];
+    let letters = [TOP_LETTERS, HOME_LETTERS, BOTTOM_LETTERS];
+
+    for (y, ((outer_left, outer_right), letters)) in rows.iter().zip(letters).enumerate() {
+        let y = y as f64;
+        layout.extend(row(y, 0.0, &keys(outer_left, &letters[..5], &[])));
+        layout.extend(row(y, right, &keys(&[], &letters[5..], outer_right)));
+    }
+
+    // thumb clusters sit a little below the last row
+    let left_thumbs = row(
+        3.25,
+        3.0,
+        &[("lsuper", "Win", 1.0), ("lalt", "Alt", 1.0), ("space", "", 1.0)],
+    );
+    let right_thumbs = row(
+        3.25,
+        right,
+        &[("enter", "Enter", 1.0), ("rshift", "Shift", 1.0), ("ralt", "Alt", 1.0)],
+    );
+    for (thumbs, finger) in [
+        (left_thumbs, Finger::LeftThumb),
+        (right_thumbs, Finger::RightThumb),
+    ] {
+        layout.extend(thumbs.into_iter().map(|key| KeyPosition {
+            finger: Some(finger),
+            ..key
+        }));
+    }
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn layout_spec_parses_and_remaps() {
+        let spec: LayoutSpec = "split:Colemak-DH".parse().unwrap();
+        assert_eq!(spec.physical, Physical::Split);
+        assert_eq!(spec.logical, Logical::ColemakDh);
+        assert!("iso:azerty".parse::<LayoutSpec>().is_err());
+
+        let file: LayoutSpec = r"C:\boards\corne.json:dvorak".parse().unwrap();
+        assert_eq!(file.physical, Physical::File(r"C:\boards\corne.json".into()));
+        assert_eq!(file.logical, Logical::Dvorak);
+
+        // remaps move keys without duplicating or losing any
+        for logical in [Logical::Dvorak, Logical::Colemak, Logical::ColemakDh] {
+            let layout = Layout::build(&LayoutSpec {
+                physical: Physical::Ansi,
+                logical,
+            })
+            .unwrap();
+            let codes: HashSet<_> = layout.keys.iter().map(|k| &k.key_code).collect();
+            assert_eq!(codes.len(), layout.keys.len(), "{logical:?}");
+        }
+
+        // on Dvorak, the key under the left middle finger types E
+        let dvorak = Layout::build(&"ansi:dvorak".parse().unwrap()).unwrap();
+        assert_eq!(dvorak.travel("e"), Some(0.0));
+        assert_eq!(dvorak.travel("d"), Some(1.0));
+    }
 }
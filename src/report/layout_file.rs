@@ -0,0 +1,337 @@
+use super::fingers::Finger;
+use super::layout::{legend, KeyPosition};
+use crate::error::Error;
+use crate::keycode::KeyCode;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// A board described key by key, in TOML or JSON:
+///
+/// ```toml
+/// name = "Corne"
+///
+/// [[keys]]
+/// code = "q"
+/// x = 1
+/// y = 0.25
+/// finger = "left-ring"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutFile {
+    name: Option<String>,
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyEntry {
+    code: String,
+    x: f64,
+    y: f64,
+    #[serde(default = "one")]
+    width: f64,
+    #[serde(default = "one")]
+    height: f64,
+    #[serde(default)]
+    rotation: f64,
+    label: Option<String>,
+    finger: Option<Finger>,
+    #[serde(default)]
+    home: bool,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+/// Loads a layout file. `.toml` and `.json` use the format above; a JSON
+/// file holding a top-level array is read as a keyboard-layout-editor
+/// (KLE) raw data export.
+pub fn load(path: &Path) -> Result<(Option<String>, Vec<KeyPosition>), Error> {
+    let err = |msg: String| Error::Layout(format!("{}: {msg}", path.display()));
+    let text = std::fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let file: LayoutFile = if is_json {
+        let value: Value = serde_json::from_str(&text).map_err(|e| err(e.to_string()))?;
+        if value.is_array() {
+            return parse_kle(&value).map_err(err);
+        }
+        serde_json::from_value(value).map_err(|e| err(e.to_string()))?
+    } else {
+        toml::from_str(&text).map_err(|e| err(e.to_string()))?
+    };
+
+    let keys = file
+        .keys
+        .into_iter()
+        .map(|entry| {
+            // catch typos here rather than drawing a key that never lights up
+            entry
+                .code
+                .parse::<KeyCode>()
+                .map_err(|_| err(format!("unknown key code '{}'", entry.code)))?;
+            Ok(KeyPosition {
+                label: entry
+                    .label
+                    .unwrap_or_else(|| legend(&entry.code).to_string()),
+                key_code: entry.code,
+                x: entry.x,
+                y: entry.y,
+                width: entry.width,
+                height: entry.height,
+                rotation: entry.rotation,
+                finger: entry.finger,
+                home: entry.home,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok((file.name, keys))
+}
+
+/// Reads KLE raw data: rows of legend strings, each optionally preceded by
+/// an object that adjusts position, size or rotation for what follows.
+fn parse_kle(data: &Value) -> Result<(Option<String>, Vec<KeyPosition>), String> {
+    let mut name = None;
+    let mut keys = Vec::new();
+    let mut modifiers = HashSet::new();
+
+    // rotation state persists across rows, size only applies to the next key
+    let (mut r, mut rx, mut ry) = (0.0, 0.0, 0.0);
+    let (mut x, mut y) = (0.0, 0.0);
+    let (mut w, mut h) = (1.0, 1.0);
+
+    for row in data.as_array().into_iter().flatten() {
+        let items = match row {
+            Value::Array(items) => items,
+            Value::Object(meta) => {
+                name = meta.get("name").and_then(Value::as_str).map(str::to_string);
+                continue;
+            }
+            _ => return Err("expected an array of rows".to_string()),
+        };
+
+        for item in items {
+            match item {
+                Value::Object(props) => {
+                    let num = |key: &str| props.get(key).and_then(Value::as_f64);
+                    if let Some(v) = num("r") {
+                        r = v;
+                    }
+                    if let Some(v) = num("rx") {
+                        rx = v;
+                        (x, y) = (rx, ry);
+                    }
+                    if let Some(v) = num("ry") {
+                        ry = v;
+                        (x, y) = (rx, ry);
+                    }
+                    x += num("x").unwrap_or(0.0);
+                    y += num("y").unwrap_or(0.0);
+                    w = num("w").unwrap_or(w);
+                    h = num("h").unwrap_or(h);
+                }
+                Value::String(legends) => {
+                    let (key_code, label) = kle_key(legends, w, &mut modifiers);
+
+                    // KLE rotates around (rx, ry); store the rotated centre
+                    // and turn the key around that instead
+                    let (sin, cos) = f64::to_radians(r).sin_cos();
+                    let (dx, dy) = (x + w / 2.0 - rx, y + h / 2.0 - ry);
+                    let cx = rx + dx * cos - dy * sin;
+                    let cy = ry + dx * sin + dy * cos;
+
+                    keys.push(KeyPosition {
+                        key_code,
+                        x: cx - w / 2.0,
+                        y: cy - h / 2.0,
+                        width: w,
+                        height: h,
+                        rotation: r,
+                        label,
+                        finger: None,
+                        home: false,
+                    });
+                    x += w;
+                    (w, h) = (1.0, 1.0);
+                }
+                _ => return Err(format!("unexpected value in row: {item}")),
+            }
+        }
+        y += 1.0;
+        x = rx;
+    }
+
+    if keys.is_empty() {
+        return Err("no keys found".to_string());
+    }
+    Ok((name, keys))
+}
+
+/// Picks a key code from a KLE legend. Legends stack several lines
+/// (e.g. "!\n1"), the unshifted one usually last.
+fn kle_key(legends: &str, width: f64, modifiers: &mut HashSet<&'static str>) -> (String, String) {
+    let lines: Vec<&str> = legends
+        .split('\n')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    if lines.is_empty() && width >= 3.0 {
+        return ("space".to_string(), String::new());
+    }
+    for line in lines.iter().rev() {
+        if let Some(code) = legend_code(line, modifiers) {
+            return (code, line.to_string());
+        }
+    }
+    let label = lines.first().copied().unwrap_or_default().to_string();
+    (String::new(), label)
+}
+
+fn legend_code(legend: &str, modifiers: &mut HashSet<&'static str>) -> Option<String> {
+    let lower = legend.to_lowercase();
+    let code = match lower.as_str() {
+        "`" | "~" => "grave",
+        "!" => "1",
+        "@" => "2",
+        "#" => "3",
+        "$" => "4",
+        "%" => "5",
+        "^" => "6",
+        "&" => "7",
+        "*" => "8",
+        "(" => "9",
+        ")" => "0",
+        "-" | "_" => "minus",
+        "=" | "+" => "equal",
+        "[" | "{" => "leftbracket",
+        "]" | "}" => "rightbracket",
+        "\\" | "|" => "backslash",
+        ";" | ":" => "semicolon",
+        "'" | "\"" => "apostrophe",
+        "," | "<" => "comma",
+        "." | ">" => "period",
+        "/" | "?" => "slash",
+        "esc" => "escape",
+        "caps" | "caps lock" => "capslock",
+        "return" | "enter" => "enter",
+        "bksp" | "back" | "backspace" => "backspace",
+        "del" => "delete",
+        "ins" => "insert",
+        "pgup" | "pg up" | "page up" => "pageup",
+        "pgdn" | "pg dn" | "page down" => "pagedown",
+        "prtsc" | "prt sc" | "print screen" => "printscreen",
+        "scroll lock" => "scrolllock",
+        "num lock" => "numlock",
+        "spacebar" => "space",
+        "↑" => "up",
+        "↓" => "down",
+        "←" => "left",
+        "→" => "right",
+        "altgr" => "ralt",
+        "shift" | "⇧" => side(modifiers, "shift"),
+        "ctrl" | "control" => side(modifiers, "ctrl"),
+        "alt" | "opt" | "option" => side(modifiers, "alt"),
+        "win" | "super" | "cmd" | "command" | "meta" | "gui" => side(modifiers, "super"),
+        other => {
+            return match other.parse::<KeyCode>() {
+                Ok(KeyCode::Unknown(_)) | Err(_) => None,
+                Ok(_) => Some(other.to_string()),
+            }
+        }
+    };
+    Some(code.to_string())
+}
+
+/// Boards list modifiers left to right, so the first of each is the left.
+fn side(seen: &mut HashSet<&'static str>, modifier: &'static str) -> &'static str {
+    let left = seen.insert(modifier);
+    match (modifier, left) {
+        ("shift", true) => "lshift",
+        ("shift", false) => "rshift",
+        ("ctrl", true) => "lctrl",
+        ("ctrl", false) => "rctrl",
+        ("alt", true) => "lalt",
+        ("alt", false) => "ralt",
+        (_, true) => "lsuper",
+        (_, false) => "rsuper",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_layout_keeps_fingers_and_rejects_typos() {
+        let file: LayoutFile = toml::from_str(
+            r#"
+            name = "Tiny"
+
+            [[keys]]
+            code = "a"
+            x = 0
+            y = 0
+            finger = "left-index"
+            home = true
+
+            [[keys]]
+            code = "space"
+            x = 1
+            y = 0
+            width = 2
+            rotation = 15
+            "#,
+        )
+        .unwrap();
+        assert_eq!(file.name.as_deref(), Some("Tiny"));
+        assert_eq!(file.keys[0].finger, Some(Finger::LeftIndex));
+        assert!(file.keys[0].home);
+        assert_eq!(file.keys[1].width, 2.0);
+        assert_eq!(file.keys[1].height, 1.0);
+        assert_eq!(file.keys[1].rotation, 15.0);
+
+        let dir = std::env::temp_dir().join(format!("keyheat-layout-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("typo.toml");
+        std::fs::write(&path, "[[keys]]\ncode = \"shfit\"\nx = 0\ny = 0\n").unwrap();
+        let err = load(&path).unwrap_err().to_string();
+        assert!(err.contains("shfit"), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn kle_rows_sizes_and_rotation() {
+        let data: Value = serde_json::from_str(
+            r#"[
+                {"name": "Thumbs"},
+                ["Shift", "!\n1", {"w": 1.5}, "Tab", "Shift"],
+                [{"y": 0.5, "w": 6.25}, ""],
+                [{"r": 90, "rx": 10, "ry": 0}, "Q"]
+            ]"#,
+        )
+        .unwrap();
+        let (name, keys) = parse_kle(&data).unwrap();
+        assert_eq!(name.as_deref(), Some("Thumbs"));
+
+        let codes: Vec<_> = keys.iter().map(|k| k.key_code.as_str()).collect();
+        assert_eq!(codes, ["lshift", "1", "tab", "rshift", "space", "q"]);
+
+        assert_eq!((keys[2].x, keys[2].width), (2.0, 1.5));
+        assert_eq!(keys[3].x, 3.5);
+        assert_eq!((keys[4].y, keys[4].width), (1.5, 6.25));
+
+        // a key at the rotation origin swings down and to the left
+        let q = &keys[5];
+        assert_eq!(q.rotation, 90.0);
+        let (cx, cy) = q.center();
+        assert!((cx - 9.5).abs() < 1e-9 && (cy - 0.5).abs() < 1e-9, "{cx},{cy}");
+    }
+}
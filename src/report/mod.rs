@@ -1,22 +1,44 @@
+mod ansi;
 mod compute;
+mod fingers;
 mod html;
+mod json;
 mod layout;
+mod layout_file;
+mod options;
 mod query;
+mod range;
 
-pub use compute::build_report;
+pub use ansi::{render_activity, render_keyboard, ColorMode};
+pub use compute::{build_report, day_period};
 pub use html::render as render_html;
+pub use json::render as render_json;
+pub use layout::{Layout, LayoutSpec};
+pub use options::configured_layout;
+pub use range::{parse_day, parse_month, parse_week, RangeChoice, RangeSpec, Span};
 
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use crate::storage::Machine;
+use crate::timezone::Zone;
+use fingers::Finger;
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
-pub struct WeekRange {
-    pub start: NaiveDate,
-    pub end: NaiveDate,
-    pub label: String,
+/// What a report covers.
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    /// days to report on, defaults to the current week
+    pub range: RangeSpec,
+    /// only count keystrokes from devices whose id contains this text
+    pub device: Option<String>,
+    /// only count data from this machine, rather than every merged one
+    pub machine: Option<Machine>,
+    /// board the heatmap and finger travel are computed for
+    pub layout: LayoutSpec,
+    /// timezone days and hours are bucketed in
+    pub zone: Zone,
 }
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)] // fields used by HTML renderer in Phase 3
+#[derive(Debug, Clone, Serialize)]
 pub struct SessionSummary {
     pub id: i64,
     pub start_time: DateTime<Utc>,
@@ -27,30 +49,163 @@ pub struct SessionSummary {
     pub peak_wpm: Option<f64>,
 }
 
-#[derive(Debug, Clone)]
+/// A stretch where capture was paused, so nothing was recorded.
+#[derive(Debug, Clone, Serialize)]
+pub struct PausePeriod {
+    pub start_time: DateTime<Utc>,
+    /// None while still paused
+    pub end_time: Option<DateTime<Utc>>,
+    pub duration_minutes: f64,
+    /// "command" or "chord"
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceUsage {
+    pub device: String,
+    pub keystrokes: u64,
+    pub avg_wpm: Option<f64>,
+}
+
+impl DeviceUsage {
+    /// Human-readable name from a `vendor:product:name` device id.
+    pub fn display_name(&self) -> &str {
+        device_display_name(&self.device)
+    }
+}
+
+pub fn device_display_name(device: &str) -> &str {
+    if device.is_empty() {
+        return "Unknown device";
+    }
+    device.splitn(3, ':').nth(2).unwrap_or(device)
+}
+
+/// Median time for a key or key-to-key transition, estimated from the
+/// stored histograms.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyTiming {
+    pub key: String,
+    pub median_ms: f64,
+    pub samples: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransitionTiming {
+    pub from: String,
+    pub to: String,
+    pub median_ms: f64,
+    pub samples: u64,
+}
+
+/// Dwell (press to release) and flight (release to next press) times.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimingStats {
+    pub median_dwell_ms: Option<f64>,
+    pub median_flight_ms: Option<f64>,
+    /// keys held down the longest, slowest first
+    pub slowest_keys: Vec<KeyTiming>,
+    /// transitions with the longest gap, slowest first
+    pub slowest_transitions: Vec<TransitionTiming>,
+}
+
+/// How consecutive keys fall across fingers and hands. Ratios are 0.0..1.0
+/// of the n-grams where every key has a known finger, thumbs excluded.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NgramStats {
+    pub bigrams: u64,
+    pub trigrams: u64,
+    /// different keys typed with the same finger back to back
+    pub same_finger_ratio: f64,
+    /// trigrams that switch hands on every key
+    pub alternation_ratio: f64,
+    /// trigrams with two different fingers of one hand, then the other hand
+    pub roll_ratio: f64,
+    /// most frequent same-finger bigrams, as (first, second, count)
+    pub top_same_finger: Vec<(String, String, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FingerLoad {
+    pub finger: Finger,
+    pub keystrokes: u64,
+    /// 0.0..1.0 of keystrokes on keys with a known finger
+    pub share: f64,
+    pub travel_mm: f64,
+}
+
+/// A pinky doing more than its share, with the keys that put it there.
+#[derive(Debug, Clone, Serialize)]
+pub struct FingerWarning {
+    pub finger: Finger,
+    pub share: f64,
+    pub top_keys: Vec<String>,
+}
+
+/// Keystrokes per finger, using the finger assignment of the report's layout.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FingerStats {
+    /// all ten fingers, left to right
+    pub fingers: Vec<FingerLoad>,
+    /// 0.0..1.0 of non-thumb keystrokes typed by the left hand
+    pub left_hand_share: f64,
+    pub warnings: Vec<FingerWarning>,
+}
+
+/// Key use within one part of the day, from hourly key counts.
+#[derive(Debug, Clone, Serialize)]
+pub struct DayPeriodKeys {
+    pub label: &'static str,
+    /// local hours, start inclusive, end exclusive
+    pub hours: (u8, u8),
+    pub keystrokes: u64,
+    /// most used keys, busiest first
+    pub top_keys: Vec<(String, u64)>,
+    /// key used here noticeably more than across the whole range
+    pub standout: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ShortcutInsight {
     pub message: String,
 }
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)] // fields used by HTML renderer in Phase 3
+/// Everything a report shows. This is also the JSON report, so field
+/// changes belong in `docs/report-json.md` and may need a schema bump.
+#[derive(Debug, Clone, Serialize)]
 pub struct ReportData {
-    pub week: WeekRange,
+    pub range: range::DateRange,
+    /// IANA name of the timezone the report is bucketed in
+    pub timezone: String,
+    pub device: Option<String>,
+    /// name of the machine the report is limited to
+    pub machine: Option<String>,
+    pub layout: layout::Layout,
+    pub devices: Vec<DeviceUsage>,
     pub total_keystrokes: u64,
-    pub prev_week_keystrokes: Option<u64>,
+    pub prev_keystrokes: Option<u64>,
     pub key_frequencies: Vec<(String, u64)>,
     pub avg_wpm: f64,
     pub peak_wpm: f64,
-    pub peak_wpm_time: Option<DateTime<Utc>>,
-    pub prev_week_avg_wpm: Option<f64>,
-    pub daily_wpm: Vec<(NaiveDate, f64)>,
+    pub peak_wpm_time: Option<DateTime<FixedOffset>>,
+    pub prev_avg_wpm: Option<f64>,
+    /// average WPM per day, week or month, depending on the range length
+    pub wpm_trend: Vec<(NaiveDate, f64)>,
     pub wpm_distribution: Vec<u32>,
     pub sessions: Vec<SessionSummary>,
     pub total_typing_minutes: f64,
     pub longest_session: Option<SessionSummary>,
-    pub hourly_activity: [[u64; 24]; 7],
+    /// keystrokes per hour, as (row label, hours)
+    pub hourly_activity: Vec<(String, [u64; 24])>,
+    pub pauses: Vec<PausePeriod>,
+    /// rows of `hourly_activity`, true where capture was paused for some
+    /// of the hour
+    pub paused_hours: Vec<[bool; 24]>,
+    /// night, morning, afternoon and evening; empty if no hourly counts
+    /// were recorded in the range
+    pub day_periods: Vec<DayPeriodKeys>,
     pub shortcuts: Vec<(String, u64)>,
-    pub prev_week_shortcuts: Vec<(String, u64)>,
+    pub prev_shortcuts: Vec<(String, u64)>,
     pub all_time_keystrokes: u64,
     pub finger_travel_mm: f64,
     pub backspace_ratio: f64,
@@ -58,4 +213,7 @@ pub struct ReportData {
     pub peak_hour: Option<u8>,
     pub night_owl_pct: f64,
     pub shortcut_insight: Option<ShortcutInsight>,
+    pub timing: TimingStats,
+    pub ngrams: NgramStats,
+    pub fingers: FingerStats,
 }
@@ -0,0 +1,75 @@
+use super::{LayoutSpec, RangeSpec, ReportOptions};
+use crate::config::Config;
+use crate::error::Error;
+use crate::storage::{Machine, Storage};
+use crate::timezone::Zone;
+
+impl ReportOptions {
+    /// What a report covers and how it's laid out. The timezone and, unless
+    /// given, the layout come from the config file.
+    pub fn build(
+        storage: &Storage,
+        config: &Config,
+        range: RangeSpec,
+        device: Option<String>,
+        machine: Option<String>,
+        layout: Option<LayoutSpec>,
+    ) -> Result<Self, Error> {
+        let zone = Zone::from_config(config)?;
+
+        // a given layout wins over the config file
+        let layout = match layout {
+            Some(layout) => layout,
+            None => configured_layout(config)?,
+        };
+
+        let machine = match machine {
+            Some(name) => Some(find_machine(storage, &name)?),
+            None => None,
+        };
+
+        Ok(ReportOptions {
+            range,
+            device,
+            machine,
+            layout,
+            zone,
+        })
+    }
+}
+
+/// The layout named in the config file, or the default one.
+pub fn configured_layout(config: &Config) -> Result<LayoutSpec, Error> {
+    let Some(layout) = &config.layout else {
+        return Ok(LayoutSpec::default());
+    };
+    let config_path = Config::config_path();
+    let spec: LayoutSpec = layout
+        .parse()
+        .map_err(|e| Error::Layout(format!("{}: {e}", config_path.display())))?;
+    // layout files named in the config live next to it
+    Ok(match config_path.parent() {
+        Some(dir) => spec.relative_to(dir),
+        None => spec,
+    })
+}
+
+/// Looks a machine up by name, or by the id it was imported under.
+fn find_machine(storage: &Storage, name: &str) -> Result<Machine, Error> {
+    let mut machines = storage.machines()?;
+    let found = machines.iter().position(|m| {
+        m.name.eq_ignore_ascii_case(name)
+            || (!m.id.is_empty() && m.id == name)
+            || (m.id.is_empty() && name.eq_ignore_ascii_case("local"))
+    });
+    match found {
+        Some(idx) => Ok(machines.swap_remove(idx)),
+        None => {
+            let names: Vec<&str> = machines.iter().map(|m| m.name.as_str()).collect();
+            Err(Error::UnknownMachine {
+                name: name.to_string(),
+                known: names.join(", "),
+            })
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::Error;
+use crate::storage::hour_key;
 use chrono::{DateTime, NaiveDate, Utc};
 use rusqlite::{params, Connection};
 use std::collections::HashMap;
@@ -14,29 +15,58 @@ pub struct RawSession {
 }
 
 #[derive(Debug)]
-#[allow(dead_code)] // timestamp used by HTML renderer for time-series
-pub struct RawWpmSample {
-    pub timestamp: DateTime<Utc>,
-    pub wpm: f64,
+pub struct RawPause {
+    pub start_time: DateTime<Utc>,
+    /// None while still paused
+    pub end_time: Option<DateTime<Utc>>,
+    pub reason: String,
+}
+
+/// WPM samples in `wpm..wpm + 10`.
+#[derive(Debug)]
+pub struct WpmBin {
+    pub wpm: u32,
+    pub samples: u64,
 }
 
+/// Which rows a report counts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Filter<'a> {
+    /// case-insensitive substring of the device id, so `kinesis` finds
+    /// `29ea:0102:Kinesis Advantage2`
+    pub device: Option<&'a str>,
+    /// exact machine id, empty for this machine
+    pub machine: Option<&'a str>,
+}
+
+/// SQL condition matching `device` and `machine` against a [`Filter`]
+/// bound as `?3` and `?4`. NULL matches everything.
+const ROW_FILTER: &str =
+    "(?3 IS NULL OR instr(lower(device), lower(?3)) > 0) AND (?4 IS NULL OR machine = ?4)";
+
+// Daily tables are keyed by the local date they were recorded on and take
+// inclusive dates. Timestamped tables (sessions, wpm_stats) are stored in
+// UTC and take half-open instants, so callers can bound them by local days.
+
 pub fn key_counts_for_range(
     conn: &Connection,
     start: NaiveDate,
     end: NaiveDate,
+    filter: Filter,
 ) -> Result<HashMap<String, u64>, Error> {
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         "SELECT key_code, SUM(count) as total
          FROM key_counts
-         WHERE date >= ?1 AND date <= ?2
+         WHERE date >= ?1 AND date <= ?2 AND {ROW_FILTER}
          GROUP BY key_code
-         ORDER BY total DESC",
-    )?;
+         ORDER BY total DESC"
+    ))?;
 
     let start_str = start.format("%Y-%m-%d").to_string();
     let end_str = end.format("%Y-%m-%d").to_string();
 
-    let rows = stmt.query_map(params![start_str, end_str], |row| {
+    let args = params![start_str, end_str, filter.device, filter.machine];
+    let rows = stmt.query_map(args, |row| {
         Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
     })?;
 
@@ -53,14 +83,18 @@ pub fn total_keystrokes_for_range(
     conn: &Connection,
     start: NaiveDate,
     end: NaiveDate,
+    filter: Filter,
 ) -> Result<u64, Error> {
     let start_str = start.format("%Y-%m-%d").to_string();
     let end_str = end.format("%Y-%m-%d").to_string();
 
     let total: u64 = conn
         .query_row(
-            "SELECT COALESCE(SUM(count), 0) FROM key_counts WHERE date >= ?1 AND date <= ?2",
-            params![start_str, end_str],
+            &format!(
+                "SELECT COALESCE(SUM(count), 0) FROM key_counts
+                 WHERE date >= ?1 AND date <= ?2 AND {ROW_FILTER}"
+            ),
+            params![start_str, end_str, filter.device, filter.machine],
             |row| row.get(0),
         )
         .unwrap_or(0);
@@ -68,11 +102,13 @@ pub fn total_keystrokes_for_range(
     Ok(total)
 }
 
-pub fn all_time_keystrokes(conn: &Connection) -> Result<u64, Error> {
+pub fn all_time_keystrokes(conn: &Connection, filter: Filter) -> Result<u64, Error> {
     let total: u64 = conn
         .query_row(
-            "SELECT COALESCE(SUM(count), 0) FROM key_counts",
-            [],
+            "SELECT COALESCE(SUM(count), 0) FROM key_counts
+             WHERE (?1 IS NULL OR instr(lower(device), lower(?1)) > 0)
+               AND (?2 IS NULL OR machine = ?2)",
+            params![filter.device, filter.machine],
             |row| row.get(0),
         )
         .unwrap_or(0);
@@ -80,45 +116,111 @@ pub fn all_time_keystrokes(conn: &Connection) -> Result<u64, Error> {
     Ok(total)
 }
 
-pub fn sessions_for_range(
+/// Keystrokes per device, most used first. Counts recorded before device
+/// tracking existed are grouped under an empty device.
+pub fn keystrokes_by_device(
     conn: &Connection,
     start: NaiveDate,
     end: NaiveDate,
-) -> Result<Vec<RawSession>, Error> {
+    machine: Option<&str>,
+) -> Result<Vec<(String, u64)>, Error> {
     let mut stmt = conn.prepare(
-        "SELECT id, start_time, end_time, keystroke_count, avg_wpm, peak_wpm
-         FROM sessions
-         WHERE date(start_time) >= ?1 AND date(start_time) <= ?2
-         ORDER BY start_time",
+        "SELECT device, SUM(count) as total
+         FROM key_counts
+         WHERE date >= ?1 AND date <= ?2 AND (?3 IS NULL OR machine = ?3)
+         GROUP BY device
+         ORDER BY total DESC",
     )?;
 
     let start_str = start.format("%Y-%m-%d").to_string();
     let end_str = end.format("%Y-%m-%d").to_string();
 
-    let rows = stmt.query_map(params![start_str, end_str], |row| {
-        let start_str: String = row.get(1)?;
-        let end_str: Option<String> = row.get(2)?;
+    let rows = stmt.query_map(params![start_str, end_str, machine], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+    })?;
 
-        let start_time = DateTime::parse_from_rfc3339(&start_str)
-            .map(|dt| dt.with_timezone(&Utc))
-            .unwrap_or_else(|_| Utc::now());
+    let mut devices = Vec::new();
+    for row in rows {
+        devices.push(row?);
+    }
 
-        let end_time = end_str.and_then(|s| {
-            DateTime::parse_from_rfc3339(&s)
-                .map(|dt| dt.with_timezone(&Utc))
-                .ok()
-        });
+    Ok(devices)
+}
 
-        Ok(RawSession {
-            id: row.get(0)?,
-            start_time,
-            end_time,
-            keystroke_count: row.get::<_, i64>(3)? as u64,
-            avg_wpm: row.get(4)?,
-            peak_wpm: row.get(5)?,
-        })
+/// Average of stored WPM samples per device.
+pub fn avg_wpm_by_device(
+    conn: &Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    machine: Option<&str>,
+) -> Result<HashMap<String, f64>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT device, SUM(wpm_sum) / SUM(samples)
+         FROM wpm_stats
+         WHERE julianday(timestamp) >= julianday(?1)
+           AND julianday(timestamp) < julianday(?2)
+           AND (?3 IS NULL OR machine = ?3)
+         GROUP BY device",
+    )?;
+
+    let (start_str, end_str) = (from.to_rfc3339(), to.to_rfc3339());
+
+    let rows = stmt.query_map(params![start_str, end_str, machine], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
     })?;
 
+    let mut wpm = HashMap::new();
+    for row in rows {
+        let (device, avg) = row?;
+        wpm.insert(device, avg);
+    }
+
+    Ok(wpm)
+}
+
+/// Sessions in the range. With a device filter, only sessions that device
+/// typed in are returned, and keystrokes and WPM are that device's share.
+pub fn sessions_for_range(
+    conn: &Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    filter: Filter,
+) -> Result<Vec<RawSession>, Error> {
+    // ?3 goes unused without a device, but binding it is harmless
+    let sql = match filter.device {
+        None => {
+            "SELECT id, start_time, end_time, keystroke_count, avg_wpm, peak_wpm
+             FROM sessions
+             WHERE julianday(start_time) >= julianday(?1)
+               AND julianday(start_time) < julianday(?2)
+               AND (?4 IS NULL OR machine = ?4)
+             ORDER BY start_time"
+        }
+        Some(_) => {
+            "SELECT s.id, s.start_time, s.end_time, SUM(sd.keystroke_count),
+                    (SELECT SUM(w.wpm_sum) / SUM(w.samples) FROM wpm_stats w
+                     WHERE w.session_id = s.id AND instr(lower(w.device), lower(?3)) > 0),
+                    (SELECT MAX(w.wpm_max) FROM wpm_stats w
+                     WHERE w.session_id = s.id AND instr(lower(w.device), lower(?3)) > 0)
+             FROM sessions s
+             JOIN session_devices sd ON sd.session_id = s.id
+             WHERE julianday(s.start_time) >= julianday(?1)
+               AND julianday(s.start_time) < julianday(?2)
+               AND instr(lower(sd.device), lower(?3)) > 0
+               AND (?4 IS NULL OR s.machine = ?4)
+             GROUP BY s.id
+             ORDER BY s.start_time"
+        }
+    };
+    let mut stmt = conn.prepare(sql)?;
+
+    let (start_str, end_str) = (from.to_rfc3339(), to.to_rfc3339());
+
+    let rows = stmt.query_map(
+        params![start_str, end_str, filter.device, filter.machine],
+        session_from_row,
+    )?;
+
     let mut sessions = Vec::new();
     for row in rows {
         sessions.push(row?);
@@ -127,58 +229,224 @@ pub fn sessions_for_range(
     Ok(sessions)
 }
 
-pub fn wpm_samples_for_range(
+fn session_from_row(row: &rusqlite::Row) -> rusqlite::Result<RawSession> {
+    let start_str: String = row.get(1)?;
+    let end_str: Option<String> = row.get(2)?;
+
+    let start_time = DateTime::parse_from_rfc3339(&start_str)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now());
+
+    let end_time = end_str.and_then(|s| {
+        DateTime::parse_from_rfc3339(&s)
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok()
+    });
+
+    Ok(RawSession {
+        id: row.get(0)?,
+        start_time,
+        end_time,
+        keystroke_count: row.get::<_, i64>(3)? as u64,
+        avg_wpm: row.get(4)?,
+        peak_wpm: row.get(5)?,
+    })
+}
+
+/// Pauses overlapping the range. They cover every keyboard, so only the
+/// machine filter applies.
+pub fn pauses_for_range(
     conn: &Connection,
-    start: NaiveDate,
-    end: NaiveDate,
-) -> Result<Vec<RawWpmSample>, Error> {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    machine: Option<&str>,
+) -> Result<Vec<RawPause>, Error> {
     let mut stmt = conn.prepare(
-        "SELECT timestamp, wpm
-         FROM wpm_samples
-         WHERE date(timestamp) >= ?1 AND date(timestamp) <= ?2
-         ORDER BY timestamp",
+        "SELECT start_time, end_time, reason
+         FROM pauses
+         WHERE julianday(start_time) < julianday(?2)
+           AND (end_time IS NULL OR julianday(end_time) > julianday(?1))
+           AND (?3 IS NULL OR machine = ?3)
+         ORDER BY start_time",
     )?;
 
-    let start_str = start.format("%Y-%m-%d").to_string();
-    let end_str = end.format("%Y-%m-%d").to_string();
+    let parse = |text: String| {
+        DateTime::parse_from_rfc3339(&text)
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok()
+    };
+    let rows = stmt.query_map(
+        params![from.to_rfc3339(), to.to_rfc3339(), machine],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        },
+    )?;
+
+    let mut pauses = Vec::new();
+    for row in rows {
+        let (start, end, reason) = row?;
+        let Some(start_time) = parse(start) else {
+            continue;
+        };
+        pauses.push(RawPause {
+            start_time,
+            end_time: end.and_then(parse),
+            reason,
+        });
+    }
+
+    Ok(pauses)
+}
+
+/// How many WPM samples fell in each 10 WPM bin, rolled up ones included.
+pub fn wpm_bins_for_range(
+    conn: &Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    filter: Filter,
+) -> Result<Vec<WpmBin>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT wpm_bin, SUM(samples)
+         FROM wpm_stats
+         WHERE julianday(timestamp) >= julianday(?1)
+           AND julianday(timestamp) < julianday(?2) AND {ROW_FILTER}
+         GROUP BY wpm_bin
+         ORDER BY wpm_bin"
+    ))?;
+
+    let (start_str, end_str) = (from.to_rfc3339(), to.to_rfc3339());
+
+    let args = params![start_str, end_str, filter.device, filter.machine];
+    let rows = stmt.query_map(args, |row| {
+        Ok(WpmBin {
+            wpm: row.get(0)?,
+            samples: row.get(1)?,
+        })
+    })?;
+
+    let mut bins = Vec::new();
+    for row in rows {
+        bins.push(row?);
+    }
+
+    Ok(bins)
+}
 
-    let rows = stmt.query_map(params![start_str, end_str], |row| {
-        let ts_str: String = row.get(0)?;
-        let timestamp = DateTime::parse_from_rfc3339(&ts_str)
+#[derive(Debug)]
+pub struct HourlyKeyCount {
+    /// UTC instant the recording zone's hour started
+    pub hour: DateTime<Utc>,
+    pub key_code: String,
+    pub count: u64,
+}
+
+/// Hour keys sort as strings (see `storage::hour_key`), so unlike the other
+/// timestamped tables these compare directly. Compacted hours come back as
+/// totals with an empty key code.
+pub fn hourly_key_counts_for_range(
+    conn: &Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    filter: Filter,
+) -> Result<Vec<HourlyKeyCount>, Error> {
+    hour_keyed_counts(
+        conn,
+        &format!(
+            "SELECT hour, key_code, SUM(count)
+             FROM hourly_key_counts
+             WHERE hour >= ?1 AND hour < ?2 AND {ROW_FILTER}
+             GROUP BY hour, key_code
+             UNION ALL
+             SELECT hour, '', SUM(count)
+             FROM hourly_totals
+             WHERE hour >= ?1 AND hour < ?2 AND {ROW_FILTER}
+             GROUP BY hour
+             ORDER BY 1"
+        ),
+        from,
+        to,
+        filter,
+    )
+}
+
+/// Compacted per-key counts, each at the first hour of its part of the day.
+pub fn period_key_counts_for_range(
+    conn: &Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    filter: Filter,
+) -> Result<Vec<HourlyKeyCount>, Error> {
+    hour_keyed_counts(
+        conn,
+        &format!(
+            "SELECT hour, key_code, SUM(count)
+             FROM period_key_counts
+             WHERE hour >= ?1 AND hour < ?2 AND {ROW_FILTER}
+             GROUP BY hour, key_code
+             ORDER BY hour"
+        ),
+        from,
+        to,
+        filter,
+    )
+}
+
+fn hour_keyed_counts(
+    conn: &Connection,
+    sql: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    filter: Filter,
+) -> Result<Vec<HourlyKeyCount>, Error> {
+    let mut stmt = conn.prepare(sql)?;
+
+    let (start_str, end_str) = (hour_key(from), hour_key(to));
+
+    let args = params![start_str, end_str, filter.device, filter.machine];
+    let rows = stmt.query_map(args, |row| {
+        let hour_str: String = row.get(0)?;
+        let hour = DateTime::parse_from_rfc3339(&hour_str)
             .map(|dt| dt.with_timezone(&Utc))
             .unwrap_or_else(|_| Utc::now());
 
-        Ok(RawWpmSample {
-            timestamp,
-            wpm: row.get(1)?,
+        Ok(HourlyKeyCount {
+            hour,
+            key_code: row.get(1)?,
+            count: row.get(2)?,
         })
     })?;
 
-    let mut samples = Vec::new();
+    let mut counts = Vec::new();
     for row in rows {
-        samples.push(row?);
+        counts.push(row?);
     }
 
-    Ok(samples)
+    Ok(counts)
 }
 
 pub fn shortcuts_for_range(
     conn: &Connection,
     start: NaiveDate,
     end: NaiveDate,
+    filter: Filter,
 ) -> Result<Vec<(String, u64)>, Error> {
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         "SELECT combo, SUM(count) as total
          FROM shortcut_counts
-         WHERE date >= ?1 AND date <= ?2
+         WHERE date >= ?1 AND date <= ?2 AND {ROW_FILTER}
          GROUP BY combo
-         ORDER BY total DESC",
-    )?;
+         ORDER BY total DESC"
+    ))?;
 
     let start_str = start.format("%Y-%m-%d").to_string();
     let end_str = end.format("%Y-%m-%d").to_string();
 
-    let rows = stmt.query_map(params![start_str, end_str], |row| {
+    let args = params![start_str, end_str, filter.device, filter.machine];
+    let rows = stmt.query_map(args, |row| {
         Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
     })?;
 
@@ -190,27 +458,190 @@ pub fn shortcuts_for_range(
     Ok(shortcuts)
 }
 
-pub fn peak_wpm_session_for_range(
+/// ((first, second), count), and the same with a third key for trigrams.
+pub type BigramCount = ((String, String), u64);
+pub type TrigramCount = ((String, String, String), u64);
+
+pub fn bigram_counts_for_range(
     conn: &Connection,
     start: NaiveDate,
     end: NaiveDate,
-) -> Result<Option<(f64, DateTime<Utc>)>, Error> {
+    filter: Filter,
+) -> Result<Vec<BigramCount>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT first, second, SUM(count) as total
+         FROM bigram_counts
+         WHERE date >= ?1 AND date <= ?2 AND {ROW_FILTER}
+         GROUP BY first, second
+         ORDER BY total DESC"
+    ))?;
+
     let start_str = start.format("%Y-%m-%d").to_string();
     let end_str = end.format("%Y-%m-%d").to_string();
 
+    let args = params![start_str, end_str, filter.device, filter.machine];
+    let rows = stmt.query_map(args, |row| {
+        Ok((
+            (row.get::<_, String>(0)?, row.get::<_, String>(1)?),
+            row.get::<_, u64>(2)?,
+        ))
+    })?;
+
+    let mut bigrams = Vec::new();
+    for row in rows {
+        bigrams.push(row?);
+    }
+
+    Ok(bigrams)
+}
+
+pub fn trigram_counts_for_range(
+    conn: &Connection,
+    start: NaiveDate,
+    end: NaiveDate,
+    filter: Filter,
+) -> Result<Vec<TrigramCount>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT first, second, third, SUM(count) as total
+         FROM trigram_counts
+         WHERE date >= ?1 AND date <= ?2 AND {ROW_FILTER}
+         GROUP BY first, second, third
+         ORDER BY total DESC"
+    ))?;
+
+    let start_str = start.format("%Y-%m-%d").to_string();
+    let end_str = end.format("%Y-%m-%d").to_string();
+
+    let args = params![start_str, end_str, filter.device, filter.machine];
+    let rows = stmt.query_map(args, |row| {
+        Ok((
+            (
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ),
+            row.get::<_, u64>(3)?,
+        ))
+    })?;
+
+    let mut trigrams = Vec::new();
+    for row in rows {
+        trigrams.push(row?);
+    }
+
+    Ok(trigrams)
+}
+
+/// Histogram buckets as (bucket lower bound in ms, count).
+pub type Histogram = Vec<(u32, u64)>;
+
+pub fn dwell_histograms_for_range(
+    conn: &Connection,
+    start: NaiveDate,
+    end: NaiveDate,
+    filter: Filter,
+) -> Result<HashMap<String, Histogram>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT key_code, bucket_ms, SUM(count)
+         FROM dwell_times
+         WHERE date >= ?1 AND date <= ?2 AND {ROW_FILTER}
+         GROUP BY key_code, bucket_ms"
+    ))?;
+
+    let start_str = start.format("%Y-%m-%d").to_string();
+    let end_str = end.format("%Y-%m-%d").to_string();
+
+    let args = params![start_str, end_str, filter.device, filter.machine];
+    let rows = stmt.query_map(args, |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, u32>(1)?,
+            row.get::<_, u64>(2)?,
+        ))
+    })?;
+
+    let mut histograms: HashMap<String, Histogram> = HashMap::new();
+    for row in rows {
+        let (key, bucket, count) = row?;
+        histograms.entry(key).or_default().push((bucket, count));
+    }
+
+    Ok(histograms)
+}
+
+pub fn flight_histograms_for_range(
+    conn: &Connection,
+    start: NaiveDate,
+    end: NaiveDate,
+    filter: Filter,
+) -> Result<HashMap<(String, String), Histogram>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT from_key, to_key, bucket_ms, SUM(count)
+         FROM flight_times
+         WHERE date >= ?1 AND date <= ?2 AND {ROW_FILTER}
+         GROUP BY from_key, to_key, bucket_ms"
+    ))?;
+
+    let start_str = start.format("%Y-%m-%d").to_string();
+    let end_str = end.format("%Y-%m-%d").to_string();
+
+    let args = params![start_str, end_str, filter.device, filter.machine];
+    let rows = stmt.query_map(args, |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, u32>(2)?,
+            row.get::<_, u64>(3)?,
+        ))
+    })?;
+
+    let mut histograms: HashMap<(String, String), Histogram> = HashMap::new();
+    for row in rows {
+        let (from, to, bucket, count) = row?;
+        histograms.entry((from, to)).or_default().push((bucket, count));
+    }
+
+    Ok(histograms)
+}
+
+/// Highest WPM in the range. Per-device peaks come from the samples, since
+/// session peaks mix every device typed on during the session.
+pub fn peak_wpm_session_for_range(
+    conn: &Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    filter: Filter,
+) -> Result<Option<(f64, DateTime<Utc>)>, Error> {
+    let (start_str, end_str) = (from.to_rfc3339(), to.to_rfc3339());
+
+    let sql = match filter.device {
+        None => "SELECT peak_wpm, start_time
+             FROM sessions
+             WHERE julianday(start_time) >= julianday(?1)
+               AND julianday(start_time) < julianday(?2)
+               AND peak_wpm IS NOT NULL
+               AND (?4 IS NULL OR machine = ?4)
+             ORDER BY peak_wpm DESC
+             LIMIT 1"
+            .to_string(),
+        Some(_) => format!(
+            "SELECT wpm_max, max_at
+             FROM wpm_stats
+             WHERE julianday(timestamp) >= julianday(?1)
+               AND julianday(timestamp) < julianday(?2)
+               AND {ROW_FILTER}
+             ORDER BY wpm_max DESC
+             LIMIT 1"
+        ),
+    };
+
+    let peak_from_row =
+        |row: &rusqlite::Row| -> rusqlite::Result<(f64, String)> { Ok((row.get(0)?, row.get(1)?)) };
+
     let result = conn.query_row(
-        "SELECT peak_wpm, start_time
-         FROM sessions
-         WHERE date(start_time) >= ?1 AND date(start_time) <= ?2
-           AND peak_wpm IS NOT NULL
-         ORDER BY peak_wpm DESC
-         LIMIT 1",
-        params![start_str, end_str],
-        |row| {
-            let wpm: f64 = row.get(0)?;
-            let ts_str: String = row.get(1)?;
-            Ok((wpm, ts_str))
-        },
+        &sql,
+        params![start_str, end_str, filter.device, filter.machine],
+        peak_from_row,
     );
 
     match result {
@@ -224,3 +655,35 @@ pub fn peak_wpm_session_for_range(
         Err(e) => Err(e.into()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    #[test]
+    fn device_filter_is_a_plain_substring() {
+        let storage = Storage::in_memory().unwrap();
+        storage
+            .connection()
+            .execute_batch(
+                "INSERT INTO key_counts (key_code, date, device, count) VALUES
+                    ('a', '2025-02-03', '04d9:a0f8:kb_1', 5),
+                    ('a', '2025-02-03', '04d9:a0f8:kbx1', 7),
+                    ('a', '2025-02-03', '046d:c52b:50% Board', 11);",
+            )
+            .unwrap();
+
+        let total = |device| {
+            let filter = Filter {
+                device: Some(device),
+                machine: None,
+            };
+            all_time_keystrokes(storage.connection(), filter).unwrap()
+        };
+        // `_` and `%` are not wildcards
+        assert_eq!(total("KB_1"), 5);
+        assert_eq!(total("50%"), 11);
+        assert_eq!(total("kb"), 12);
+    }
+}
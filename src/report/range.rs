@@ -0,0 +1,449 @@
+use crate::timezone::Zone;
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which days a report covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeSpec {
+    /// the Monday to Sunday week containing this date
+    Week(NaiveDate),
+    Day(NaiveDate),
+    /// the calendar month containing this date
+    Month(NaiveDate),
+    Year(i32),
+    /// inclusive at both ends
+    Custom { from: NaiveDate, to: NaiveDate },
+    /// a span of time ending today
+    Last(Span),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RangeKind {
+    Week,
+    Day,
+    Month,
+    Year,
+    Custom,
+}
+
+/// An inclusive span of days a report covers.
+#[derive(Debug, Clone, Serialize)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub label: String,
+    pub kind: RangeKind,
+}
+
+/// How finely a chart over the range is bucketed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrendStep {
+    Day,
+    Week,
+    Month,
+}
+
+/// Today in the configured timezone.
+pub fn today() -> NaiveDate {
+    Zone::configured().today()
+}
+
+impl RangeSpec {
+    pub fn resolve(self) -> DateRange {
+        match self {
+            RangeSpec::Week(date) => {
+                let start = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+                let end = start + Duration::days(6);
+                let label = format!(
+                    "Week of {} - {}",
+                    start.format("%b %d"),
+                    end.format("%b %d, %Y")
+                );
+                DateRange::new(start, end, label, RangeKind::Week)
+            }
+            RangeSpec::Day(date) => DateRange::new(
+                date,
+                date,
+                date.format("%A, %b %d, %Y").to_string(),
+                RangeKind::Day,
+            ),
+            RangeSpec::Month(date) => {
+                let start = date.with_day(1).unwrap_or(date);
+                let end = start + Months::new(1) - Duration::days(1);
+                DateRange::new(
+                    start,
+                    end,
+                    start.format("%B %Y").to_string(),
+                    RangeKind::Month,
+                )
+            }
+            RangeSpec::Year(year) => {
+                let start = NaiveDate::from_ymd_opt(year, 1, 1).unwrap_or(NaiveDate::MIN);
+                let end = NaiveDate::from_ymd_opt(year, 12, 31).unwrap_or(NaiveDate::MAX);
+                DateRange::new(start, end, year.to_string(), RangeKind::Year)
+            }
+            RangeSpec::Custom { from, to } => {
+                DateRange::new(from, to, span_label(from, to), RangeKind::Custom)
+            }
+            RangeSpec::Last(span) => {
+                let end = today();
+                let start = span.before(end) + Duration::days(1);
+                let label = format!("Last {span}: {}", span_label(start, end));
+                DateRange::new(start, end, label, RangeKind::Custom)
+            }
+        }
+    }
+
+    /// The range a report is compared against: the previous week, day,
+    /// month or year, or the same number of days just before a custom range.
+    pub fn previous(self) -> DateRange {
+        match self {
+            RangeSpec::Week(date) => RangeSpec::Week(date - Duration::days(7)).resolve(),
+            RangeSpec::Day(date) => RangeSpec::Day(date - Duration::days(1)).resolve(),
+            RangeSpec::Month(date) => {
+                let start = date.with_day(1).unwrap_or(date);
+                RangeSpec::Month(start - Months::new(1)).resolve()
+            }
+            RangeSpec::Year(year) => RangeSpec::Year(year - 1).resolve(),
+            RangeSpec::Custom { .. } | RangeSpec::Last(_) => {
+                let current = self.resolve();
+                let days = Duration::days(current.days() as i64);
+                let (from, to) = (current.start - days, current.end - days);
+                RangeSpec::Custom { from, to }.resolve()
+            }
+        }
+    }
+}
+
+/// The ways a range can be picked, at most one of which is set, as given on
+/// the command line or in a dashboard query.
+#[derive(Debug, Clone, Default)]
+pub struct RangeChoice {
+    pub week: Option<NaiveDate>,
+    pub day: Option<NaiveDate>,
+    pub month: Option<NaiveDate>,
+    pub year: Option<i32>,
+    pub from: Option<NaiveDate>,
+    /// only with `from`
+    pub to: Option<NaiveDate>,
+    pub last: Option<Span>,
+}
+
+impl RangeChoice {
+    pub fn is_empty(&self) -> bool {
+        self.week.is_none()
+            && self.day.is_none()
+            && self.month.is_none()
+            && self.year.is_none()
+            && self.from.is_none()
+            && self.to.is_none()
+            && self.last.is_none()
+    }
+
+    /// The chosen range, or the current week if none was.
+    pub fn into_spec(self) -> Result<RangeSpec, String> {
+        if self.to.is_some() && self.from.is_none() {
+            return Err("an end date needs a start date".to_string());
+        }
+        let spec = if let Some(date) = self.day {
+            RangeSpec::Day(date)
+        } else if let Some(date) = self.month {
+            RangeSpec::Month(date)
+        } else if let Some(year) = self.year {
+            RangeSpec::Year(year)
+        } else if let Some(from) = self.from {
+            let to = self.to.unwrap_or_else(today);
+            if to < from {
+                return Err(format!("end date {to} is before start date {from}"));
+            }
+            RangeSpec::Custom { from, to }
+        } else if let Some(span) = self.last {
+            RangeSpec::Last(span)
+        } else {
+            RangeSpec::Week(self.week.unwrap_or_else(today))
+        };
+        Ok(spec)
+    }
+}
+
+impl Default for RangeSpec {
+    fn default() -> Self {
+        RangeSpec::Week(today())
+    }
+}
+
+impl DateRange {
+    fn new(start: NaiveDate, end: NaiveDate, label: String, kind: RangeKind) -> Self {
+        Self {
+            start,
+            end,
+            label,
+            kind,
+        }
+    }
+
+    pub fn days(&self) -> u32 {
+        ((self.end - self.start).num_days() + 1).max(0) as u32
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+
+    /// "week", "month", ... for use in sentences like "this week".
+    pub fn noun(&self) -> &'static str {
+        match self.kind {
+            RangeKind::Week => "week",
+            RangeKind::Day => "day",
+            RangeKind::Month => "month",
+            RangeKind::Year => "year",
+            RangeKind::Custom => "period",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self.kind {
+            RangeKind::Week => "Weekly Report",
+            RangeKind::Day => "Daily Report",
+            RangeKind::Month => "Monthly Report",
+            RangeKind::Year => "Yearly Report",
+            RangeKind::Custom => "Report",
+        }
+    }
+
+    /// What the previous range is called, as in "+5% from last week".
+    pub fn previous_name(&self) -> String {
+        match self.kind {
+            RangeKind::Week => "last week".to_string(),
+            RangeKind::Day => "the day before".to_string(),
+            RangeKind::Month => "last month".to_string(),
+            RangeKind::Year => "last year".to_string(),
+            RangeKind::Custom => format!("the previous {} days", self.days()),
+        }
+    }
+
+    /// Filename-safe name for the range, e.g. `week-2025-W06`.
+    pub fn slug(&self) -> String {
+        match self.kind {
+            RangeKind::Week => format!("week-{}", self.start.format("%G-W%V")),
+            RangeKind::Day => format!("day-{}", self.start),
+            RangeKind::Month => format!("month-{}", self.start.format("%Y-%m")),
+            RangeKind::Year => format!("year-{}", self.start.format("%Y")),
+            RangeKind::Custom => format!("range-{}-to-{}", self.start, self.end),
+        }
+    }
+
+    /// Keeps trend charts to a readable number of points.
+    pub fn trend_step(&self) -> TrendStep {
+        match self.days() {
+            0..=31 => TrendStep::Day,
+            32..=183 => TrendStep::Week,
+            _ => TrendStep::Month,
+        }
+    }
+}
+
+impl TrendStep {
+    /// Start of the bucket after the one starting at `date`.
+    pub fn next(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            TrendStep::Day => date + Duration::days(1),
+            TrendStep::Week => date + Duration::days(7),
+            TrendStep::Month => date + Months::new(1),
+        }
+    }
+
+    /// Start of the bucket holding the start of a range.
+    pub fn first(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            TrendStep::Day | TrendStep::Week => start,
+            TrendStep::Month => start.with_day(1).unwrap_or(start),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TrendStep::Day => "Daily",
+            TrendStep::Week => "Weekly",
+            TrendStep::Month => "Monthly",
+        }
+    }
+}
+
+fn span_label(start: NaiveDate, end: NaiveDate) -> String {
+    if start == end {
+        end.format("%b %d, %Y").to_string()
+    } else if start.year() == end.year() {
+        format!("{} - {}", start.format("%b %d"), end.format("%b %d, %Y"))
+    } else {
+        format!("{} - {}", start.format("%b %d, %Y"), end.format("%b %d, %Y"))
+    }
+}
+
+/// A relative length of time such as `30d`, `4w`, `6m` or `1y`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    count: u32,
+    unit: SpanUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpanUnit {
+    Days,
+    Weeks,
+    Months,
+    Years,
+}
+
+impl Span {
+    fn before(self, date: NaiveDate) -> NaiveDate {
+        match self.unit {
+            SpanUnit::Days => date - Duration::days(self.count as i64),
+            SpanUnit::Weeks => date - Duration::days(self.count as i64 * 7),
+            SpanUnit::Months => date - Months::new(self.count),
+            SpanUnit::Years => date - Months::new(self.count * 12),
+        }
+    }
+}
+
+impl FromStr for Span {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid span '{s}', expected a count and unit like 30d, 4w, 6m or 1y");
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(err)?;
+        let count: u32 = s[..split].parse().map_err(|_| err())?;
+        let unit = match &s[split..] {
+            "d" => SpanUnit::Days,
+            "w" => SpanUnit::Weeks,
+            "m" => SpanUnit::Months,
+            "y" => SpanUnit::Years,
+            _ => return Err(err()),
+        };
+        if count == 0 || count > 100 * 366 {
+            return Err(err());
+        }
+        Ok(Self { count, unit })
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.unit {
+            SpanUnit::Days => "day",
+            SpanUnit::Weeks => "week",
+            SpanUnit::Months => "month",
+            SpanUnit::Years => "year",
+        };
+        let plural = if self.count == 1 { "" } else { "s" };
+        write!(f, "{} {unit}{plural}", self.count)
+    }
+}
+
+/// Parses `YYYY-MM-DD`, `today` or `yesterday`.
+pub fn parse_day(s: &str) -> Result<NaiveDate, String> {
+    match s {
+        "today" => Ok(today()),
+        "yesterday" => Ok(today() - Duration::days(1)),
+        _ => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map_err(|_| "invalid date, use YYYY-MM-DD, today or yesterday".to_string()),
+    }
+}
+
+/// Parses an ISO week such as `2025-W06` to its Monday.
+pub fn parse_week(s: &str) -> Result<NaiveDate, String> {
+    let err = || "invalid week format, use YYYY-Www (e.g. 2025-W06)".to_string();
+    let (year, week) = s.split_once("-W").ok_or_else(err)?;
+    let year: i32 = year.parse().map_err(|_| err())?;
+    let week: u32 = week.parse().map_err(|_| err())?;
+    NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).ok_or_else(err)
+}
+
+/// Parses `YYYY-MM` to the first of the month.
+pub fn parse_month(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d")
+        .map_err(|_| "invalid month, use YYYY-MM (e.g. 2025-02)".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        parse_day(s).unwrap()
+    }
+
+    #[test]
+    fn ranges_resolve_and_step_back() {
+        let week = RangeSpec::Week(date("2026-10-17")).resolve();
+        assert_eq!((week.start, week.end), (date("2026-10-12"), date("2026-10-18")));
+        assert_eq!(week.slug(), "week-2026-W42");
+
+        let month = RangeSpec::Month(parse_month("2024-03").unwrap());
+        assert_eq!(month.resolve().end, date("2024-03-31"));
+        let prev = month.previous();
+        assert_eq!((prev.start, prev.end), (date("2024-02-01"), date("2024-02-29")));
+        assert_eq!(prev.label, "February 2024");
+
+        let custom = RangeSpec::Custom {
+            from: date("2026-01-01"),
+            to: date("2026-01-10"),
+        };
+        assert_eq!(custom.resolve().days(), 10);
+        let prev = custom.previous();
+        assert_eq!((prev.start, prev.end), (date("2025-12-22"), date("2025-12-31")));
+
+        assert_eq!(RangeSpec::Year(2025).resolve().trend_step(), TrendStep::Month);
+        assert_eq!(parse_week("2025-W06"), Ok(date("2025-02-03")));
+        assert!(parse_week("2025-06").is_err());
+    }
+
+    #[test]
+    fn spans_parse_and_count_back() {
+        let span: Span = "30d".parse().unwrap();
+        assert_eq!(span.to_string(), "30 days");
+        assert_eq!(span.before(date("2026-10-17")), date("2026-09-17"));
+
+        let span: Span = "1m".parse().unwrap();
+        assert_eq!(span.to_string(), "1 month");
+        assert_eq!(span.before(date("2026-03-31")), date("2026-02-28"));
+
+        for bad in ["", "30", "d", "0d", "3x", "-2w"] {
+            assert!(bad.parse::<Span>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn choices_pick_one_range() {
+        let choice = RangeChoice {
+            from: Some(date("2026-01-01")),
+            to: Some(date("2026-01-10")),
+            ..Default::default()
+        };
+        assert!(!choice.is_empty());
+        assert_eq!(
+            choice.into_spec(),
+            Ok(RangeSpec::Custom {
+                from: date("2026-01-01"),
+                to: date("2026-01-10"),
+            })
+        );
+
+        let backwards = RangeChoice {
+            from: Some(date("2026-01-10")),
+            to: Some(date("2026-01-01")),
+            ..Default::default()
+        };
+        assert!(backwards.into_spec().is_err());
+        let open_ended = RangeChoice {
+            to: Some(date("2026-01-01")),
+            ..Default::default()
+        };
+        assert!(open_ended.into_spec().is_err());
+        assert!(RangeChoice::default().is_empty());
+    }
+}
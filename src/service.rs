@@ -0,0 +1,177 @@
+//! Running the daemon as a `systemd --user` service.
+//!
+//! `keyheat service install` writes a unit that runs `keyheat run` in the
+//! foreground. systemd supervises it instead of the PID file: the daemon
+//! reports readiness over `$NOTIFY_SOCKET`, and its stderr goes to the
+//! journal.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+pub const UNIT_NAME: &str = "keyheat.service";
+
+pub fn unit_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("systemd")
+        .join("user")
+        .join(UNIT_NAME)
+}
+
+pub fn is_installed() -> bool {
+    unit_path().exists()
+}
+
+/// The unit file for running `exe` as the daemon.
+fn unit_contents(exe: &Path) -> String {
+    format!(
+        "[Unit]
+Description=KeyHeat keyboard analytics
+Documentation=https://github.com/0xSaiNova/keyheat
+
+[Service]
+Type=notify
+ExecStart={} run
+Restart=on-failure
+RestartSec=5
+SyslogIdentifier=keyheat
+
+[Install]
+WantedBy=default.target
+",
+        quote_exec_arg(&exe.to_string_lossy())
+    )
+}
+
+/// Quotes a path for `ExecStart=`, where `%` starts a specifier and
+/// whitespace splits arguments.
+fn quote_exec_arg(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    if escaped.contains(char::is_whitespace) {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .context("failed to run systemctl, is systemd running?")?;
+    if !status.success() {
+        anyhow::bail!("systemctl --user {} failed ({status})", args.join(" "));
+    }
+    Ok(())
+}
+
+/// Writes the unit, then enables and starts it.
+#[cfg(target_os = "linux")]
+pub fn install() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("failed to get executable path")?;
+    let path = unit_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    std::fs::write(&path, unit_contents(&exe))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", UNIT_NAME])?;
+    Ok(path)
+}
+
+/// Stops and disables the service and removes its unit.
+#[cfg(target_os = "linux")]
+pub fn uninstall() -> Result<PathBuf> {
+    let path = unit_path();
+    if !path.exists() {
+        anyhow::bail!("{UNIT_NAME} is not installed");
+    }
+
+    systemctl(&["disable", "--now", UNIT_NAME])?;
+    std::fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))?;
+    systemctl(&["daemon-reload"])?;
+    Ok(path)
+}
+
+/// Starts the installed service.
+#[cfg(target_os = "linux")]
+pub fn start() -> Result<()> {
+    systemctl(&["start", UNIT_NAME])
+}
+
+/// Shows what systemd thinks of the service.
+#[cfg(target_os = "linux")]
+pub fn print_status() -> Result<()> {
+    // exits non-zero for a stopped service, which isn't an error here
+    std::process::Command::new("systemctl")
+        .args(["--user", "--no-pager", "status", UNIT_NAME])
+        .status()
+        .context("failed to run systemctl, is systemd running?")?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn install() -> Result<PathBuf> {
+    anyhow::bail!("systemd services are only supported on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn uninstall() -> Result<PathBuf> {
+    anyhow::bail!("systemd services are only supported on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start() -> Result<()> {
+    anyhow::bail!("systemd services are only supported on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn print_status() -> Result<()> {
+    anyhow::bail!("systemd services are only supported on Linux")
+}
+
+/// Tells systemd about the daemon's state, e.g. `READY=1`. Does nothing
+/// unless started by systemd with `Type=notify`.
+#[cfg(target_os = "linux")]
+pub fn notify(state: &str) {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let path = path.to_string_lossy();
+    // a leading @ means the abstract namespace
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(path.as_ref()),
+    };
+    let sent = addr.and_then(|addr| UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr));
+    if let Err(e) = sent {
+        eprintln!("failed to notify systemd: {e}");
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn notify(_state: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_runs_the_daemon_in_the_foreground() {
+        let unit = unit_contents(Path::new("/home/me/my apps/keyheat"));
+        assert!(unit.contains("Type=notify\n"));
+        assert!(unit.contains("ExecStart=\"/home/me/my apps/keyheat\" run\n"));
+        assert_eq!(quote_exec_arg("/opt/100%/keyheat"), "/opt/100%%/keyheat");
+    }
+}
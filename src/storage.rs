@@ -1,13 +1,81 @@
-use crate::aggregator::WpmSample;
+mod compact;
+mod import;
+mod recovery;
+
+use crate::aggregator::{FlushBatch, WpmSample};
 use crate::error::Error;
-use crate::keycode::KeyCode;
-use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection};
+use crate::keycode::{DeviceId, KeyCode};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::hash::{BuildHasher, RandomState};
+use std::path::{Path, PathBuf};
+
+pub use compact::{Compaction, Cutoffs};
+pub use recovery::RecoveredSession;
+
+const SCHEMA_VERSION: i32 = 15;
 
-const SCHEMA_VERSION: i32 = 3;
+/// How long a connection waits for another one's write lock.
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Tables of counts keyed by some columns plus device and machine, with
+/// the key columns and their types.
+pub const COUNT_TABLES: [(&str, &[(&str, &str)]); 9] = [
+    ("key_counts", &[("key_code", "TEXT"), ("date", "TEXT")]),
+    ("shortcut_counts", &[("combo", "TEXT"), ("date", "TEXT")]),
+    (
+        "hourly_key_counts",
+        &[("key_code", "TEXT"), ("hour", "TEXT")],
+    ),
+    (
+        "dwell_times",
+        &[
+            ("key_code", "TEXT"),
+            ("date", "TEXT"),
+            ("bucket_ms", "INTEGER"),
+        ],
+    ),
+    (
+        "flight_times",
+        &[
+            ("from_key", "TEXT"),
+            ("to_key", "TEXT"),
+            ("date", "TEXT"),
+            ("bucket_ms", "INTEGER"),
+        ],
+    ),
+    (
+        "bigram_counts",
+        &[("first", "TEXT"), ("second", "TEXT"), ("date", "TEXT")],
+    ),
+    (
+        "trigram_counts",
+        &[
+            ("first", "TEXT"),
+            ("second", "TEXT"),
+            ("third", "TEXT"),
+            ("date", "TEXT"),
+        ],
+    ),
+    ("hourly_totals", &[("hour", "TEXT")]),
+    (
+        "period_key_counts",
+        &[("key_code", "TEXT"), ("hour", "TEXT")],
+    ),
+];
+
+/// A machine whose data is in this database.
+#[derive(Debug, Clone)]
+pub struct Machine {
+    /// value of the `machine` column: empty for this machine, otherwise the
+    /// id the other machine generated for itself
+    pub id: String,
+    pub name: String,
+    /// when its data was last imported, None for this machine
+    pub imported_at: Option<DateTime<Utc>>,
+}
 
 pub struct Storage {
     conn: Connection,
@@ -29,7 +97,19 @@ impl Storage {
             }
         }
 
-        let conn = Connection::open(&path)?;
+        let mut storage = Self::open_at(&path)?;
+        storage.ensure_machine_id()?;
+
+        Ok(storage)
+    }
+
+    /// Opens and migrates the database at `path`, e.g. a copy of another
+    /// machine's database being imported.
+    pub fn open_at(path: &Path) -> Result<Self, Error> {
+        let conn = Connection::open(path)?;
+        // the daemon, imports and compaction share the file, so wait out
+        // each other's write transactions instead of failing
+        conn.busy_timeout(BUSY_TIMEOUT)?;
         conn.pragma_update(None, "journal_mode", "WAL")?;
         conn.pragma_update(None, "foreign_keys", "ON")?;
 
@@ -39,6 +119,16 @@ impl Storage {
         Ok(storage)
     }
 
+    /// A fresh, fully migrated database that lives only as long as this.
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self, Error> {
+        let mut storage = Self {
+            conn: Connection::open_in_memory()?,
+        };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
     fn migrate(&mut self) -> Result<(), Error> {
         let version: i32 = self
             .conn
@@ -74,6 +164,54 @@ impl Storage {
             self.migrate_to_v3()?;
         }
 
+        if version < 4 {
+            self.migrate_to_v4()?;
+        }
+
+        if version < 5 {
+            self.migrate_to_v5()?;
+        }
+
+        if version < 6 {
+            self.migrate_to_v6()?;
+        }
+
+        if version < 7 {
+            self.migrate_to_v7()?;
+        }
+
+        if version < 8 {
+            self.migrate_to_v8()?;
+        }
+
+        if version < 9 {
+            self.migrate_to_v9()?;
+        }
+
+        if version < 10 {
+            self.migrate_to_v10()?;
+        }
+
+        if version < 11 {
+            self.migrate_to_v11()?;
+        }
+
+        if version < 12 {
+            self.migrate_to_v12()?;
+        }
+
+        if version < 13 {
+            self.migrate_to_v13()?;
+        }
+
+        if version < 14 {
+            self.migrate_to_v14()?;
+        }
+
+        if version < 15 {
+            self.migrate_to_v15()?;
+        }
+
         self.conn
             .pragma_update(None, "user_version", SCHEMA_VERSION)?;
 
@@ -200,6 +338,337 @@ impl Storage {
         Ok(())
     }
 
+    fn migrate_to_v4(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v4: adding per-device attribution...");
+
+        // device is part of the primary key, so the count tables are rebuilt.
+        // existing rows predate device tracking and keep an empty device.
+        let tx = self.conn.transaction()?;
+        for (table, key_column) in [("key_counts", "key_code"), ("shortcut_counts", "combo")] {
+            tx.execute_batch(&format!(
+                "CREATE TABLE {table}_new (
+                    {key_column} TEXT NOT NULL,
+                    date TEXT NOT NULL,
+                    device TEXT NOT NULL DEFAULT '',
+                    count INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY ({key_column}, date, device)
+                );
+                INSERT INTO {table}_new ({key_column}, date, count)
+                    SELECT {key_column}, date, count FROM {table};
+                DROP TABLE {table};
+                ALTER TABLE {table}_new RENAME TO {table};"
+            ))?;
+        }
+
+        tx.execute(
+            "ALTER TABLE wpm_samples ADD COLUMN device TEXT NOT NULL DEFAULT ''",
+            [],
+        )?;
+
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS session_devices (
+                session_id INTEGER NOT NULL,
+                device TEXT NOT NULL,
+                keystroke_count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (session_id, device),
+                FOREIGN KEY (session_id) REFERENCES sessions(id)
+            )",
+            [],
+        )?;
+        tx.commit()?;
+
+        eprintln!("v4 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v5(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v5: adding dwell and flight time histograms...");
+
+        // bucket_ms is the lower bound of the bucket, see TIMING_BUCKETS_MS
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS dwell_times (
+                key_code TEXT NOT NULL,
+                date TEXT NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                bucket_ms INTEGER NOT NULL,
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (key_code, date, device, bucket_ms)
+            );
+            CREATE TABLE IF NOT EXISTS flight_times (
+                from_key TEXT NOT NULL,
+                to_key TEXT NOT NULL,
+                date TEXT NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                bucket_ms INTEGER NOT NULL,
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (from_key, to_key, date, device, bucket_ms)
+            );",
+        )?;
+
+        eprintln!("v5 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v6(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v6: adding bigram and trigram counts...");
+
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS bigram_counts (
+                first TEXT NOT NULL,
+                second TEXT NOT NULL,
+                date TEXT NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (first, second, date, device)
+            );
+            CREATE TABLE IF NOT EXISTS trigram_counts (
+                first TEXT NOT NULL,
+                second TEXT NOT NULL,
+                third TEXT NOT NULL,
+                date TEXT NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (first, second, third, date, device)
+            );",
+        )?;
+
+        eprintln!("v6 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v7(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v7: adding metadata table...");
+
+        // timestamps are UTC, but daily tables are keyed by local date, so
+        // remember which timezone those dates are in
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );",
+        )?;
+
+        eprintln!("v7 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v8(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v8: adding hourly key and shortcut counts...");
+
+        // hour is the UTC instant the local hour started, e.g.
+        // 2025-02-03T08:00:00Z, so reports can re-bucket by any timezone.
+        // the daily tables stay as they are for older data and quick totals.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS hourly_key_counts (
+                key_code TEXT NOT NULL,
+                hour TEXT NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (key_code, hour, device)
+            );
+            CREATE INDEX IF NOT EXISTS idx_hourly_key_counts_hour ON hourly_key_counts(hour);
+            CREATE TABLE IF NOT EXISTS hourly_shortcut_counts (
+                combo TEXT NOT NULL,
+                hour TEXT NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (combo, hour, device)
+            );
+            CREATE INDEX IF NOT EXISTS idx_hourly_shortcut_counts_hour ON hourly_shortcut_counts(hour);",
+        )?;
+
+        eprintln!("v8 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v9(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v9: adding machine attribution...");
+
+        // rows merged in from other machines carry that machine's id, local
+        // rows keep an empty machine. machine joins the primary key of the
+        // count tables, so those are rebuilt as in v4.
+        let tx = self.conn.transaction()?;
+        for (table, columns) in COUNT_TABLES {
+            // tables added since are created with a machine column
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+                params![table],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                continue;
+            }
+            let names: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+            let names = names.join(", ");
+            let definitions: String = columns
+                .iter()
+                .map(|(name, kind)| format!("{name} {kind} NOT NULL, "))
+                .collect();
+            tx.execute_batch(&format!(
+                "CREATE TABLE {table}_new (
+                    {definitions}
+                    device TEXT NOT NULL DEFAULT '',
+                    machine TEXT NOT NULL DEFAULT '',
+                    count INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY ({names}, device, machine)
+                );
+                INSERT INTO {table}_new ({names}, device, count)
+                    SELECT {names}, device, count FROM {table};
+                DROP TABLE {table};
+                ALTER TABLE {table}_new RENAME TO {table};"
+            ))?;
+        }
+
+        tx.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_hourly_key_counts_hour ON hourly_key_counts(hour);
+            CREATE INDEX IF NOT EXISTS idx_hourly_shortcut_counts_hour ON hourly_shortcut_counts(hour);
+            ALTER TABLE sessions ADD COLUMN machine TEXT NOT NULL DEFAULT '';
+            ALTER TABLE wpm_samples ADD COLUMN machine TEXT NOT NULL DEFAULT '';
+            CREATE TABLE IF NOT EXISTS machines (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                imported_at TEXT NOT NULL,
+                -- machine whose database the rows were imported from
+                source TEXT NOT NULL
+            );",
+        )?;
+        tx.commit()?;
+
+        eprintln!("v9 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v10(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v10: adding WPM rollups...");
+
+        // old samples get rolled up into one row per 15 minutes, session,
+        // device, machine and 10 WPM bin. every UTC offset is a multiple of
+        // 15 minutes, so local days still split cleanly between slots.
+        // wpm_stats reads both as weighted rows, so reports don't change.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS wpm_rollups (
+                slot TEXT NOT NULL,
+                session_id INTEGER NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                machine TEXT NOT NULL DEFAULT '',
+                wpm_bin INTEGER NOT NULL,
+                samples INTEGER NOT NULL,
+                wpm_sum REAL NOT NULL,
+                wpm_max REAL NOT NULL,
+                max_at TEXT NOT NULL,
+                PRIMARY KEY (slot, session_id, device, machine, wpm_bin),
+                FOREIGN KEY (session_id) REFERENCES sessions(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_wpm_rollups_session ON wpm_rollups(session_id);
+            CREATE VIEW IF NOT EXISTS wpm_stats AS
+                SELECT session_id, timestamp, device, machine,
+                       CAST(wpm / 10 AS INTEGER) * 10 AS wpm_bin,
+                       1 AS samples, wpm AS wpm_sum, wpm AS wpm_max, timestamp AS max_at
+                FROM wpm_samples
+                UNION ALL
+                SELECT session_id, slot, device, machine,
+                       wpm_bin, samples, wpm_sum, wpm_max, max_at
+                FROM wpm_rollups;",
+        )?;
+
+        eprintln!("v10 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v11(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v11: adding session heartbeat...");
+
+        // the daemon bumps this on every keystroke update, so a session a
+        // crash left open can be closed at about the right time
+        self.conn
+            .execute("ALTER TABLE sessions ADD COLUMN updated_at TEXT", [])?;
+
+        eprintln!("v11 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v12(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v12: adding pauses...");
+
+        // nothing is recorded between start_time and end_time, so reports
+        // can tell a pause from not typing
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS pauses (
+                id INTEGER PRIMARY KEY,
+                start_time TEXT NOT NULL,
+                end_time TEXT,
+                -- 'command' or 'chord'
+                reason TEXT NOT NULL,
+                machine TEXT NOT NULL DEFAULT ''
+            );
+            CREATE INDEX IF NOT EXISTS idx_pauses_start ON pauses(start_time);",
+        )?;
+
+        eprintln!("v12 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v13(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v13: adding timezone history...");
+
+        // daily rows can't be re-bucketed, so remember which zone each
+        // stretch of dates was counted in. The zone kept in meta so far
+        // covers everything before.
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS timezones (
+                -- first local date counted in this zone
+                since TEXT PRIMARY KEY,
+                name TEXT NOT NULL
+            );
+            INSERT OR IGNORE INTO timezones (since, name)
+                SELECT '0000-01-01', value FROM meta WHERE key = 'timezone';
+            DELETE FROM meta WHERE key = 'timezone';",
+        )?;
+
+        eprintln!("v13 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v14(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v14: adding hourly key count rollups...");
+
+        // compaction folds old hourly_key_counts rows into per-hour totals,
+        // which the activity grid needs, and per-key counts for each part
+        // of the day, keyed by the first hour of that part. see compact.rs
+        self.conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS hourly_totals (
+                hour TEXT NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                machine TEXT NOT NULL DEFAULT '',
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (hour, device, machine)
+            );
+            CREATE TABLE IF NOT EXISTS period_key_counts (
+                key_code TEXT NOT NULL,
+                hour TEXT NOT NULL,
+                device TEXT NOT NULL DEFAULT '',
+                machine TEXT NOT NULL DEFAULT '',
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (key_code, hour, device, machine)
+            );
+            CREATE INDEX IF NOT EXISTS idx_period_key_counts_hour ON period_key_counts(hour);",
+        )?;
+
+        eprintln!("v14 migration complete");
+        Ok(())
+    }
+
+    fn migrate_to_v15(&mut self) -> Result<(), Error> {
+        eprintln!("migrating to v15: dropping hourly shortcut counts...");
+
+        // nothing ever read them, reports use the daily shortcut_counts
+        self.conn
+            .execute_batch("DROP TABLE IF EXISTS hourly_shortcut_counts;")?;
+
+        eprintln!("v15 migration complete");
+        Ok(())
+    }
+
     fn db_path() -> Result<PathBuf, Error> {
         let base_dir = dirs::data_local_dir()
             .ok_or_else(|| Error::Database(rusqlite::Error::InvalidPath(
@@ -219,7 +688,7 @@ impl Storage {
             let mut stmt = tx.prepare_cached(
                 "INSERT INTO key_counts (key_code, date, count)
                  VALUES (?1, ?2, ?3)
-                 ON CONFLICT (key_code, date)
+                 ON CONFLICT (key_code, date, device, machine)
                  DO UPDATE SET count = count + excluded.count",
             )?;
 
@@ -239,11 +708,19 @@ impl Storage {
         Ok(self.conn.last_insert_rowid())
     }
 
-    pub fn update_session_keystrokes(&mut self, session_id: i64, count: u64) -> Result<(), Error> {
-        self.conn.execute(
-            "UPDATE sessions SET keystroke_count = ?1 WHERE id = ?2",
-            params![count, session_id],
+    pub fn update_session_keystrokes(
+        &mut self,
+        session_id: i64,
+        count: u64,
+        device_counts: &HashMap<Option<DeviceId>, u64>,
+    ) -> Result<(), Error> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "UPDATE sessions SET keystroke_count = ?1, updated_at = ?2 WHERE id = ?3",
+            params![count, Utc::now().to_rfc3339(), session_id],
         )?;
+        write_session_devices(&tx, session_id, device_counts)?;
+        tx.commit()?;
         Ok(())
     }
 
@@ -252,13 +729,33 @@ impl Storage {
         session_id: i64,
         end_time: DateTime<Utc>,
         keystroke_count: u64,
+        device_counts: &HashMap<Option<DeviceId>, u64>,
         avg_wpm: Option<f64>,
         peak_wpm: Option<f64>,
     ) -> Result<(), Error> {
-        self.conn.execute(
+        let tx = self.conn.transaction()?;
+        tx.execute(
             "UPDATE sessions SET end_time = ?1, keystroke_count = ?2, avg_wpm = ?3, peak_wpm = ?4 WHERE id = ?5",
             params![end_time.to_rfc3339(), keystroke_count, avg_wpm, peak_wpm, session_id],
         )?;
+        write_session_devices(&tx, session_id, device_counts)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn start_pause(&mut self, start_time: DateTime<Utc>, reason: &str) -> Result<i64, Error> {
+        self.conn.execute(
+            "INSERT INTO pauses (start_time, reason) VALUES (?1, ?2)",
+            params![start_time.to_rfc3339(), reason],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn end_pause(&mut self, pause_id: i64, end_time: DateTime<Utc>) -> Result<(), Error> {
+        self.conn.execute(
+            "UPDATE pauses SET end_time = ?1 WHERE id = ?2",
+            params![end_time.to_rfc3339(), pause_id],
+        )?;
         Ok(())
     }
 
@@ -273,7 +770,7 @@ impl Storage {
             let mut stmt = tx.prepare_cached(
                 "INSERT INTO shortcut_counts (combo, date, count)
                  VALUES (?1, ?2, ?3)
-                 ON CONFLICT (combo, date)
+                 ON CONFLICT (combo, date, device, machine)
                  DO UPDATE SET count = count + excluded.count",
             )?;
 
@@ -311,49 +808,70 @@ impl Storage {
         Ok(())
     }
 
-    pub fn flush_all(
-        &mut self,
-        counts: &HashMap<KeyCode, u64>,
-        shortcuts: &HashMap<String, u64>,
-        wpm_samples: &[WpmSample],
-        date: &str,
-    ) -> Result<(), Error> {
+    /// Writes a batch to the day `date` (YYYY-MM-DD) and the hour starting
+    /// at `hour` (see [`hour_key`]).
+    pub fn flush_all(&mut self, batch: &FlushBatch, date: &str, hour: &str) -> Result<(), Error> {
+        let counts = &batch.key_counts;
+        let shortcuts = &batch.shortcut_counts;
+        let wpm_samples = &batch.wpm_samples;
+
         // Single transaction for all flush operations to ensure atomicity
         let tx = self.conn.transaction()?;
 
         // Flush key counts
         if !counts.is_empty() {
             let mut stmt = tx.prepare_cached(
-                "INSERT INTO key_counts (key_code, date, count)
-                 VALUES (?1, ?2, ?3)
-                 ON CONFLICT (key_code, date)
+                "INSERT INTO key_counts (key_code, date, device, count)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (key_code, date, device, machine)
                  DO UPDATE SET count = count + excluded.count",
             )?;
 
-            for (key_code, &count) in counts {
-                stmt.execute(params![key_code.to_string(), date, count])?;
+            for ((device, key_code), &count) in counts {
+                stmt.execute(params![
+                    key_code.to_string(),
+                    date,
+                    device_column(device),
+                    count
+                ])?;
+            }
+
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO hourly_key_counts (key_code, hour, device, count)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (key_code, hour, device, machine)
+                 DO UPDATE SET count = count + excluded.count",
+            )?;
+
+            for ((device, key_code), &count) in counts {
+                stmt.execute(params![
+                    key_code.to_string(),
+                    hour,
+                    device_column(device),
+                    count
+                ])?;
             }
         }
 
         // Flush shortcuts
         if !shortcuts.is_empty() {
             let mut stmt = tx.prepare_cached(
-                "INSERT INTO shortcut_counts (combo, date, count)
-                 VALUES (?1, ?2, ?3)
-                 ON CONFLICT (combo, date)
+                "INSERT INTO shortcut_counts (combo, date, device, count)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (combo, date, device, machine)
                  DO UPDATE SET count = count + excluded.count",
             )?;
 
-            for (combo, &count) in shortcuts {
-                stmt.execute(params![combo, date, count])?;
+            for ((device, combo), &count) in shortcuts {
+                stmt.execute(params![combo, date, device_column(device), count])?;
             }
         }
 
         // Flush WPM samples
         if !wpm_samples.is_empty() {
             let mut stmt = tx.prepare_cached(
-                "INSERT INTO wpm_samples (session_id, timestamp, wpm, keystrokes_in_window)
-                 VALUES (?1, ?2, ?3, ?4)",
+                "INSERT INTO wpm_samples (session_id, timestamp, wpm, keystrokes_in_window, device)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
             )?;
 
             for sample in wpm_samples {
@@ -362,6 +880,87 @@ impl Storage {
                     sample.timestamp.to_rfc3339(),
                     sample.wpm,
                     sample.keystrokes_in_window,
+                    device_column(&sample.device),
+                ])?;
+            }
+        }
+
+        // Flush timing histograms
+        if !batch.dwell_counts.is_empty() {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO dwell_times (key_code, date, device, bucket_ms, count)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (key_code, date, device, bucket_ms, machine)
+                 DO UPDATE SET count = count + excluded.count",
+            )?;
+
+            for ((device, (key_code, bucket)), &count) in &batch.dwell_counts {
+                stmt.execute(params![
+                    key_code.to_string(),
+                    date,
+                    device_column(device),
+                    bucket,
+                    count
+                ])?;
+            }
+        }
+
+        if !batch.flight_counts.is_empty() {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO flight_times (from_key, to_key, date, device, bucket_ms, count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (from_key, to_key, date, device, bucket_ms, machine)
+                 DO UPDATE SET count = count + excluded.count",
+            )?;
+
+            for ((device, (from, to, bucket)), &count) in &batch.flight_counts {
+                stmt.execute(params![
+                    from.to_string(),
+                    to.to_string(),
+                    date,
+                    device_column(device),
+                    bucket,
+                    count
+                ])?;
+            }
+        }
+
+        // Flush n-grams
+        if !batch.bigram_counts.is_empty() {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO bigram_counts (first, second, date, device, count)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (first, second, date, device, machine)
+                 DO UPDATE SET count = count + excluded.count",
+            )?;
+
+            for ((device, (first, second)), &count) in &batch.bigram_counts {
+                stmt.execute(params![
+                    first.to_string(),
+                    second.to_string(),
+                    date,
+                    device_column(device),
+                    count
+                ])?;
+            }
+        }
+
+        if !batch.trigram_counts.is_empty() {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO trigram_counts (first, second, third, date, device, count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (first, second, third, date, device, machine)
+                 DO UPDATE SET count = count + excluded.count",
+            )?;
+
+            for ((device, (first, second, third)), &count) in &batch.trigram_counts {
+                stmt.execute(params![
+                    first.to_string(),
+                    second.to_string(),
+                    third.to_string(),
+                    date,
+                    device_column(device),
+                    count
                 ])?;
             }
         }
@@ -370,11 +969,153 @@ impl Storage {
         Ok(())
     }
 
+    /// Key presses stored for this machine on `date`, across devices.
+    pub fn key_counts_on(&self, date: &str) -> Result<HashMap<String, u64>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT key_code, SUM(count) FROM key_counts
+             WHERE date = ?1 AND machine = '' GROUP BY key_code",
+        )?;
+        let rows = stmt.query_map(params![date], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_, _>>().map_err(Into::into)
+    }
+
+    pub fn set_meta(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn meta(&self, key: &str) -> Result<Option<String>, Error> {
+        self.conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", params![key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// Notes that days from `since` on are counted in `zone`. Nothing is
+    /// written while the zone stays the same.
+    pub fn record_timezone(&mut self, zone: &str, since: &str) -> Result<(), Error> {
+        let current: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT name FROM timezones ORDER BY since DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if current.as_deref() != Some(zone) {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO timezones (since, name) VALUES (?1, ?2)",
+                params![since, zone],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Zones that days from `start` to `end` (inclusive) were counted in,
+    /// oldest first.
+    pub fn timezones_between(&self, start: &str, end: &str) -> Result<Vec<String>, Error> {
+        // a zone counts unless a later one took over by `start`
+        let mut stmt = self.conn.prepare(
+            "SELECT name FROM timezones t
+             WHERE since <= ?2
+               AND NOT EXISTS (SELECT 1 FROM timezones n WHERE n.since > t.since AND n.since <= ?1)
+             ORDER BY since",
+        )?;
+        let rows = stmt.query_map(params![start, end], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>().map_err(Into::into)
+    }
+
+    /// Gives this database a random id the first time it's opened, so
+    /// other machines importing it can tell it apart.
+    fn ensure_machine_id(&mut self) -> Result<(), Error> {
+        if self.meta("machine_id")?.is_none() {
+            let seed = (hostname(), Utc::now(), std::process::id());
+            let id = format!("{:016x}", RandomState::new().hash_one(seed));
+            self.set_meta("machine_id", &id)?;
+            self.set_meta("machine_name", &hostname())?;
+        }
+        Ok(())
+    }
+
+    /// This machine first, then every imported one by name.
+    pub fn machines(&self) -> Result<Vec<Machine>, Error> {
+        let mut machines = vec![Machine {
+            id: String::new(),
+            name: self.meta("machine_name")?.unwrap_or_else(hostname),
+            imported_at: None,
+        }];
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name, imported_at FROM machines ORDER BY name")?;
+        let rows = stmt.query_map([], |row| {
+            let imported_at: String = row.get(2)?;
+            Ok(Machine {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                imported_at: DateTime::parse_from_rfc3339(&imported_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .ok(),
+            })
+        })?;
+        for row in rows {
+            machines.push(row?);
+        }
+
+        Ok(machines)
+    }
+
     pub fn connection(&self) -> &Connection {
         &self.conn
     }
 }
 
+/// Best guess at what this machine is called.
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "this machine".to_string())
+}
+
+/// Hourly rows are keyed by the UTC instant the local hour started, in a
+/// fixed format so keys compare as strings.
+pub fn hour_key(start: DateTime<Utc>) -> String {
+    start.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Devices are stored as text, with an empty string for unknown devices.
+fn device_column(device: &Option<DeviceId>) -> &str {
+    device.as_deref().unwrap_or("")
+}
+
+fn write_session_devices(
+    tx: &rusqlite::Transaction,
+    session_id: i64,
+    device_counts: &HashMap<Option<DeviceId>, u64>,
+) -> Result<(), Error> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO session_devices (session_id, device, keystroke_count)
+         VALUES (?1, ?2, ?3)
+         ON CONFLICT (session_id, device)
+         DO UPDATE SET keystroke_count = excluded.keystroke_count",
+    )?;
+
+    for (device, &count) in device_counts {
+        stmt.execute(params![session_id, device_column(device), count])?;
+    }
+    Ok(())
+}
+
 fn evdev_code_to_string(code: u32) -> String {
     match code {
         30 => "a",
@@ -494,3 +1235,30 @@ fn evdev_code_to_string(code: u32) -> String {
     }
     .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timezones_are_kept_per_date_range() {
+        let mut storage = Storage::in_memory().unwrap();
+        storage
+            .record_timezone("Europe/Berlin", "2025-01-10")
+            .unwrap();
+        // restarting in the same zone changes nothing
+        storage
+            .record_timezone("Europe/Berlin", "2025-02-01")
+            .unwrap();
+        storage.record_timezone("Asia/Tokyo", "2025-03-01").unwrap();
+
+        let zones = |start, end| storage.timezones_between(start, end).unwrap();
+        assert_eq!(zones("2025-02-01", "2025-02-28"), ["Europe/Berlin"]);
+        assert_eq!(
+            zones("2025-02-20", "2025-03-05"),
+            ["Europe/Berlin", "Asia/Tokyo"]
+        );
+        assert_eq!(zones("2025-03-01", "2025-03-31"), ["Asia/Tokyo"]);
+        assert!(zones("2024-12-01", "2024-12-31").is_empty());
+    }
+}
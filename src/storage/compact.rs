@@ -0,0 +1,369 @@
+use super::{hour_key, Storage, COUNT_TABLES};
+use crate::config::Retention;
+use crate::error::Error;
+use crate::report::day_period;
+use crate::timezone::Zone;
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeDelta, Timelike, Utc};
+use rusqlite::{params, ErrorCode, Transaction, TransactionBehavior};
+use std::collections::HashMap;
+
+/// Width of a WPM rollup slot. Every UTC offset is a multiple of this, so
+/// local day boundaries never fall inside a slot.
+const SLOT_SECS: i64 = 15 * 60;
+
+/// Tables of per-day counts, which [`Cutoffs::daily`] merges into one row
+/// per month.
+const DAILY_TABLES: [&str; 6] = [
+    "key_counts",
+    "shortcut_counts",
+    "dwell_times",
+    "flight_times",
+    "bigram_counts",
+    "trigram_counts",
+];
+
+/// What to compact. Everything before a cutoff goes, None keeps it all.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cutoffs {
+    /// raw WPM samples before this are rolled up, always on a slot boundary
+    pub samples: Option<DateTime<Utc>>,
+    /// hourly detail from before this local day is rolled up
+    pub detail: Option<NaiveDate>,
+    /// per-day counts from before this are merged per month, always the
+    /// first of a month
+    pub daily: Option<NaiveDate>,
+}
+
+impl Cutoffs {
+    pub fn from_retention(retention: &Retention, zone: Zone, now: DateTime<Utc>) -> Self {
+        let samples = (retention.wpm_samples_days > 0).then(|| {
+            let cutoff = now - TimeDelta::days(retention.wpm_samples_days.into());
+            let excess = cutoff.timestamp().rem_euclid(SLOT_SECS);
+            DateTime::from_timestamp(cutoff.timestamp() - excess, 0).unwrap_or(cutoff)
+        });
+        let today = zone.naive_local(now).date();
+        let days_ago = |days: u32| {
+            (days > 0)
+                .then(|| today.checked_sub_days(Days::new(days.into())))
+                .flatten()
+        };
+        let detail = days_ago(retention.detail_days);
+        let daily = days_ago(retention.daily_days).and_then(|date| date.with_day(1));
+        Self {
+            samples,
+            detail,
+            daily,
+        }
+    }
+}
+
+/// What a compaction did.
+#[derive(Debug, Default)]
+pub struct Compaction {
+    pub samples_rolled_up: usize,
+    /// (table, rows folded into coarser ones)
+    pub rolled_up: Vec<(&'static str, usize)>,
+}
+
+impl Compaction {
+    pub fn is_empty(&self) -> bool {
+        self.samples_rolled_up == 0 && self.rolled_up.iter().all(|(_, rows)| *rows == 0)
+    }
+}
+
+impl Storage {
+    /// Rolls old WPM samples, hourly key counts and per-day counts up into
+    /// coarser rows that reports read the same way, in one transaction.
+    /// Running it again with the same cutoffs does nothing.
+    pub fn compact(&mut self, cutoffs: &Cutoffs, zone: Zone) -> Result<Compaction, Error> {
+        // take the write lock up front, so a busy daemon is waited for
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut compaction = Compaction::default();
+
+        if let Some(cutoff) = cutoffs.samples {
+            let cutoff = cutoff.to_rfc3339();
+            // MAX() makes the bare timestamp come from the fastest sample
+            tx.execute(
+                "INSERT INTO wpm_rollups
+                    (slot, session_id, device, machine, wpm_bin, samples, wpm_sum, wpm_max, max_at)
+                 SELECT slot, session_id, device, machine, wpm_bin,
+                        COUNT(*), SUM(wpm), MAX(wpm), timestamp
+                 FROM (
+                    SELECT strftime('%Y-%m-%dT%H:', timestamp)
+                            || printf('%02d', CAST(strftime('%M', timestamp) AS INTEGER) / 15 * 15)
+                            || ':00+00:00' AS slot,
+                           session_id, device, machine,
+                           CAST(wpm / 10 AS INTEGER) * 10 AS wpm_bin, wpm, timestamp
+                    FROM wpm_samples
+                    WHERE julianday(timestamp) < julianday(?1)
+                 )
+                 WHERE true
+                 GROUP BY slot, session_id, device, machine, wpm_bin
+                 ON CONFLICT (slot, session_id, device, machine, wpm_bin) DO UPDATE SET
+                    samples = samples + excluded.samples,
+                    wpm_sum = wpm_sum + excluded.wpm_sum,
+                    max_at = CASE WHEN excluded.wpm_max > wpm_max
+                                  THEN excluded.max_at ELSE max_at END,
+                    wpm_max = MAX(wpm_max, excluded.wpm_max)",
+                params![cutoff],
+            )?;
+            compaction.samples_rolled_up = tx.execute(
+                "DELETE FROM wpm_samples WHERE julianday(timestamp) < julianday(?1)",
+                params![cutoff],
+            )?;
+        }
+
+        if let Some(date) = cutoffs.detail {
+            let hour = hour_key(zone.start_of_day(date));
+            let rows = roll_up_hourly_keys(&tx, &hour, zone)?;
+            compaction.rolled_up.push(("hourly_key_counts", rows));
+        }
+
+        if let Some(date) = cutoffs.daily {
+            let date = date.format("%Y-%m-%d").to_string();
+            for table in DAILY_TABLES {
+                let rows = roll_up_days(&tx, table, &date)?;
+                compaction.rolled_up.push((table, rows));
+            }
+        }
+
+        tx.commit()?;
+        Ok(compaction)
+    }
+
+    /// Gives the space freed by a compaction back to the filesystem.
+    /// Returns false if another connection kept the database busy, in which
+    /// case the freed pages are simply reused by later writes.
+    pub fn vacuum(&self) -> Result<bool, Error> {
+        match self.conn.execute_batch("VACUUM") {
+            Ok(()) => Ok(true),
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Folds `hourly_key_counts` rows from before `hour` into `hourly_totals`,
+/// for the activity grid, and `period_key_counts`, for the parts of the day.
+/// A part of the day is keyed by its first hour with data, so reports in
+/// the same zone bucket it exactly as before.
+fn roll_up_hourly_keys(tx: &Transaction, hour: &str, zone: Zone) -> Result<usize, Error> {
+    tx.execute(
+        "INSERT INTO hourly_totals (hour, device, machine, count)
+         SELECT hour, device, machine, SUM(count) FROM hourly_key_counts
+         WHERE hour < ?1
+         GROUP BY hour, device, machine
+         ON CONFLICT (hour, device, machine) DO UPDATE SET count = count + excluded.count",
+        params![hour],
+    )?;
+
+    let hours: Vec<String> = {
+        let mut stmt = tx
+            .prepare("SELECT DISTINCT hour FROM hourly_key_counts WHERE hour < ?1 ORDER BY hour")?;
+        let rows = stmt.query_map(params![hour], |row| row.get(0))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    let mut firsts: HashMap<(NaiveDate, Option<usize>), String> = HashMap::new();
+    for key in hours {
+        let Ok(start) = DateTime::parse_from_rfc3339(&key) else {
+            continue;
+        };
+        let local = zone.naive_local(start.with_timezone(&Utc));
+        let period = day_period(local.hour());
+        let first = firsts
+            .entry((local.date(), period))
+            .or_insert_with(|| key.clone())
+            .clone();
+        tx.execute(
+            "INSERT INTO period_key_counts (key_code, hour, device, machine, count)
+             SELECT key_code, ?2, device, machine, count FROM hourly_key_counts
+             WHERE hour = ?1
+             ON CONFLICT (key_code, hour, device, machine) DO UPDATE SET
+                count = count + excluded.count",
+            params![key, first],
+        )?;
+    }
+
+    let rows = tx.execute(
+        "DELETE FROM hourly_key_counts WHERE hour < ?1",
+        params![hour],
+    )?;
+    Ok(rows)
+}
+
+/// Merges the rows of a per-day table from before `date` into the first day
+/// of their month, returning how many rows went.
+fn roll_up_days(tx: &Transaction, table: &str, date: &str) -> Result<usize, Error> {
+    let columns = COUNT_TABLES
+        .iter()
+        .find(|(name, _)| *name == table)
+        .map(|(_, columns)| *columns)
+        .unwrap_or_default();
+    let keys: Vec<&str> = columns
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| *name != "date")
+        .collect();
+    let keys = keys.join(", ");
+
+    tx.execute(
+        &format!(
+            "INSERT INTO {table} ({keys}, date, device, machine, count)
+             SELECT {keys}, substr(date, 1, 8) || '01', device, machine, SUM(count)
+             FROM {table}
+             WHERE date < ?1 AND substr(date, 9, 2) != '01'
+             GROUP BY {keys}, substr(date, 1, 8), device, machine
+             ON CONFLICT ({keys}, date, device, machine) DO UPDATE SET
+                count = count + excluded.count"
+        ),
+        params![date],
+    )?;
+    let rows = tx.execute(
+        &format!("DELETE FROM {table} WHERE date < ?1 AND substr(date, 9, 2) != '01'"),
+        params![date],
+    )?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{build_report, RangeSpec, ReportOptions};
+
+    #[test]
+    fn rolled_up_samples_report_the_same() {
+        let mut storage = Storage::in_memory().unwrap();
+        storage
+            .connection()
+            .execute_batch(
+                "INSERT INTO sessions (id, start_time, keystroke_count, avg_wpm, peak_wpm)
+                    VALUES (1, '2025-02-03T09:00:00+00:00', 300, 50.0, 125.0);
+                 INSERT INTO session_devices (session_id, device, keystroke_count)
+                    VALUES (1, 'kb', 300);
+                 INSERT INTO key_counts (key_code, date, device, count)
+                    VALUES ('a', '2025-02-03', 'kb', 300);
+                 INSERT INTO wpm_samples (session_id, timestamp, wpm, keystrokes_in_window, device)
+                    VALUES (1, '2025-02-03T09:00:10+00:00', 35.5, 20, 'kb'),
+                           (1, '2025-02-03T09:07:10+00:00', 38.0, 20, 'kb'),
+                           (1, '2025-02-03T09:14:50+00:00', 125.0, 50, 'kb'),
+                           (1, '2025-02-03T09:15:00+00:00', 64.0, 30, 'kb'),
+                           (1, '2025-02-03T10:00:00+00:00', 91.0, 40, 'kb');
+                 INSERT INTO shortcut_counts (combo, date, device, count)
+                    VALUES ('ctrl+c', '2025-02-03', 'kb', 12);
+                 INSERT INTO hourly_key_counts (key_code, hour, device, count)
+                    VALUES ('a', '2025-02-03T03:30:00Z', 'kb', 150),
+                           ('a', '2025-02-03T04:30:00Z', 'kb', 100),
+                           ('a', '2025-02-03T13:30:00Z', 'kb', 50),
+                           ('space', '2025-02-03T13:30:00Z', 'kb', 40);
+                 INSERT INTO dwell_times (key_code, date, bucket_ms, device, count)
+                    VALUES ('a', '2025-02-03', 80, 'kb', 300);
+                 INSERT INTO flight_times (from_key, to_key, date, bucket_ms, device, count)
+                    VALUES ('a', 'a', '2025-02-03', 120, 'kb', 299);
+                 INSERT INTO bigram_counts (first, second, date, device, count)
+                    VALUES ('a', 'a', '2025-02-03', 'kb', 299);
+                 INSERT INTO trigram_counts (first, second, third, date, device, count)
+                    VALUES ('a', 'a', 'a', '2025-02-03', 'kb', 298);",
+            )
+            .unwrap();
+
+        let zone = Zone::Named(chrono_tz::Asia::Kolkata);
+        let options = ReportOptions {
+            range: RangeSpec::Day(NaiveDate::from_ymd_opt(2025, 2, 3).unwrap()),
+            device: Some("kb".to_string()),
+            zone,
+            ..ReportOptions::default()
+        };
+        let before = build_report(storage.connection(), &options).unwrap();
+
+        let cutoffs = Cutoffs {
+            samples: Some("2025-02-03T09:30:00Z".parse().unwrap()),
+            detail: NaiveDate::from_ymd_opt(2025, 2, 10),
+            daily: None,
+        };
+        let compaction = storage.compact(&cutoffs, zone).unwrap();
+        assert_eq!(compaction.samples_rolled_up, 4);
+        assert_eq!(compaction.rolled_up, vec![("hourly_key_counts", 4)]);
+        assert!(storage.compact(&cutoffs, zone).unwrap().is_empty());
+
+        let after = build_report(storage.connection(), &options).unwrap();
+        assert_eq!(after.wpm_distribution, before.wpm_distribution);
+        // the fastest sample was rolled up and keeps its time
+        assert_eq!(after.peak_wpm, 125.0);
+        assert_eq!(after.peak_wpm_time, before.peak_wpm_time);
+        assert_eq!(after.sessions[0].avg_wpm, before.sessions[0].avg_wpm);
+        assert_eq!(after.devices[0].avg_wpm, before.devices[0].avg_wpm);
+
+        // and nothing else in the report changed either
+        let active = before.day_periods.iter().filter(|p| p.keystrokes > 0);
+        assert!(active.count() >= 2);
+        assert!(before.timing.median_dwell_ms.is_some());
+        assert_eq!(
+            serde_json::to_value(&after).unwrap(),
+            serde_json::to_value(&before).unwrap()
+        );
+    }
+
+    #[test]
+    fn days_merged_per_month_report_the_same_month() {
+        let mut storage = Storage::in_memory().unwrap();
+        storage
+            .connection()
+            .execute_batch(
+                "INSERT INTO key_counts (key_code, date, device, count)
+                    VALUES ('a', '2025-02-01', 'kb', 10),
+                           ('a', '2025-02-03', 'kb', 20),
+                           ('b', '2025-02-17', 'kb', 5),
+                           ('a', '2025-03-02', 'kb', 7);
+                 INSERT INTO shortcut_counts (combo, date, device, count)
+                    VALUES ('ctrl+c', '2025-02-04', 'kb', 3),
+                           ('ctrl+c', '2025-02-05', 'kb', 4);
+                 INSERT INTO dwell_times (key_code, date, bucket_ms, device, count)
+                    VALUES ('a', '2025-02-03', 80, 'kb', 20),
+                           ('a', '2025-02-04', 80, 'kb', 10);
+                 INSERT INTO bigram_counts (first, second, date, device, count)
+                    VALUES ('a', 'b', '2025-02-03', 'kb', 4),
+                           ('a', 'b', '2025-02-17', 'kb', 2);",
+            )
+            .unwrap();
+
+        let zone = Zone::Named(chrono_tz::UTC);
+        let month = |month| ReportOptions {
+            range: RangeSpec::Month(NaiveDate::from_ymd_opt(2025, month, 1).unwrap()),
+            zone,
+            ..ReportOptions::default()
+        };
+        let report = |storage: &Storage, month| {
+            let report = build_report(storage.connection(), &month).unwrap();
+            serde_json::to_value(&report).unwrap()
+        };
+        let (february, march) = (report(&storage, month(2)), report(&storage, month(3)));
+
+        // a cutoff in March only reaches whole months before it
+        let cutoffs = Cutoffs {
+            daily: NaiveDate::from_ymd_opt(2025, 3, 1),
+            ..Cutoffs::default()
+        };
+        let compaction = storage.compact(&cutoffs, zone).unwrap();
+        let rolled_up: usize = compaction.rolled_up.iter().map(|(_, rows)| rows).sum();
+        assert_eq!(rolled_up, 8);
+        assert!(storage.compact(&cutoffs, zone).unwrap().is_empty());
+
+        assert_eq!(report(&storage, month(2)), february);
+        assert_eq!(report(&storage, month(3)), march);
+        let days: i64 = storage
+            .connection()
+            .query_row(
+                "SELECT COUNT(DISTINCT date) FROM key_counts WHERE date < '2025-03-01'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(days, 1);
+    }
+}
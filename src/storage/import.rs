@@ -0,0 +1,377 @@
+use super::{Storage, COUNT_TABLES};
+use crate::error::Error;
+use chrono::Utc;
+use rusqlite::{params, Transaction};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What an import brought in for one machine.
+#[derive(Debug)]
+pub struct ImportedMachine {
+    pub name: String,
+    pub keystrokes: u64,
+    pub sessions: usize,
+}
+
+impl Storage {
+    /// Merges another machine's database into this one. Whatever an earlier
+    /// import brought in from that machine is replaced, so importing the
+    /// same file again never double counts. Machines the other database
+    /// had imported come along too, unless they were imported directly.
+    ///
+    /// `name` labels the machine in reports, and identifies databases too
+    /// old to have a machine id of their own.
+    pub fn import(
+        &mut self,
+        source: &Path,
+        name: Option<&str>,
+    ) -> Result<Vec<ImportedMachine>, Error> {
+        // migrations run on a copy so the original is left alone
+        let dir = private_dir().map_err(Error::Io)?;
+        let result = self.import_copy(source, &dir.join("import.db"), name);
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    fn import_copy(
+        &mut self,
+        source: &Path,
+        copy: &Path,
+        name: Option<&str>,
+    ) -> Result<Vec<ImportedMachine>, Error> {
+        fs::copy(source, copy).map_err(Error::Io)?;
+        // recent writes may still sit in the write-ahead log
+        let wal = with_suffix(source, "-wal");
+        if wal.exists() {
+            fs::copy(&wal, with_suffix(copy, "-wal")).map_err(Error::Io)?;
+        }
+
+        let (source_id, source_name) = {
+            let other = Storage::open_at(copy)?;
+            let id = match (other.meta("machine_id")?, name) {
+                (Some(id), _) => id,
+                (None, Some(name)) => format!("named:{}", name.to_lowercase()),
+                (None, None) => {
+                    return Err(Error::Import(format!(
+                        "{} has no machine id, name it with --machine",
+                        source.display()
+                    )))
+                }
+            };
+            let stem = source.file_stem().map(|s| s.to_string_lossy().into_owned());
+            let name = name
+                .map(str::to_string)
+                .or(other.meta("machine_name")?)
+                .or(stem)
+                .unwrap_or_else(|| id.clone());
+            (id, name)
+        };
+
+        let local_id = self.meta("machine_id")?.unwrap_or_default();
+        if source_id == local_id {
+            return Err(Error::Import(format!(
+                "{} is this machine's own database",
+                source.display()
+            )));
+        }
+
+        self.conn
+            .execute("ATTACH DATABASE ?1 AS src", params![copy.to_string_lossy()])?;
+        let result = self.conn.transaction().map_err(Error::from).and_then(|tx| {
+            let imported = merge_attached(&tx, &local_id, &source_id, &source_name)?;
+            tx.commit()?;
+            Ok(imported)
+        });
+        self.conn.execute("DETACH DATABASE src", [])?;
+        result
+    }
+}
+
+fn merge_attached(
+    tx: &Transaction,
+    local_id: &str,
+    source_id: &str,
+    source_name: &str,
+) -> Result<Vec<ImportedMachine>, Error> {
+    // (machine column in the source, id, name); the source's own rows have
+    // an empty machine
+    let mut machines = vec![(
+        String::new(),
+        source_id.to_string(),
+        source_name.to_string(),
+    )];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT s.id, s.name FROM src.machines s
+             LEFT JOIN main.machines m ON m.id = s.id
+             WHERE s.id != ?1 AND s.id != ?2 AND (m.id IS NULL OR m.source = ?2)",
+        )?;
+        let rows = stmt.query_map(params![local_id, source_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (id, name) = row?;
+            machines.push((id.clone(), id, name));
+        }
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut imported = Vec::new();
+    for (column, id, name) in machines {
+        // drop whatever an earlier import of this machine brought in
+        for (table, _) in COUNT_TABLES {
+            tx.execute(
+                &format!("DELETE FROM main.{table} WHERE machine = ?1"),
+                params![id],
+            )?;
+        }
+        tx.execute(
+            "DELETE FROM main.session_devices WHERE session_id IN
+                (SELECT id FROM main.sessions WHERE machine = ?1)",
+            params![id],
+        )?;
+        tx.execute(
+            "DELETE FROM main.wpm_samples WHERE machine = ?1",
+            params![id],
+        )?;
+        tx.execute(
+            "DELETE FROM main.wpm_rollups WHERE machine = ?1",
+            params![id],
+        )?;
+        tx.execute("DELETE FROM main.sessions WHERE machine = ?1", params![id])?;
+        tx.execute("DELETE FROM main.pauses WHERE machine = ?1", params![id])?;
+
+        for (table, columns) in COUNT_TABLES {
+            let names: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+            let names = names.join(", ");
+            tx.execute(
+                &format!(
+                    "INSERT INTO main.{table} ({names}, device, machine, count)
+                     SELECT {names}, device, ?1, count FROM src.{table} WHERE machine = ?2"
+                ),
+                params![id, column],
+            )?;
+        }
+
+        // session ids are per database, so imported sessions get new ones
+        let mut session_ids = HashMap::new();
+        {
+            let mut read = tx.prepare(
+                "SELECT id, start_time, end_time, keystroke_count, avg_wpm, peak_wpm
+                 FROM src.sessions WHERE machine = ?1",
+            )?;
+            let mut write = tx.prepare(
+                "INSERT INTO main.sessions
+                    (start_time, end_time, keystroke_count, avg_wpm, peak_wpm, machine)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            let mut rows = read.query(params![column])?;
+            while let Some(row) = rows.next()? {
+                write.execute(params![
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, Option<f64>>(4)?,
+                    row.get::<_, Option<f64>>(5)?,
+                    id
+                ])?;
+                session_ids.insert(row.get::<_, i64>(0)?, tx.last_insert_rowid());
+            }
+        }
+        {
+            let mut read = tx.prepare(
+                "SELECT session_id, timestamp, wpm, keystrokes_in_window, device
+                 FROM src.wpm_samples WHERE machine = ?1",
+            )?;
+            let mut write = tx.prepare(
+                "INSERT INTO main.wpm_samples
+                    (session_id, timestamp, wpm, keystrokes_in_window, device, machine)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            let mut rows = read.query(params![column])?;
+            while let Some(row) = rows.next()? {
+                let Some(session_id) = session_ids.get(&row.get::<_, i64>(0)?) else {
+                    continue;
+                };
+                write.execute(params![
+                    session_id,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, String>(4)?,
+                    id
+                ])?;
+            }
+        }
+        {
+            let mut read = tx.prepare(
+                "SELECT session_id, slot, device, wpm_bin, samples, wpm_sum, wpm_max, max_at
+                 FROM src.wpm_rollups WHERE machine = ?1",
+            )?;
+            let mut write = tx.prepare(
+                "INSERT INTO main.wpm_rollups
+                    (session_id, slot, device, wpm_bin, samples, wpm_sum, wpm_max, max_at, machine)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            let mut rows = read.query(params![column])?;
+            while let Some(row) = rows.next()? {
+                let Some(session_id) = session_ids.get(&row.get::<_, i64>(0)?) else {
+                    continue;
+                };
+                write.execute(params![
+                    session_id,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, f64>(5)?,
+                    row.get::<_, f64>(6)?,
+                    row.get::<_, String>(7)?,
+                    id
+                ])?;
+            }
+        }
+        {
+            let mut read = tx.prepare(
+                "SELECT sd.session_id, sd.device, sd.keystroke_count
+                 FROM src.session_devices sd
+                 JOIN src.sessions s ON s.id = sd.session_id
+                 WHERE s.machine = ?1",
+            )?;
+            let mut write = tx.prepare(
+                "INSERT INTO main.session_devices (session_id, device, keystroke_count)
+                 VALUES (?1, ?2, ?3)",
+            )?;
+            let mut rows = read.query(params![column])?;
+            while let Some(row) = rows.next()? {
+                if let Some(session_id) = session_ids.get(&row.get::<_, i64>(0)?) {
+                    write.execute(params![
+                        session_id,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?
+                    ])?;
+                }
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO main.pauses (start_time, end_time, reason, machine)
+             SELECT start_time, end_time, reason, ?1 FROM src.pauses WHERE machine = ?2",
+            params![id, column],
+        )?;
+
+        tx.execute(
+            "INSERT INTO main.machines (id, name, imported_at, source) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                imported_at = excluded.imported_at,
+                source = excluded.source",
+            params![id, name, now, source_id],
+        )?;
+
+        let keystrokes: u64 = tx.query_row(
+            "SELECT COALESCE(SUM(count), 0) FROM main.key_counts WHERE machine = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+        imported.push(ImportedMachine {
+            name,
+            keystrokes,
+            sessions: session_ids.len(),
+        });
+    }
+
+    Ok(imported)
+}
+
+/// Creates a fresh directory in the temp dir that only this user can enter.
+/// Creating it fails if anything is already there, so another user can't
+/// plant a file or symlink where the copy will go.
+fn private_dir() -> std::io::Result<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    let mut attempt = 0;
+    loop {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let dir =
+            std::env::temp_dir().join(format!("keyheat-import-{}-{nanos:08x}", std::process::id()));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempt < 16 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reimporting_replaces_rather_than_adds() {
+        let dir = std::env::temp_dir().join(format!("keyheat-import-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let laptop = dir.join("laptop.db");
+
+        let mut other = Storage::open_at(&laptop).unwrap();
+        other.set_meta("machine_id", "laptop-id").unwrap();
+        other.set_meta("machine_name", "laptop").unwrap();
+        other
+            .connection()
+            .execute_batch(
+                "INSERT INTO key_counts (key_code, date, device, count) VALUES ('a', '2025-02-03', '', 5);
+                 INSERT INTO sessions (id, start_time, keystroke_count) VALUES (40, '2025-02-03T09:00:00+00:00', 5);
+                 INSERT INTO wpm_samples (session_id, timestamp, wpm, keystrokes_in_window)
+                    VALUES (40, '2025-02-03T09:00:10+00:00', 60.0, 5);
+                 INSERT INTO session_devices (session_id, device, keystroke_count) VALUES (40, '', 5);",
+            )
+            .unwrap();
+        drop(other);
+
+        let mut local = Storage::in_memory().unwrap();
+        local.set_meta("machine_id", "desktop-id").unwrap();
+        local
+            .connection()
+            .execute_batch(
+                "INSERT INTO sessions (id, start_time, keystroke_count) VALUES (40, '2025-02-03T10:00:00+00:00', 3);",
+            )
+            .unwrap();
+
+        for _ in 0..2 {
+            let imported = local.import(&laptop, None).unwrap();
+            assert_eq!(imported.len(), 1);
+            assert_eq!(imported[0].name, "laptop");
+            assert_eq!((imported[0].keystrokes, imported[0].sessions), (5, 1));
+        }
+
+        let conn = local.connection();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT SUM(count) FROM key_counts"), 5);
+        assert_eq!(count("SELECT COUNT(*) FROM sessions"), 2);
+        // the imported session got a fresh id and its sample followed it
+        let session: i64 = count("SELECT id FROM sessions WHERE machine = 'laptop-id'");
+        assert_ne!(session, 40);
+        assert_eq!(
+            count("SELECT session_id FROM wpm_samples WHERE machine = 'laptop-id'"),
+            session
+        );
+
+        let machines = local.machines().unwrap();
+        assert_eq!(machines[1].id, "laptop-id");
+
+        local.set_meta("machine_id", "laptop-id").unwrap();
+        assert!(local.import(&laptop, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use super::Storage;
+use crate::error::Error;
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+
+/// A session [`Storage::close_orphaned_sessions`] closed.
+#[derive(Debug)]
+pub struct RecoveredSession {
+    pub id: i64,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub avg_wpm: Option<f64>,
+    pub peak_wpm: Option<f64>,
+}
+
+impl Storage {
+    /// Closes this machine's sessions that a crash or power loss left open,
+    /// unless something was recorded for them after `idle_before`. Each one
+    /// ends at its last sign of life, the latest WPM sample or keystroke
+    /// update, and gets its WPM stats from the stored samples.
+    pub fn close_orphaned_sessions(
+        &mut self,
+        idle_before: DateTime<Utc>,
+    ) -> Result<Vec<RecoveredSession>, Error> {
+        let tx = self.conn.transaction()?;
+        let mut recovered = Vec::new();
+        {
+            let mut stmt = tx.prepare(
+                "SELECT s.id, s.start_time, s.updated_at,
+                        (SELECT timestamp FROM wpm_samples w WHERE w.session_id = s.id
+                         ORDER BY julianday(timestamp) DESC LIMIT 1),
+                        (SELECT SUM(wpm_sum) / SUM(samples) FROM wpm_stats w
+                         WHERE w.session_id = s.id),
+                        (SELECT MAX(wpm_max) FROM wpm_stats w WHERE w.session_id = s.id)
+                 FROM sessions s
+                 WHERE s.end_time IS NULL AND s.machine = ''
+                 ORDER BY s.id",
+            )?;
+            let mut update = tx.prepare(
+                "UPDATE sessions SET end_time = ?1, avg_wpm = ?2, peak_wpm = ?3 WHERE id = ?4",
+            )?;
+
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let Some(start_time) = parse_time(row.get(1)?) else {
+                    continue;
+                };
+                let end_time = [parse_time(row.get(2)?), parse_time(row.get(3)?)]
+                    .into_iter()
+                    .flatten()
+                    .fold(start_time, DateTime::max);
+                if end_time >= idle_before {
+                    continue;
+                }
+
+                let session = RecoveredSession {
+                    id: row.get(0)?,
+                    start_time,
+                    end_time,
+                    avg_wpm: row.get(4)?,
+                    peak_wpm: row.get(5)?,
+                };
+                update.execute(params![
+                    session.end_time.to_rfc3339(),
+                    session.avg_wpm,
+                    session.peak_wpm,
+                    session.id
+                ])?;
+                recovered.push(session);
+            }
+        }
+        tx.commit()?;
+
+        Ok(recovered)
+    }
+
+    /// Ends this machine's pauses a crash left open at `end_time`. Nothing
+    /// was recorded while the daemon was down either, so the gap stands.
+    pub fn close_open_pauses(&mut self, end_time: DateTime<Utc>) -> Result<usize, Error> {
+        let closed = self.conn.execute(
+            "UPDATE pauses SET end_time = ?1 WHERE end_time IS NULL AND machine = ''",
+            params![end_time.to_rfc3339()],
+        )?;
+        Ok(closed)
+    }
+}
+
+fn parse_time(text: Option<String>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&text?)
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orphaned_sessions_end_at_their_last_sign_of_life() {
+        let mut storage = Storage::in_memory().unwrap();
+        storage
+            .connection()
+            .execute_batch(
+                "INSERT INTO sessions (id, start_time, updated_at, keystroke_count) VALUES
+                    (1, '2025-02-03T09:00:00+00:00', '2025-02-03T09:10:00+00:00', 500),
+                    (2, '2025-02-03T11:00:00+00:00', NULL, 40),
+                    (3, '2025-02-03T12:00:00+00:00', '2025-02-03T12:30:00+00:00', 90);
+                 INSERT INTO sessions (id, start_time, end_time, machine, keystroke_count) VALUES
+                    (4, '2025-02-03T08:00:00+00:00', NULL, 'laptop-id', 10);
+                 INSERT INTO wpm_samples (session_id, timestamp, wpm, keystrokes_in_window) VALUES
+                    (1, '2025-02-03T09:05:00+00:00', 40.0, 20),
+                    (1, '2025-02-03T09:10:10+00:00', 60.0, 30),
+                    (2, '2025-02-03T11:00:30.5+00:00', 30.0, 10);",
+            )
+            .unwrap();
+
+        // session 3 was still being updated, so it may be live
+        let cutoff = "2025-02-03T12:00:00Z".parse().unwrap();
+        let recovered = storage.close_orphaned_sessions(cutoff).unwrap();
+        assert_eq!(recovered.len(), 2);
+
+        assert_eq!(
+            recovered[0].end_time.to_rfc3339(),
+            "2025-02-03T09:10:10+00:00"
+        );
+        assert_eq!(recovered[0].avg_wpm, Some(50.0));
+        assert_eq!(recovered[0].peak_wpm, Some(60.0));
+        assert_eq!(
+            recovered[1].end_time.to_rfc3339(),
+            "2025-02-03T11:00:30.500+00:00"
+        );
+
+        let open: i64 = storage
+            .connection()
+            .query_row(
+                "SELECT COUNT(*) FROM sessions WHERE end_time IS NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(open, 2);
+        assert!(storage.close_orphaned_sessions(cutoff).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::config::Config;
+use crate::error::Error;
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike, Utc,
+};
+use chrono_tz::Tz;
+
+/// The timezone calendar days and hours are counted in. Timestamps are
+/// stored in UTC and converted through this, so DST shifts land on the
+/// right local hour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Zone {
+    /// whatever the system is set to
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+impl Zone {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        name.trim()
+            .parse::<Tz>()
+            .map(Zone::Named)
+            .map_err(|_| Error::Timezone(name.to_string()))
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        match &config.timezone {
+            Some(name) => Self::parse(name),
+            None => Ok(Zone::Local),
+        }
+    }
+
+    /// The configured zone, falling back to the system one (with a warning,
+    /// once per process) if the config names a zone that doesn't exist.
+    pub fn configured() -> Self {
+        static WARNED: std::sync::Once = std::sync::Once::new();
+        Self::from_config(&Config::load()).unwrap_or_else(|e| {
+            WARNED.call_once(|| eprintln!("warning: {e}, using the system timezone"));
+            Zone::Local
+        })
+    }
+
+    /// IANA name where one is known, e.g. "Europe/Berlin".
+    pub fn name(self) -> String {
+        match self {
+            Zone::Named(tz) => tz.name().to_string(),
+            Zone::Local => iana_time_zone::get_timezone().unwrap_or_else(|_| "local".to_string()),
+        }
+    }
+
+    pub fn today(self) -> NaiveDate {
+        self.to_local(Utc::now()).date_naive()
+    }
+
+    /// The instant in this zone, keeping its UTC offset.
+    pub fn to_local(self, utc: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Zone::Local => utc.with_timezone(&Local).fixed_offset(),
+            Zone::Named(tz) => utc.with_timezone(&tz).fixed_offset(),
+        }
+    }
+
+    pub fn naive_local(self, utc: DateTime<Utc>) -> NaiveDateTime {
+        self.to_local(utc).naive_local()
+    }
+
+    /// First instant of a local day. Where DST skips midnight the day
+    /// starts at the first local time that exists.
+    pub fn start_of_day(self, date: NaiveDate) -> DateTime<Utc> {
+        match self {
+            Zone::Local => first_instant(&Local, date),
+            Zone::Named(tz) => first_instant(&tz, date),
+        }
+    }
+
+    /// Start of the local hour holding `utc`. Zones with half-hour offsets
+    /// still get whole local hours.
+    pub fn hour_start(self, utc: DateTime<Utc>) -> DateTime<Utc> {
+        let local = self.to_local(utc);
+        let into_hour = Duration::seconds(i64::from(local.minute() * 60 + local.second()))
+            + Duration::nanoseconds(i64::from(local.nanosecond()));
+        utc - into_hour
+    }
+
+    /// Half-open UTC bounds covering local days `start..=end`.
+    pub fn day_bounds(self, start: NaiveDate, end: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let after = end.succ_opt().unwrap_or(end);
+        (self.start_of_day(start), self.start_of_day(after))
+    }
+}
+
+fn first_instant<Z: TimeZone>(zone: &Z, date: NaiveDate) -> DateTime<Utc> {
+    let mut local = date.and_time(NaiveTime::MIN);
+    // DST gaps are at most a couple of hours
+    for _ in 0..16 {
+        if let Some(instant) = zone.from_local_datetime(&local).earliest() {
+            return instant.with_timezone(&Utc);
+        }
+        local += Duration::minutes(15);
+    }
+    Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_follow_dst_in_named_zones() {
+        let berlin = Zone::parse("Europe/Berlin").unwrap();
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        // the spring-forward day is 23 hours long, the fall-back day 25
+        let (start, end) = berlin.day_bounds(date("2026-03-29"), date("2026-03-29"));
+        assert_eq!(start.to_rfc3339(), "2026-03-28T23:00:00+00:00");
+        assert_eq!((end - start).num_hours(), 23);
+        let (start, end) = berlin.day_bounds(date("2026-10-25"), date("2026-10-25"));
+        assert_eq!((end - start).num_hours(), 25);
+
+        // 23:30 UTC is already the next morning in summer
+        let late = Utc.with_ymd_and_hms(2026, 7, 1, 23, 30, 0).unwrap();
+        let local = berlin.naive_local(late);
+        assert_eq!((local.date(), local.hour()), (date("2026-07-02"), 1));
+
+        // Havana skips midnight itself when DST starts
+        let havana = Zone::parse("America/Havana").unwrap();
+        let start = havana.start_of_day(date("2026-03-08"));
+        assert_eq!(havana.naive_local(start).hour(), 1);
+
+        // Kolkata is +05:30, so its hours start at half past in UTC
+        let kolkata = Zone::parse("Asia/Kolkata").unwrap();
+        let start = kolkata.hour_start(Utc.with_ymd_and_hms(2026, 7, 1, 4, 10, 0).unwrap());
+        assert_eq!(start.to_rfc3339(), "2026-07-01T03:30:00+00:00");
+
+        assert!(Zone::parse("Mars/Olympus_Mons").is_err());
+    }
+}